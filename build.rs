@@ -1,6 +1,5 @@
 fn main() {
-    let proj_dir = env!("CARGO_MANIFEST_DIR");
-    let db_url = format!("sqlite://{}/budge.db", proj_dir);
-    println!("cargo:rustc-env=DATABASE_URL={}", db_url);
+    // Queries are checked against the cached metadata in .sqlx, so building never needs a
+    // database. Run `cargo sqlx prepare` against a migrated database after changing a query
     println!("cargo:rustc-env=SQLX_OFFLINE=true");
 }
@@ -0,0 +1,31 @@
+-- The part of the SQLite schema that the storage backend needs. Dates stay text in the
+-- same `YYYY-MM-DD HH:MM:SS` form so that both backends sort and filter them alike
+CREATE TABLE IF NOT EXISTS budget (
+		id         BIGSERIAL        PRIMARY KEY,
+		amount     DOUBLE PRECISION NOT NULL,
+		month      TEXT             NOT NULL UNIQUE,
+		deleted_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS payees (
+		id   BIGSERIAL PRIMARY KEY,
+		name TEXT      NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS payees_name ON payees (lower(name));
+
+CREATE TABLE IF NOT EXISTS payments (
+		id         BIGSERIAL        PRIMARY KEY,
+		amount     DOUBLE PRECISION NOT NULL,
+		kind       TEXT             NOT NULL,
+		budget_id  BIGINT           NOT NULL
+				REFERENCES budget (id)
+				ON DELETE CASCADE,
+		day_of     TEXT             NOT NULL
+				DEFAULT (to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')),
+		payee_id   BIGINT
+				REFERENCES payees (id)
+				ON DELETE SET NULL,
+		memo       TEXT,
+		deleted_at TEXT
+);
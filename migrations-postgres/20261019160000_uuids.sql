@@ -0,0 +1,7 @@
+-- Uuids like the SQLite ones, which stay the same when records move between databases
+ALTER TABLE budget ADD COLUMN IF NOT EXISTS uuid TEXT NOT NULL DEFAULT gen_random_uuid()::text;
+ALTER TABLE payees ADD COLUMN IF NOT EXISTS uuid TEXT NOT NULL DEFAULT gen_random_uuid()::text;
+ALTER TABLE payments ADD COLUMN IF NOT EXISTS uuid TEXT NOT NULL DEFAULT gen_random_uuid()::text;
+CREATE UNIQUE INDEX IF NOT EXISTS budget_uuid ON budget (uuid);
+CREATE UNIQUE INDEX IF NOT EXISTS payees_uuid ON payees (uuid);
+CREATE UNIQUE INDEX IF NOT EXISTS payments_uuid ON payments (uuid);
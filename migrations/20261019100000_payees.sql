@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS payees (
+		id   INTEGER PRIMARY KEY,
+		name TEXT    NOT NULL UNIQUE COLLATE NOCASE
+);
+
+ALTER TABLE payments ADD COLUMN payee_id INTEGER
+		REFERENCES payees (id)
+		ON DELETE SET NULL;
@@ -0,0 +1,14 @@
+ALTER TABLE payments ADD COLUMN memo TEXT;
+
+CREATE TABLE IF NOT EXISTS attachments (
+		id         INTEGER PRIMARY KEY,
+		payment_id INTEGER NOT NULL,
+		name       TEXT    NOT NULL,
+		hash       TEXT    NOT NULL,
+		added_at   TEXT    NOT NULL
+				DEFAULT (datetime('now')),
+		CONSTRAINT to_payment
+				FOREIGN KEY (payment_id)
+				REFERENCES payments (id)
+				ON DELETE CASCADE
+);
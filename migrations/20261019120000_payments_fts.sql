@@ -0,0 +1,32 @@
+CREATE VIRTUAL TABLE IF NOT EXISTS payments_fts USING fts5(kind, memo, payee);
+
+INSERT INTO payments_fts (rowid, kind, memo, payee)
+		SELECT payments.id, payments.kind, payments.memo, payees.name
+		FROM payments
+		LEFT JOIN payees ON payees.id = payments.payee_id;
+
+CREATE TRIGGER IF NOT EXISTS payments_fts_insert AFTER INSERT ON payments
+BEGIN
+		INSERT INTO payments_fts (rowid, kind, memo, payee)
+				VALUES (new.id, new.kind, new.memo,
+						(SELECT name FROM payees WHERE id = new.payee_id));
+END;
+
+CREATE TRIGGER IF NOT EXISTS payments_fts_update AFTER UPDATE ON payments
+BEGIN
+		DELETE FROM payments_fts WHERE rowid = old.id;
+		INSERT INTO payments_fts (rowid, kind, memo, payee)
+				VALUES (new.id, new.kind, new.memo,
+						(SELECT name FROM payees WHERE id = new.payee_id));
+END;
+
+CREATE TRIGGER IF NOT EXISTS payments_fts_delete AFTER DELETE ON payments
+BEGIN
+		DELETE FROM payments_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS payments_fts_payee_rename AFTER UPDATE OF name ON payees
+BEGIN
+		UPDATE payments_fts SET payee = new.name
+				WHERE rowid IN (SELECT id FROM payments WHERE payee_id = new.id);
+END;
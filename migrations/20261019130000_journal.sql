@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS journal (
+		id               INTEGER PRIMARY KEY,
+		action           TEXT    NOT NULL
+				CHECK (action IN ('add', 'edit', 'delete')),
+		payment_id       INTEGER NOT NULL,
+		before_amount    REAL,
+		before_kind      TEXT,
+		before_budget_id INTEGER,
+		before_day_of    TEXT,
+		before_payee_id  INTEGER,
+		before_memo      TEXT,
+		after_amount     REAL,
+		after_kind       TEXT,
+		after_budget_id  INTEGER,
+		after_day_of     TEXT,
+		after_payee_id   INTEGER,
+		after_memo       TEXT,
+		undone           INTEGER NOT NULL DEFAULT 0,
+		created_at       TEXT    NOT NULL
+				DEFAULT (datetime('now'))
+);
@@ -0,0 +1,3 @@
+ALTER TABLE budget ADD COLUMN deleted_at TEXT;
+
+ALTER TABLE payments ADD COLUMN deleted_at TEXT;
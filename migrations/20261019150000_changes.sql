@@ -0,0 +1,188 @@
+-- Which database this is, so that every change says where it was made. `applying` is only
+-- set while `budge sync` writes changes that were made elsewhere, which aren't logged again
+CREATE TABLE IF NOT EXISTS sync_device (
+		id       TEXT    NOT NULL,
+		applying INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO sync_device (id) VALUES (lower(hex(randomblob(16))));
+
+ALTER TABLE budget ADD COLUMN uuid TEXT;
+ALTER TABLE payments ADD COLUMN uuid TEXT;
+UPDATE budget SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+		|| substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', 1 + (abs(random()) % 4), 1)
+		|| substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)));
+UPDATE payments SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+		|| substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', 1 + (abs(random()) % 4), 1)
+		|| substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)));
+CREATE UNIQUE INDEX IF NOT EXISTS budget_uuid ON budget (uuid);
+CREATE UNIQUE INDEX IF NOT EXISTS payments_uuid ON payments (uuid);
+
+-- Every state a budget or payment went through. Budgets are keyed by month, so that the ones
+-- two devices made for the same month become one, and payments by their uuid. `at` is in
+-- milliseconds and always later than every change this database has seen, whatever its clock
+CREATE TABLE IF NOT EXISTS changes (
+		id     TEXT    PRIMARY KEY,
+		device TEXT    NOT NULL,
+		at     INTEGER NOT NULL,
+		record TEXT    NOT NULL
+				CHECK (record IN ('budget', 'payment')),
+		key    TEXT    NOT NULL,
+		op     TEXT    NOT NULL
+				CHECK (op IN ('put', 'delete')),
+		data   TEXT
+);
+CREATE INDEX IF NOT EXISTS changes_key ON changes (record, key, at);
+CREATE INDEX IF NOT EXISTS changes_at ON changes (at);
+
+INSERT INTO changes (id, device, at, record, key, op, data)
+		SELECT lower(hex(randomblob(16))), sync_device.id,
+				CAST(unixepoch('subsec') * 1000 AS INTEGER), 'budget', month, 'put',
+				json_object('uuid', uuid, 'amount', amount, 'month', month,
+						'deleted_at', deleted_at)
+		FROM budget, sync_device;
+
+INSERT INTO changes (id, device, at, record, key, op, data)
+		SELECT lower(hex(randomblob(16))), sync_device.id,
+				CAST(unixepoch('subsec') * 1000 AS INTEGER), 'payment', payments.uuid, 'put',
+				json_object('uuid', payments.uuid, 'amount', payments.amount,
+						'kind', payments.kind, 'month', budget.month, 'day_of', payments.day_of,
+						'payee', payees.name, 'memo', payments.memo,
+						'deleted_at', payments.deleted_at)
+		FROM payments
+		JOIN budget ON budget.id = payments.budget_id
+		LEFT JOIN payees ON payees.id = payments.payee_id, sync_device;
+
+CREATE TRIGGER IF NOT EXISTS budget_changes_insert AFTER INSERT ON budget
+BEGIN
+		UPDATE budget SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2)))
+				|| '-4' || substr(lower(hex(randomblob(2))), 2) || '-'
+				|| substr('89ab', 1 + (abs(random()) % 4), 1)
+				|| substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)))
+				WHERE id = new.id AND uuid IS NULL;
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), sync_device.id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'budget', budget.month, 'put',
+						json_object('uuid', budget.uuid, 'amount', budget.amount,
+								'month', budget.month, 'deleted_at', budget.deleted_at)
+				FROM budget, sync_device
+				WHERE budget.id = new.id AND sync_device.applying = 0;
+END;
+
+CREATE TRIGGER IF NOT EXISTS budget_changes_update AFTER UPDATE OF amount, deleted_at ON budget
+		WHEN old.month = new.month AND NOT (SELECT applying FROM sync_device)
+BEGIN
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'budget', new.month, 'put',
+						json_object('uuid', new.uuid, 'amount', new.amount, 'month', new.month,
+								'deleted_at', new.deleted_at)
+				FROM sync_device;
+END;
+
+-- A budget that moves to another month leaves its old one, and takes its payments along
+CREATE TRIGGER IF NOT EXISTS budget_changes_move AFTER UPDATE OF month ON budget
+		WHEN old.month IS NOT new.month AND NOT (SELECT applying FROM sync_device)
+BEGIN
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'budget', old.month, 'delete', NULL
+				FROM sync_device;
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'budget', new.month, 'put',
+						json_object('uuid', new.uuid, 'amount', new.amount, 'month', new.month,
+								'deleted_at', new.deleted_at)
+				FROM sync_device;
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), sync_device.id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'payment', payments.uuid, 'put',
+						json_object('uuid', payments.uuid, 'amount', payments.amount,
+								'kind', payments.kind, 'month', new.month,
+								'day_of', payments.day_of, 'payee', payees.name,
+								'memo', payments.memo, 'deleted_at', payments.deleted_at)
+				FROM payments
+				LEFT JOIN payees ON payees.id = payments.payee_id, sync_device
+				WHERE payments.budget_id = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS budget_changes_delete AFTER DELETE ON budget
+		WHEN NOT (SELECT applying FROM sync_device)
+BEGIN
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'budget', old.month, 'delete', NULL
+				FROM sync_device;
+END;
+
+-- Filling in the uuid of a new payment would otherwise index it twice
+DROP TRIGGER IF EXISTS payments_fts_update;
+CREATE TRIGGER IF NOT EXISTS payments_fts_update AFTER UPDATE OF kind, memo, payee_id ON payments
+BEGIN
+		DELETE FROM payments_fts WHERE rowid = old.id;
+		INSERT INTO payments_fts (rowid, kind, memo, payee)
+				VALUES (new.id, new.kind, new.memo,
+						(SELECT name FROM payees WHERE id = new.payee_id));
+END;
+
+CREATE TRIGGER IF NOT EXISTS payments_changes_insert AFTER INSERT ON payments
+BEGIN
+		UPDATE payments SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2)))
+				|| '-4' || substr(lower(hex(randomblob(2))), 2) || '-'
+				|| substr('89ab', 1 + (abs(random()) % 4), 1)
+				|| substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)))
+				WHERE id = new.id AND uuid IS NULL;
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), sync_device.id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'payment', payments.uuid, 'put',
+						json_object('uuid', payments.uuid, 'amount', payments.amount,
+								'kind', payments.kind, 'month', budget.month,
+								'day_of', payments.day_of, 'payee', payees.name,
+								'memo', payments.memo, 'deleted_at', payments.deleted_at)
+				FROM payments
+				JOIN budget ON budget.id = payments.budget_id
+				LEFT JOIN payees ON payees.id = payments.payee_id, sync_device
+				WHERE payments.id = new.id AND sync_device.applying = 0;
+END;
+
+CREATE TRIGGER IF NOT EXISTS payments_changes_update
+		AFTER UPDATE OF amount, kind, budget_id, day_of, payee_id, memo, deleted_at ON payments
+		WHEN NOT (SELECT applying FROM sync_device)
+BEGIN
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), sync_device.id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'payment', new.uuid, 'put',
+						json_object('uuid', new.uuid, 'amount', new.amount, 'kind', new.kind,
+								'month', budget.month, 'day_of', new.day_of,
+								'payee', payees.name, 'memo', new.memo,
+								'deleted_at', new.deleted_at)
+				FROM budget
+				LEFT JOIN payees ON payees.id = new.payee_id, sync_device
+				WHERE budget.id = new.budget_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS payments_changes_delete AFTER DELETE ON payments
+		WHEN NOT (SELECT applying FROM sync_device)
+BEGIN
+		INSERT INTO changes (id, device, at, record, key, op, data)
+				SELECT lower(hex(randomblob(16))), id,
+						max(CAST(unixepoch('subsec') * 1000 AS INTEGER),
+								coalesce((SELECT max(at) FROM changes), 0) + 1),
+						'payment', old.uuid, 'delete', NULL
+				FROM sync_device;
+END;
@@ -0,0 +1,32 @@
+-- Payees and attachments get uuids as well, so that every record can be told apart across
+-- databases. The undo journal and the change log never leave the database they're in
+ALTER TABLE payees ADD COLUMN uuid TEXT;
+ALTER TABLE attachments ADD COLUMN uuid TEXT;
+UPDATE payees SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+		|| substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', 1 + (abs(random()) % 4), 1)
+		|| substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)));
+UPDATE attachments SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+		|| substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', 1 + (abs(random()) % 4), 1)
+		|| substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)));
+CREATE UNIQUE INDEX IF NOT EXISTS payees_uuid ON payees (uuid);
+CREATE UNIQUE INDEX IF NOT EXISTS attachments_uuid ON attachments (uuid);
+
+CREATE TRIGGER IF NOT EXISTS payees_uuid_insert AFTER INSERT ON payees
+		WHEN new.uuid IS NULL
+BEGIN
+		UPDATE payees SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2)))
+				|| '-4' || substr(lower(hex(randomblob(2))), 2) || '-'
+				|| substr('89ab', 1 + (abs(random()) % 4), 1)
+				|| substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)))
+				WHERE id = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS attachments_uuid_insert AFTER INSERT ON attachments
+		WHEN new.uuid IS NULL
+BEGIN
+		UPDATE attachments SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2)))
+				|| '-4' || substr(lower(hex(randomblob(2))), 2) || '-'
+				|| substr('89ab', 1 + (abs(random()) % 4), 1)
+				|| substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)))
+				WHERE id = new.id;
+END;
@@ -0,0 +1,8 @@
+-- The journal keeps each payment's uuid too, so that undoing the delete of a payment that has
+-- since been purged brings it back as the same record
+ALTER TABLE journal ADD COLUMN before_uuid TEXT;
+ALTER TABLE journal ADD COLUMN after_uuid TEXT;
+UPDATE journal SET before_uuid = (SELECT uuid FROM payments WHERE id = journal.payment_id)
+		WHERE before_amount IS NOT NULL;
+UPDATE journal SET after_uuid = (SELECT uuid FROM payments WHERE id = journal.payment_id)
+		WHERE after_amount IS NOT NULL;
@@ -1,32 +1,37 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{Local, NaiveDate};
 use itertools::multiunzip;
 use ratatui::{
-    Terminal, crossterm::event::{self, Event, KeyCode}, layout::Margin, macros::{horizontal, vertical}, prelude::Backend, widgets::{Row, ScrollbarOrientation, ScrollbarState, Table, TableState}
+    Terminal, crossterm::event::{self, Event, EventStream, KeyCode, KeyEvent}, layout::Margin, macros::{horizontal, vertical}, prelude::Backend, widgets::{Row, ScrollbarOrientation, ScrollbarState, Table, TableState}
 };
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style, Stylize},
-    widgets::{Block, Borders, Gauge, Paragraph, Scrollbar},
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Gauge, Paragraph, Scrollbar},
     Frame,
 };
 
-use sqlx::{query, query_as, Pool, Sqlite};
+use futures::StreamExt;
+use budge::attachment::{self, Attachment};
+use budge::filter::{Filters, SortKey};
+use budge::payee::{self, PayeeSummary};
+use budge::storage::Ref;
+use budge::trash::{self, Record, TrashItem};
+use budge::viewport::Viewport;
+use budge::{budget, db, doctor, journal, payment, search, Budget, Payment, Storage};
+use sqlx::{pool::PoolConnection, Pool, Sqlite};
+use tokio::time::MissedTickBehavior;
 use tui_input::{backend::crossterm::EventHandler, Input};
 
-#[allow(dead_code)]
-#[derive(Default, Debug)]
-pub struct Payment {
-    pub id: i64,
-    pub amount: f64,
-    pub budget_id: i64,
-    pub kind: String,
-    pub day_of: String,
-}
-#[derive(Debug, Clone, Default)]
-pub struct Budget {
-    pub id: i64,
-    pub amount: f64,
-    pub month: String,
-}
+use crate::config::Config;
+use crate::keymap::{Action, Key, Keymap, Resolved, Scope, EDITING_KEYS};
+use crate::theme::Theme;
+
+/// How often the UI checks whether the database changed underneath it
+const TICK: Duration = Duration::from_secs(1);
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum InputMode {
@@ -34,40 +39,184 @@ pub enum InputMode {
     Editing,
     Deleting,
     NewBudget,
+    Payees,
+    Details,
+    Memo,
+    Attaching,
+    Search,
+    Filtering,
+    Trash,
+}
+
+impl InputMode {
+    /// Shown in the status bar
+    pub fn name(self) -> &'static str {
+        match self {
+            InputMode::Normal => "NORMAL",
+            InputMode::Editing => "ADD PAYMENT",
+            InputMode::Deleting => "DELETE",
+            InputMode::NewBudget => "NEW BUDGET",
+            InputMode::Payees => "PAYEES",
+            InputMode::Details => "DETAILS",
+            InputMode::Memo => "MEMO",
+            InputMode::Attaching => "ATTACH",
+            InputMode::Search => "SEARCH",
+            InputMode::Filtering => "FILTER",
+            InputMode::Trash => "TRASH",
+        }
+    }
+
+    /// The keymap scope of the modes that aren't typing into an input
+    pub fn scope(self) -> Option<Scope> {
+        match self {
+            InputMode::Normal => Some(Scope::Normal),
+            InputMode::Details => Some(Scope::Details),
+            InputMode::Trash => Some(Scope::Trash),
+            InputMode::Payees => Some(Scope::Payees),
+            _ => None,
+        }
+    }
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InputLocation {
     Type,
     Amount,
+    Payee,
     Budget,
     Month,
+    MinAmount,
+    MaxAmount,
+    KindFilter,
+    From,
+    To,
+    Id,
+    Path,
+}
+
+/// Something typed into an input that can't be used, shown under that input instead of
+/// as an error toast
+#[derive(Debug)]
+pub struct Invalid {
+    pub location: InputLocation,
+    pub message: String,
+}
+
+impl Invalid {
+    fn at(location: InputLocation, message: impl Into<String>) -> Box<dyn std::error::Error> {
+        Box::new(Invalid {
+            location,
+            message: message.into(),
+        })
+    }
+}
+
+impl std::fmt::Display for Invalid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+impl InputLocation {
+    /// Position of a filter field inside `App::filter_input`
+    fn filter_index(self) -> usize {
+        match self {
+            InputLocation::MinAmount => 0,
+            InputLocation::MaxAmount => 1,
+            InputLocation::KindFilter => 2,
+            InputLocation::From => 3,
+            InputLocation::To => 4,
+            _ => unreachable!(),
+        }
+    }
 }
 
 pub struct App {
     pub pool: Pool<Sqlite>,
-    pub scroll: usize,
-    pub scroll_state: ScrollbarState,
+    pub config: Config,
+    pub keymap: Keymap,
+    pub theme: Theme,
+    /// Keys typed so far towards a multi-key binding
+    pub pending: Vec<Key>,
+    /// Selection and scroll position of the payments table
+    pub viewport: Viewport,
     pub current_budget_id: i64,
     pub payments: Vec<Payment>,
+    /// Sum of every payment in the budget, regardless of the active filters
+    pub total_payout: f64,
     pub budget: Option<Budget>,
-    pub payment_input: (Input, Input),
+    pub payment_input: (Input, Input, Input),
+    pub payees: Vec<String>,
+    pub payee_summaries: Vec<PayeeSummary>,
     pub deletion_id: Input,
+    pub attachments_dir: PathBuf,
+    pub attachments: Vec<Attachment>,
+    pub attachment_scroll: usize,
+    pub memo_input: Input,
+    pub attachment_path: Input,
+    pub search_input: Input,
+    pub matches: Vec<i64>,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub filters: Filters,
+    pub filter_input: [Input; 5],
+    pub trash: Vec<TrashItem>,
+    pub trash_scroll: usize,
+    /// Whether the `?` overlay is open
+    pub help: bool,
+    /// Shown in the status bar until the next key press
+    pub message: Option<String>,
+    /// Why the last submitted input was rejected, shown under that input
+    pub input_error: Option<(InputLocation, String)>,
+    /// Errors that aren't the user's fault, shown until the next key press
+    pub toasts: Vec<String>,
     pub mode: InputMode,
     pub location: InputLocation,
     pub new_budget: (Input, Input),
 }
 
 impl App {
-    pub fn new(pool: Pool<Sqlite>, id: i64) -> App {
+    pub fn new(
+        pool: Pool<Sqlite>,
+        id: i64,
+        attachments_dir: PathBuf,
+        config: Config,
+        keymap: Keymap,
+        theme: Theme,
+    ) -> App {
         App {
             pool,
-            scroll: usize::default(),
-            scroll_state: ScrollbarState::default(),
+            config,
+            keymap,
+            theme,
+            pending: Vec::new(),
+            viewport: Viewport::default(),
             current_budget_id: id,
             payments: Vec::new(),
+            total_payout: 0.0,
             budget: None,
-            payment_input: (Input::default(), Input::default()),
+            payment_input: (Input::default(), Input::default(), Input::default()),
+            payees: Vec::new(),
+            payee_summaries: Vec::new(),
             deletion_id: Input::default(),
+            attachments_dir,
+            attachments: Vec::new(),
+            attachment_scroll: usize::default(),
+            memo_input: Input::default(),
+            attachment_path: Input::default(),
+            search_input: Input::default(),
+            matches: Vec::new(),
+            sort: SortKey::default(),
+            descending: true,
+            filters: Filters::default(),
+            filter_input: Default::default(),
+            trash: Vec::new(),
+            trash_scroll: usize::default(),
+            help: false,
+            message: None,
+            input_error: None,
+            toasts: Vec::new(),
             mode: InputMode::Normal,
             location: InputLocation::Type,
             new_budget: (Input::default(), Input::default()),
@@ -75,376 +224,1119 @@ impl App {
     }
 
     pub async fn load(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        match query_as!(
-            Budget,
-            "SELECT * FROM budget WHERE id = ?",
-            self.current_budget_id
-        )
-        .fetch_one(&self.pool)
-        .await
-        {
-            Ok(budget) => self.budget = Some(budget),
-            Err(_) => self.budget = None,
-        }
-        match query_as!(
-            Payment,
-            "SELECT * FROM payments WHERE budget_id = ? ORDER BY day_of DESC",
-            self.current_budget_id
+        self.budget = budget::get(&self.pool, self.current_budget_id).await?;
+        self.total_payout = payment::total(&self.pool, self.current_budget_id).await?;
+        self.payments = payment::list(
+            &self.pool,
+            self.current_budget_id,
+            &self.filters,
+            self.sort,
+            self.descending,
         )
-        .fetch_all(&self.pool)
-        .await
-        {
-            Ok(payments) => self.payments = payments,
-            Err(_) => {}
-        }
+        .await?;
+        self.payees = payee::names(&self.pool).await?;
+        self.viewport.set_len(self.payments.len());
 
         Ok(())
     }
     pub async fn add_payment(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let ty = self.payment_input.0.value();
-        let amount = self.payment_input.1.value().parse::<f64>()?;
-        query!(
-            r#"INSERT INTO payments (amount, budget_id, kind) VALUES (?, ?, ?)"#,
-            amount,
-            self.current_budget_id,
-            ty
-        )
-        .execute(&self.pool)
-        .await?;
+        let ty = self.payment_input.0.value().trim();
+        if ty.is_empty() {
+            return Err(Invalid::at(InputLocation::Type, "what was it for?"));
+        }
+        let amount = parse_amount(self.payment_input.1.value(), InputLocation::Amount)?;
+        let payee = Some(self.payment_input.2.value());
+        let id = payment::add(&self.pool, self.current_budget_id, ty, amount, payee).await?;
 
-        self.payment_input = (Input::default(), Input::default());
+        self.payment_input = (Input::default(), Input::default(), Input::default());
+        self.message = Some(format!("Added payment {id}"));
 
         Ok(())
     }
     pub async fn add_budget(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let amount = self.new_budget.0.value().parse::<f64>()?;
-        let month = self.new_budget.1.value();
+        let amount = match self.new_budget.0.value().trim() {
+            "" => self.config.defaults.budget_amount,
+            amount => parse_amount(amount, InputLocation::Budget)?,
+        };
+        let month = doctor::normalize_month(self.new_budget.1.value())
+            .ok_or_else(|| Invalid::at(InputLocation::Month, "expected YYYY-MM"))?;
+        match budget::create(&self.pool, amount, &month).await {
+            Err(e @ (budge::Error::DuplicateMonth { .. } | budge::Error::TrashedMonth { .. })) => {
+                return Err(Invalid::at(InputLocation::Month, e.to_string()))
+            }
+            res => res?,
+        };
 
-        query!(
-            "INSERT INTO budget (amount, month) VALUES (?,?)",
-            amount,
-            month
-        )
-        .execute(&self.pool)
-        .await?;
+        self.message = Some(format!("Created the budget for {month}"));
+        Ok(())
+    }
+    pub fn selected_payment(&self) -> Option<&Payment> {
+        self.payments.get(self.viewport.selected())
+    }
+    pub async fn load_attachments(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(id) = self.selected_payment().map(|x| x.id) {
+            self.attachments = attachment::for_payment(&self.pool, id).await?;
+            self.attachment_scroll = 0;
+        }
+        Ok(())
+    }
+    pub async fn save_memo(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(id) = self.selected_payment().map(|x| x.id) else {
+            return Ok(());
+        };
+        let memo = match self.memo_input.value().trim() {
+            "" => None,
+            memo => Some(memo),
+        };
+        payment::set_memo(&self.pool, id, memo).await?;
 
+        self.memo_input = Input::default();
+        self.message = Some("Saved the memo".to_string());
         Ok(())
     }
-    pub async fn delete(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let id = self.deletion_id.value().parse::<i64>()?;
+    pub async fn add_attachment(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(id) = self.selected_payment().map(|x| x.id) else {
+            return Ok(());
+        };
+        let path = PathBuf::from(self.attachment_path.value().trim());
+        if !path.is_file() {
+            return Err(Invalid::at(InputLocation::Path, "no such file"));
+        }
+        attachment::attach(&self.pool, &self.attachments_dir, id, &path).await?;
 
-        query!("DELETE FROM payments WHERE id = ?", id)
-            .execute(&self.pool)
-            .await?;
+        self.attachment_path = Input::default();
+        self.message = Some(format!("Attached {}", path.to_string_lossy()));
+        Ok(())
+    }
+    pub async fn update_search(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.matches =
+            search::matching_ids(&self.pool, self.search_input.value(), self.current_budget_id)
+                .await?;
+        Ok(())
+    }
+    /// Moves the selection to the next (or previous) payment matching the search, wrapping around
+    pub fn jump_to_match(&mut self, forward: bool, include_current: bool) {
+        let len = self.payments.len();
+        let current = self.viewport.selected();
+        let skip = if include_current { 0 } else { 1 };
+        let found = (skip..len + skip)
+            .map(|step| match forward {
+                true => (current + step) % len,
+                false => (current + len * 2 - step) % len,
+            })
+            .find(|&i| self.matches.contains(&self.payments[i].id));
+        if let Some(i) = found {
+            self.viewport.select(i)
+        }
+    }
+    /// Reads the filter form into `self.filters`, leaving blank fields unfiltered
+    pub fn apply_filters(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let field = |location: InputLocation| match self.filter_input[location.filter_index()]
+            .value()
+            .trim()
+        {
+            "" => None,
+            value => Some(value.to_string()),
+        };
+        let date = |location: InputLocation| {
+            field(location)
+                .map(|x| match NaiveDate::parse_from_str(&x, "%Y-%m-%d") {
+                    Ok(_) => Ok(x),
+                    Err(_) => Err(Invalid::at(location, "expected YYYY-MM-DD")),
+                })
+                .transpose()
+        };
+        self.filters = Filters {
+            min_amount: field(InputLocation::MinAmount)
+                .map(|x| parse_amount(&x, InputLocation::MinAmount))
+                .transpose()?,
+            max_amount: field(InputLocation::MaxAmount)
+                .map(|x| parse_amount(&x, InputLocation::MaxAmount))
+                .transpose()?,
+            kind: field(InputLocation::KindFilter),
+            from: date(InputLocation::From)?,
+            to: date(InputLocation::To)?,
+            flow: self.filters.flow,
+        };
+        Ok(())
+    }
+    pub async fn load_trash(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.trash = trash::list(&self.pool).await?;
+        self.trash_scroll = self.trash_scroll.min(self.trash.len().saturating_sub(1));
+        Ok(())
+    }
+    pub async fn delete(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let reference = self
+            .deletion_id
+            .value()
+            .parse::<Ref>()
+            .map_err(|_| Invalid::at(InputLocation::Id, "expected a payment id or uuid"))?;
+        let not_found = || Invalid::at(InputLocation::Id, format!("no payment {reference}"));
+        let id = match self.pool.resolve(Record::Payment, &reference).await {
+            Err(budge::Error::UnknownUuid { .. }) => return Err(not_found()),
+            res => res?,
+        };
+        match payment::delete(&self.pool, id).await {
+            Err(budge::Error::NotFound { .. }) => return Err(not_found()),
+            res => res?,
+        }
 
         self.deletion_id = Input::default();
+        self.message = Some(format!("Moved payment {id} to the trash"));
+        Ok(())
+    }
+    /// Carries out a bound action in the current mode, returning whether the app should quit
+    pub async fn perform(&mut self, action: Action) -> Result<bool, Box<dyn std::error::Error>> {
+        match (self.mode, action) {
+            (InputMode::Normal, Action::Quit) => return Ok(true),
+            (_, Action::Close) => self.mode = InputMode::Normal,
+            (_, Action::Help) => self.help = true,
+
+            (InputMode::Normal, Action::Delete) => {
+                self.mode = InputMode::Deleting;
+                self.location = InputLocation::Id
+            }
+            (InputMode::Normal, Action::NewBudget) => {
+                self.mode = InputMode::NewBudget;
+                self.location = InputLocation::Budget
+            }
+            (InputMode::Normal, Action::ScrollDown) => self.viewport.down(1),
+            (InputMode::Normal, Action::ScrollUp) => self.viewport.up(1),
+            (InputMode::Normal, Action::PageDown) => self.viewport.page_down(),
+            (InputMode::Normal, Action::PageUp) => self.viewport.page_up(),
+            (InputMode::Normal, Action::ScrollTop) => self.viewport.top(),
+            (InputMode::Normal, Action::ScrollBottom) => self.viewport.bottom(),
+            (InputMode::Normal, Action::AddPayment) => {
+                self.mode = InputMode::Editing;
+                self.location = InputLocation::Type
+            }
+            (InputMode::Normal, Action::Details) if self.selected_payment().is_some() => {
+                self.load_attachments().await?;
+                self.mode = InputMode::Details
+            }
+            (InputMode::Normal, Action::Trash) => {
+                self.load_trash().await?;
+                self.mode = InputMode::Trash
+            }
+            (InputMode::Normal, Action::Search) => {
+                self.search_input = Input::default();
+                self.matches.clear();
+                self.mode = InputMode::Search
+            }
+            (InputMode::Normal, Action::Undo) => {
+                self.message = Some(match journal::undo(&self.pool).await? {
+                    Some(entry) => format!("Undid {}", entry.describe()),
+                    None => "Nothing to undo".to_string(),
+                });
+                self.load().await?
+            }
+            (InputMode::Normal, Action::Redo) => {
+                self.message = Some(match journal::redo(&self.pool).await? {
+                    Some(entry) => format!("Redid {}", entry.describe()),
+                    None => "Nothing to redo".to_string(),
+                });
+                self.load().await?
+            }
+            (InputMode::Normal, Action::CycleSort) => {
+                self.sort = self.sort.next();
+                self.load().await?
+            }
+            (InputMode::Normal, Action::ReverseSort) => {
+                self.descending = !self.descending;
+                self.load().await?
+            }
+            (InputMode::Normal, Action::CycleFlow) => {
+                self.filters.flow = self.filters.flow.next();
+                self.load().await?
+            }
+            (InputMode::Normal, Action::Filter) => {
+                self.mode = InputMode::Filtering;
+                self.location = InputLocation::MinAmount
+            }
+            (InputMode::Normal, Action::ThisWeek) => {
+                let (start, end) = self.config.week_of(Local::now().date_naive());
+                self.filters.from = Some(start.to_string());
+                self.filters.to = Some(end.to_string());
+                self.filter_input[InputLocation::From.filter_index()] = Input::new(start.to_string());
+                self.filter_input[InputLocation::To.filter_index()] = Input::new(end.to_string());
+                self.load().await?
+            }
+            (InputMode::Normal, Action::ClearFilters) => {
+                self.filters = Filters::default();
+                self.filter_input = Default::default();
+                self.load().await?
+            }
+            (InputMode::Normal, Action::NextMatch) => self.jump_to_match(true, false),
+            (InputMode::Normal, Action::PrevMatch) => self.jump_to_match(false, false),
+            (InputMode::Normal, Action::Payees) => {
+                self.payee_summaries = payee::summaries(&self.pool).await?;
+                self.mode = InputMode::Payees
+            }
+
+            (InputMode::Details, Action::EditMemo) => {
+                let memo = self
+                    .selected_payment()
+                    .and_then(|x| x.memo.clone())
+                    .unwrap_or_default();
+                self.memo_input = Input::new(memo);
+                self.mode = InputMode::Memo
+            }
+            (InputMode::Details, Action::Attach) => {
+                self.mode = InputMode::Attaching;
+                self.location = InputLocation::Path
+            }
+            (InputMode::Details, Action::ScrollDown) => {
+                self.attachment_scroll = self
+                    .attachment_scroll
+                    .saturating_add(1)
+                    .min(self.attachments.len().saturating_sub(1))
+            }
+            (InputMode::Details, Action::ScrollUp) => {
+                self.attachment_scroll = self.attachment_scroll.saturating_sub(1)
+            }
+            (InputMode::Details, Action::Open) => {
+                if let Some(file) = self.attachments.get(self.attachment_scroll) {
+                    attachment::open(&file.path(&self.attachments_dir))?;
+                }
+            }
+
+            (InputMode::Trash, Action::ScrollDown) => {
+                self.trash_scroll = self
+                    .trash_scroll
+                    .saturating_add(1)
+                    .min(self.trash.len().saturating_sub(1))
+            }
+            (InputMode::Trash, Action::ScrollUp) => {
+                self.trash_scroll = self.trash_scroll.saturating_sub(1)
+            }
+            (InputMode::Trash, Action::Restore) => {
+                if let Some(item) = self.trash.get(self.trash_scroll) {
+                    trash::restore(&self.pool, item.record, item.id).await?;
+                    self.message = Some(format!("Restored {} {}", item.record.as_str(), item.id));
+                    self.load_trash().await?;
+                    self.load().await?
+                }
+            }
+            (InputMode::Trash, Action::Purge) => {
+                if let Some(item) = self.trash.get(self.trash_scroll) {
+                    trash::purge_one(&self.pool, item.record, item.id).await?;
+                    self.message = Some(format!("Deleted {} {} forever", item.record.as_str(), item.id));
+                    self.load_trash().await?
+                }
+            }
+
+            (_, _) => {}
+        }
+        Ok(false)
+    }
+    /// Handles a key press, returning whether the app should quit
+    async fn handle_key(
+        &mut self,
+        evt: &Event,
+        key: KeyEvent,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        match self.mode {
+            InputMode::Editing => match key.code {
+                KeyCode::Enter => {
+                    self.add_payment().await?;
+                    self.mode = InputMode::Normal;
+                    self.load().await?
+                }
+                KeyCode::Esc => self.mode = InputMode::Normal,
+                KeyCode::Tab => {
+                    self.location = match self.location {
+                        InputLocation::Type => InputLocation::Amount,
+                        InputLocation::Amount => InputLocation::Payee,
+                        InputLocation::Payee => InputLocation::Type,
+                        _ => unreachable!(),
+                    }
+                }
+                KeyCode::Right
+                    if self.location == InputLocation::Payee
+                        && self.payment_input.2.cursor()
+                            == self.payment_input.2.value().chars().count() =>
+                {
+                    if let Some(name) =
+                        payee::complete(&self.payees, self.payment_input.2.value())
+                    {
+                        self.payment_input.2 = Input::new(name.to_string());
+                    }
+                }
+                _ => match self.location {
+                    InputLocation::Type => {
+                        self.payment_input.0.handle_event(evt);
+                    }
+                    InputLocation::Amount => {
+                        self.payment_input.1.handle_event(evt);
+                    }
+                    InputLocation::Payee => {
+                        self.payment_input.2.handle_event(evt);
+                    }
+                    _ => unreachable!(),
+                },
+            },
+
+            InputMode::NewBudget => match key.code {
+                KeyCode::Esc => {
+                    self.new_budget = (Input::default(), Input::default());
+                    self.mode = InputMode::Normal;
+                }
+                KeyCode::Enter => {
+                    self.add_budget().await?;
+                    self.load().await?;
+                    self.mode = InputMode::Normal
+                }
+                KeyCode::Tab => {
+                    self.location = match self.location {
+                        InputLocation::Budget => InputLocation::Month,
+                        InputLocation::Month => InputLocation::Budget,
+                        _ => unreachable!(),
+                    };
+                }
+                _ => match self.location {
+                    InputLocation::Budget => {
+                        self.new_budget.0.handle_event(evt);
+                    }
+                    InputLocation::Month => {
+                        self.new_budget.1.handle_event(evt);
+                    }
+                    _ => unreachable!(),
+                },
+            },
+            InputMode::Deleting => match key.code {
+                KeyCode::Esc => {
+                    self.deletion_id = Input::default();
+                    self.mode = InputMode::Normal;
+                }
+                KeyCode::Enter => {
+                    self.delete().await?;
+                    self.load().await?;
+                    self.mode = InputMode::Normal
+                }
+                _ => {
+                    self.deletion_id.handle_event(evt);
+                }
+            },
+
+            InputMode::Memo => match key.code {
+                KeyCode::Esc => {
+                    self.memo_input = Input::default();
+                    self.mode = InputMode::Details
+                }
+                KeyCode::Enter => {
+                    self.save_memo().await?;
+                    self.load().await?;
+                    self.mode = InputMode::Details
+                }
+                _ => {
+                    self.memo_input.handle_event(evt);
+                }
+            },
+            InputMode::Attaching => match key.code {
+                KeyCode::Esc => {
+                    self.attachment_path = Input::default();
+                    self.mode = InputMode::Details
+                }
+                KeyCode::Enter => {
+                    self.add_attachment().await?;
+                    self.load_attachments().await?;
+                    self.mode = InputMode::Details
+                }
+                _ => {
+                    self.attachment_path.handle_event(evt);
+                }
+            },
+
+            InputMode::Search => match key.code {
+                KeyCode::Esc => {
+                    self.search_input = Input::default();
+                    self.matches.clear();
+                    self.mode = InputMode::Normal
+                }
+                KeyCode::Enter => self.mode = InputMode::Normal,
+                _ => {
+                    self.search_input.handle_event(evt);
+                    self.update_search().await?;
+                    self.jump_to_match(true, true);
+                }
+            },
+
+            InputMode::Filtering => match key.code {
+                KeyCode::Esc => self.mode = InputMode::Normal,
+                KeyCode::Enter => {
+                    self.apply_filters()?;
+                    self.load().await?;
+                    self.mode = InputMode::Normal
+                }
+                KeyCode::Tab => {
+                    self.location = match self.location {
+                        InputLocation::MinAmount => InputLocation::MaxAmount,
+                        InputLocation::MaxAmount => InputLocation::KindFilter,
+                        InputLocation::KindFilter => InputLocation::From,
+                        InputLocation::From => InputLocation::To,
+                        InputLocation::To => InputLocation::MinAmount,
+                        _ => unreachable!(),
+                    }
+                }
+                _ => {
+                    self.filter_input[self.location.filter_index()].handle_event(evt);
+                }
+            },
+
+            InputMode::Normal | InputMode::Details | InputMode::Trash | InputMode::Payees => {
+                let scope = self.mode.scope().unwrap_or(Scope::Normal);
+                if let Resolved::Action(action) =
+                    self.keymap.resolve(scope, &mut self.pending, Key::from_event(&key))
+                    && self.perform(action).await?
+                {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+    /// Reloads whatever is on screen when the database was changed by someone else, such as
+    /// another `budge` process. `watcher` is a connection kept aside for `PRAGMA data_version`,
+    /// which only moves when a different connection commits
+    async fn refresh(
+        &mut self,
+        watcher: &mut PoolConnection<Sqlite>,
+        seen: &mut i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let version = db::data_version(watcher).await?;
+        if version == *seen {
+            return Ok(());
+        }
+        *seen = version;
+
+        self.load().await?;
+        match self.mode {
+            InputMode::Details => self.load_attachments().await?,
+            InputMode::Trash => self.load_trash().await?,
+            InputMode::Payees => self.payee_summaries = payee::summaries(&self.pool).await?,
+            InputMode::Search => self.update_search().await?,
+            _ => {}
+        }
         Ok(())
     }
     pub async fn run<B: Backend>(
         mut self,
         terminal: &mut Terminal<B>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        match self.load().await {
-            Ok(_) => {}
-            Err(_) => {
-                query!(
-                    "INSERT INTO budget (amount, month) VALUES (?, ?)",
-                    1000,
-                    202501
-                )
-                .execute(&self.pool)
-                .await?;
-            }
-        }
+        self.load().await?;
+
+        let mut events = EventStream::new();
+        let mut tick = tokio::time::interval(TICK);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut watcher = self.pool.acquire().await?;
+        let mut data_version = db::data_version(&mut watcher).await?;
         loop {
             terminal.draw(|f| self.draw(f)).unwrap();
-            let evt = event::read()?;
+            let evt = tokio::select! {
+                evt = events.next() => match evt {
+                    Some(evt) => evt?,
+                    None => return Ok(()),
+                },
+                _ = tick.tick() => {
+                    if let Err(e) = self.refresh(&mut watcher, &mut data_version).await {
+                        let e = e.to_string();
+                        if !self.toasts.contains(&e) {
+                            self.toasts.push(e);
+                        }
+                    }
+                    continue;
+                }
+            };
+            // Drawing lays everything out again at the new size, so a resize only needs the
+            // loop to go round once more
+            if let Event::Resize(..) = evt {
+                continue;
+            }
             if let Event::Key(key) = evt {
                 if key.kind == event::KeyEventKind::Release {
                     continue;
                 }
-                match self.mode {
-                    InputMode::Editing => match key.code {
-                        KeyCode::Enter => {
-                            self.add_payment().await?;
-                            self.mode = InputMode::Normal;
-                            self.load().await?
-                        }
-                        KeyCode::Esc => self.mode = InputMode::Normal,
-                        KeyCode::Tab => {
-                            self.location = match self.location {
-                                InputLocation::Type => InputLocation::Amount,
-                                InputLocation::Amount => InputLocation::Type,
-                                _ => unreachable!(),
-                            }
-                        }
-                        _ => match self.location {
-                            InputLocation::Type => {
-                                self.payment_input.0.handle_event(&evt);
-                            }
-                            InputLocation::Amount => {
-                                self.payment_input.1.handle_event(&evt);
-                            }
-                            _ => unreachable!(),
-                        },
-                    },
-
-                    InputMode::NewBudget => match key.code {
-                        KeyCode::Esc => {
-                            self.new_budget = (Input::default(), Input::default());
-                            self.mode = InputMode::Normal;
-                        }
-                        KeyCode::Enter => {
-                            self.add_budget().await?;
-                            self.load().await?;
-                            self.mode = InputMode::Normal
-                        }
-                        KeyCode::Tab => {
-                            self.location = match self.location {
-                                InputLocation::Budget => InputLocation::Month,
-                                InputLocation::Month => InputLocation::Budget,
-                                _ => unreachable!(),
-                            };
-                        }
-                        _ => match self.location {
-                            InputLocation::Budget => {
-                                self.new_budget.0.handle_event(&evt);
-                            }
-                            InputLocation::Month => {
-                                self.new_budget.1.handle_event(&evt);
-                            }
-                            _ => unreachable!(),
-                        },
-                    },
-                    InputMode::Deleting => match key.code {
-                        KeyCode::Esc => {
-                            self.deletion_id = Input::default();
-                            self.mode = InputMode::Normal;
-                        }
-                        KeyCode::Enter => {
-                            self.delete().await?;
-                            self.load().await?;
-                            self.mode = InputMode::Normal
-                        }
-                        _ => {
-                            self.deletion_id.handle_event(&evt);
-                        }
-                    },
-
-                    InputMode::Normal => match key.code {
-                        KeyCode::Delete => self.mode = InputMode::Deleting,
-                        KeyCode::Char('b') => {
-                            self.mode = InputMode::NewBudget;
-                            self.location = InputLocation::Budget
-                        }
-                        KeyCode::Char('q') => return Ok(()),
-                        KeyCode::Char('j') => {
-                            self.scroll = self.scroll.saturating_add(1).clamp(0, self.payments.len() - 1);
-                            self.scroll_state = self.scroll_state.position(self.scroll)
-                        }
-                        KeyCode::Char('k') => {
-                            self.scroll = self.scroll.saturating_sub(1).clamp(0, self.payments.len() - 1);
-                            self.scroll_state = self.scroll_state.position(self.scroll)
-                        }
-                        KeyCode::Char('a') => {
-                            self.mode = InputMode::Editing;
-                            self.location = InputLocation::Type
+                self.message = None;
+                self.input_error = None;
+                if !self.toasts.is_empty() {
+                    self.toasts.clear();
+                    continue;
+                }
+                if self.help {
+                    self.help = false;
+                    continue;
+                }
+                if key.code == KeyCode::F(1) {
+                    self.help = true;
+                    continue;
+                }
+                match self.handle_key(&evt, key).await {
+                    Ok(true) => return Ok(()),
+                    Ok(false) => {}
+                    Err(e) => match e.downcast::<Invalid>() {
+                        Ok(invalid) => {
+                            self.location = invalid.location;
+                            self.input_error = Some((invalid.location, invalid.message))
                         }
-                        _ => {}
+                        Err(e) => self.toasts.push(e.to_string()),
                     },
                 }
             }
         }
     }
+    /// A bordered block in the theme's colors, with the title highlighted when `focused`
+    fn block<'a>(&self, title: impl Into<Line<'a>>, focused: bool) -> Block<'a> {
+        Block::bordered()
+            .title(title)
+            .title_style(match focused {
+                true => self.theme.focused,
+                false => self.theme.title,
+            })
+            .border_style(self.theme.border)
+    }
+    /// Puts the reason the input at `location` was rejected, if it was, under it
+    fn with_error<'a>(&self, block: Block<'a>, location: InputLocation) -> Block<'a> {
+        match &self.input_error {
+            Some((at, message)) if *at == location => {
+                block.title_bottom(Line::styled(format!(" {message} "), self.theme.error))
+            }
+            _ => block,
+        }
+    }
+    /// Key hints like ` m: memo  f: attach ` built from the active keymap
+    pub fn hints(&self, scope: Scope, actions: &[(Action, &str)]) -> String {
+        let hints: Vec<String> = actions
+            .iter()
+            .filter_map(|(action, label)| {
+                let keys = self.keymap.keys_for(scope, *action);
+                keys.first().map(|key| format!("{key}: {label}"))
+            })
+            .collect();
+        format!(" {} ", hints.join("  "))
+    }
+    /// Draws `input` inside `block`, scrolled to keep its cursor between the borders, and
+    /// places the terminal cursor there when `focused`. `suggestion` is dimmed after the text
+    fn render_input(
+        &self,
+        frame: &mut Frame,
+        input: &Input,
+        suggestion: String,
+        block: Block,
+        area: Rect,
+        focused: bool,
+    ) {
+        let scroll = input.visual_scroll(area.width.saturating_sub(3) as usize);
+        let text = Paragraph::new(Line::from(vec![
+            Span::raw(input.value()),
+            Span::styled(suggestion, self.theme.dim),
+        ]))
+        .scroll((0, scroll as u16))
+        .block(block);
+        frame.render_widget(text, area);
+
+        if focused && area.width > 2 && area.height > 2 {
+            let x = (input.visual_cursor().saturating_sub(scroll) + 1).min(area.width as usize - 2);
+            frame.set_cursor_position((area.x + x as u16, area.y + 1));
+        }
+    }
     pub fn render_add_payment_textbox(&self, frame: &mut Frame, area: Rect) {
-        let input_scroll = self
-            .payment_input
-            .0
-            .visual_scroll((area.width.max(3) - 3) as usize);
-        let [ty, amount] = Layout::default()
+        let [ty, amount, payee] = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Fill(3), Constraint::Fill(1)])
+            .constraints([Constraint::Fill(3), Constraint::Fill(1), Constraint::Fill(2)])
             .split(area)[..]
         else {
             panic!()
         };
+        let focused = |location| self.mode == InputMode::Editing && self.location == location;
 
-        let in_type = Paragraph::new(self.payment_input.0.value()).block(
-            Block::bordered()
-                .title(" kind ")
-                .title_style(match (self.location, self.mode) {
-                    (InputLocation::Type, InputMode::Editing) => Style::default().yellow(),
-                    (_, _) => Style::default().white(),
-                }).border_style(Style::default().red())
-        );
-        let in_amount = Paragraph::new(self.payment_input.1.value()).block(
-            Block::bordered()
-                .title(" amount ")
-                .title_style(match (self.location, self.mode) {
-                    (InputLocation::Amount, InputMode::Editing) => Style::default().yellow(),
-                    (_, _) => Style::default().white(),
-                }).border_style(Style::default().red())
-        );
-        if self.mode == InputMode::Editing {
-            match self.location {
-                InputLocation::Type => {
-                    let x =
-                        self.payment_input.0.visual_cursor().max(input_scroll) - input_scroll + 1;
-                    frame.set_cursor_position((ty.x + x as u16, ty.y + 1))
-                }
-                InputLocation::Amount => {
-                    let x =
-                        self.payment_input.1.visual_cursor().max(input_scroll) - input_scroll + 1;
-                    frame.set_cursor_position((amount.x + x as u16, amount.y + 1))
-                }
-                _ => unreachable!(),
-            }
+        let typed = self.payment_input.2.value();
+        let suggestion = match focused(InputLocation::Payee) {
+            true => payee::complete(&self.payees, typed)
+                .map(|name| name.chars().skip(typed.chars().count()).collect::<String>())
+                .unwrap_or_default(),
+            false => String::new(),
+        };
+        let fields = [
+            (&self.payment_input.0, " kind ", InputLocation::Type, String::new(), ty),
+            (&self.payment_input.1, " amount ", InputLocation::Amount, String::new(), amount),
+            (&self.payment_input.2, " payee ", InputLocation::Payee, suggestion, payee),
+        ];
+        for (input, title, location, suggestion, area) in fields {
+            let block = self.with_error(self.block(title, focused(location)), location);
+            self.render_input(frame, input, suggestion, block, area, focused(location));
         }
-        frame.render_widget(in_type, ty);
-        frame.render_widget(in_amount, amount);
     }
     pub fn render_deletion(&self, frame: &mut Frame, area: Rect) {
-        let in_del =
-            Paragraph::new(self.deletion_id.value()).block(Block::bordered().title(" deleting id: "));
-
-        let input_scroll = self
-            .deletion_id
-            .visual_scroll((area.width.max(3) - 3) as usize);
-
-        let x = self.deletion_id.visual_cursor().max(input_scroll) - input_scroll + 1;
-        frame.render_widget(in_del, area);
-        frame.set_cursor_position((area.x + x as u16, area.y + 1));
+        let block = self.with_error(self.block(" deleting id: ", true), InputLocation::Id);
+        self.render_input(frame, &self.deletion_id, String::new(), block, area, true);
     }
     pub fn render_adding_budget(&self, frame: &mut Frame, area: Rect) {
-        let in_amount = Paragraph::new(self.new_budget.0.value())
-            .block(Block::bordered().title(" budget amount ".white()).border_style(Style::default().red()));
-        let in_month = Paragraph::new(self.new_budget.1.value())
-            .block(Block::bordered().title(" budget month ".white()).border_style(Style::default().red()));
-
-        let input_scroll = self
-            .new_budget
-            .0
-            .visual_scroll((area.width.max(3) - 3) as usize);
-        let [amount, month] = horizontal![*=2, *=1].split(area)[..] else {
+        let [amount, month] = horizontal![*=1, *=1].split(area)[..] else {
             unreachable!()
         };
+        let focused = |location| self.mode == InputMode::NewBudget && self.location == location;
 
-        if self.mode == InputMode::NewBudget {
-            match self.location {
-                InputLocation::Budget => {
-                    let x = self.new_budget.0.visual_cursor().max(input_scroll) - input_scroll + 1;
-                    frame.set_cursor_position((amount.x + x as u16, amount.y + 1))
-                }
-                InputLocation::Month => {
-                    let x = self.new_budget.1.visual_cursor().max(input_scroll) - input_scroll + 1;
-                    frame.set_cursor_position((month.x + x as u16, month.y + 1))
-                }
-                _ => unreachable!(),
-            }
+        let fields = [
+            (&self.new_budget.0, " budget amount ", InputLocation::Budget, amount),
+            (&self.new_budget.1, " budget month ", InputLocation::Month, month),
+        ];
+        for (input, title, location, area) in fields {
+            let block = self.with_error(self.block(title, focused(location)), location);
+            self.render_input(frame, input, String::new(), block, area, focused(location));
         }
-        frame.render_widget(in_amount, amount);
-        frame.render_widget(in_month, month);
     }
     pub fn render_budget(&self, frame: &mut Frame, area: Rect) {
-        let total_payout: f64 = self.payments.iter().map(|x| x.amount).sum();
-        let budget = match self.budget.clone() {
-            Some(b) => b,
-            None => Budget::default(),
-        };
+        let total_payout = self.total_payout;
+        let budget = self.budget.clone().unwrap_or_default();
         let ratio = match self.budget.clone() {
-            Some(b) => total_payout.abs() / b.amount,
+            Some(b) if b.amount > 0.0 => total_payout.abs() / b.amount,
+            Some(_) => 0.0,
             None => 1.0,
         };
         let budget_visualizer = Gauge::default()
             .block(
                 Block::bordered()
-                    .title(" budget ".fg(Color::White))
-                    .border_style(Style::default().fg(Color::Red)),
+                    .title(" budget ")
+                    .title_style(self.theme.title)
+                    .border_style(self.theme.border),
             )
             .ratio(ratio.abs().clamp(0.0, 1.0))
             .gauge_style(match total_payout.signum() {
                 0.0 => Style::default(),
                 1.0 => {
                     if total_payout > budget.amount {
-                        Style::default().red()
+                        self.theme.over_budget
                     } else {
-                        Style::default().yellow()
+                        self.theme.under_budget
                     }
                 }
-                -1.0 => Style::default().green(),
+                -1.0 => self.theme.income,
                 _ => unreachable!(),
             })
             .label(match self.budget.clone() {
-                Some(_b) => format!("{:.2}/{} $", total_payout, budget.amount.abs()).black(),
-                None => "No budget loaded!".black(),
+                Some(_b) => Span::styled(
+                    format!(
+                        "{}/{}",
+                        self.config.money(total_payout),
+                        self.config.money(budget.amount.abs())
+                    ),
+                    self.theme.gauge_label,
+                ),
+                None => Span::styled("No budget loaded!", self.theme.gauge_label),
             });
         frame.render_widget(budget_visualizer, area);
     }
     pub fn _render_main(&self, frame: &mut Frame, area: Rect) {
         let main_info = Block::default()
-            .title(" info ".fg(Color::White))
+            .title(" info ")
+            .title_style(self.theme.title)
             .title_alignment(ratatui::layout::HorizontalAlignment::Left)
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Red));
+            .border_style(self.theme.border);
         frame.render_widget(main_info, area);
     }
     pub fn render_payments(&self, frame: &mut Frame, area: Rect) {
-        let (ids, kinds, amounts, days): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) =
+        let block = self.payments_block();
+        if self.payments.is_empty() {
+            let hint = match self.filters.chips().is_empty() {
+                true => self
+                    .keymap
+                    .keys_for(Scope::Normal, Action::AddPayment)
+                    .first()
+                    .map(|key| format!("No payments yet, press {key} to add one"))
+                    .unwrap_or_else(|| "No payments yet".to_string()),
+                false => self
+                    .keymap
+                    .keys_for(Scope::Normal, Action::ClearFilters)
+                    .first()
+                    .map(|key| format!("No payments match the filters, press {key} to clear them"))
+                    .unwrap_or_else(|| "No payments match the filters".to_string()),
+            };
+            let inner = block.inner(area);
+            let middle = Rect {
+                y: inner.y + inner.height / 2,
+                height: inner.height.min(1),
+                ..inner
+            };
+            frame.render_widget(block, area);
+            frame.render_widget(
+                Paragraph::new(Span::styled(hint, self.theme.dim)).centered(),
+                middle,
+            );
+            return;
+        }
+
+        let (ids, kinds, amounts, days, payees): (Vec<_>, Vec<_>, Vec<_>, Vec<_>, Vec<_>) =
             multiunzip(self.payments.iter().map(|x| {
                 (
                     x.id.to_string(),
                     x.kind.clone(),
-                    x.amount.to_string(),
-                    x.day_of.clone(),
+                    self.config.money(x.amount),
+                    self.config.date(&x.day_of),
+                    x.payee.clone().unwrap_or_default(),
                 )
             }));
 
         let rows = self.payments.iter().enumerate().map(|(i, x)| {
             let s_1 = x.id.to_string();
             let s_2 = x.kind.clone();
-            let s_3 = x.amount.to_string();
-            let s_4 = x.day_of.clone();
+            let s_3 = self.config.money(x.amount);
+            let s_4 = self.config.date(&x.day_of);
+            let s_5 = x.payee.clone().unwrap_or_default();
 
-            Row::new([s_1, s_2, s_3, s_4]).style(match i % 2 {
-                0 => Style::default().on_black(),
-                1 => Style::default(),
-                _ => unreachable!(),
+            let style = self.theme.row(i);
+            Row::new([s_1, s_2, s_3, s_4, s_5]).style(match self.matches.contains(&x.id) {
+                true => style.patch(self.theme.search_match),
+                false => style,
             })
         });
         let table = Table::new(
             rows.clone(),
             [
-                Constraint::Min(
-                    ids.iter().max().unwrap_or(&"".to_string()).len() as u16 + 1,
-                ),
-                Constraint::Min(
-                    kinds.iter().max().unwrap_or(&"".to_string()).len() as u16 + 1,
-                ),
-                Constraint::Min(
-                    amounts.iter().max().unwrap_or(&"".to_string()).len() as u16 + 1,
-                ),
-                Constraint::Min(
-                    days.iter().max().unwrap_or(&"".to_string()).len() as u16 + 1,
-                ),
+                Constraint::Min(width(ids.iter())),
+                Constraint::Min(width(kinds.iter())),
+                Constraint::Min(width(amounts.iter())),
+                Constraint::Min(width(days.iter())),
+                Constraint::Min(width(payees.iter())),
             ]
-        ).block(Block::bordered().title(" payments ".white()).border_style(Style::default().red()));
+        ).block(block);
 
         let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
             .begin_symbol(Some("↑"))
             .end_symbol(Some("↓"))
-            .style(Style::default().fg(Color::Gray));
-        let mut scr = self.scroll_state.content_length(rows.len());
+            .style(self.theme.scrollbar);
+        let mut scrollbar_state =
+            ScrollbarState::new(self.payments.len()).position(self.viewport.selected());
 
-        frame.render_stateful_widget(table, area, &mut TableState::default().with_offset(self.scroll));
+        frame.render_stateful_widget(
+            table.row_highlight_style(self.theme.selected),
+            area,
+            &mut TableState::default()
+                .with_offset(self.viewport.offset())
+                .with_selected(Some(self.viewport.selected())),
+        );
         frame.render_stateful_widget(
             scrollbar,
             area.inner(Margin {
                 horizontal: 0,
                 vertical: 1,
             }),
-            &mut scr,
+            &mut scrollbar_state,
+        );
+    }
+    fn payments_block(&self) -> Block<'_> {
+        Block::bordered()
+                .title(Line::from(
+                    [
+                        vec![
+                            Span::styled(" payments ", self.theme.title),
+                            Span::styled(
+                                format!(
+                                    "by {} {} ",
+                                    self.sort.as_str(),
+                                    if self.descending { "↓" } else { "↑" }
+                                ),
+                                self.theme.dim,
+                            ),
+                        ],
+                        self.filters
+                            .chips()
+                            .into_iter()
+                            .map(|chip| Span::styled(format!("[{chip}]"), self.theme.chip))
+                            .flat_map(|chip| [chip, " ".into()])
+                            .collect(),
+                    ]
+                    .concat(),
+                ))
+                .title_bottom(match (self.mode, self.search_input.value()) {
+                    (InputMode::Search, query) => Line::styled(
+                        format!(" /{query} ({} found) ", self.matches.len()),
+                        self.theme.focused,
+                    ),
+                    (_, "") => "".into(),
+                    (_, query) => Line::styled(
+                        format!(" /{query} ({} found, n/N to jump) ", self.matches.len()),
+                        self.theme.title,
+                    ),
+                })
+                .border_style(self.theme.border)
+    }
+    pub fn render_filters(&self, frame: &mut Frame, area: Rect) {
+        let fields = [
+            (InputLocation::MinAmount, " min amount "),
+            (InputLocation::MaxAmount, " max amount "),
+            (InputLocation::KindFilter, " kind "),
+            (InputLocation::From, " from (YYYY-MM-DD) "),
+            (InputLocation::To, " to (YYYY-MM-DD) "),
+        ];
+        let rows = vertical![==3, ==3, ==3, ==3, ==3].split(area);
+
+        frame.render_widget(Clear, area);
+        for ((location, title), row) in fields.into_iter().zip(rows.iter()) {
+            let input = &self.filter_input[location.filter_index()];
+            let block = self.with_error(self.block(title, self.location == location), location);
+            self.render_input(frame, input, String::new(), block, *row, self.location == location);
+        }
+    }
+    pub fn render_trash(&self, frame: &mut Frame, area: Rect) {
+        let header = Row::new(["", "id", "what", "deleted"]).style(self.theme.header);
+        let rows = self.trash.iter().map(|x| {
+            Row::new([
+                x.record.as_str().to_string(),
+                x.id.to_string(),
+                x.description.clone(),
+                x.deleted_at.clone(),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(6),
+                Constraint::Fill(3),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .row_highlight_style(self.theme.selected)
+        .block(
+            self.block(" trash ", false).title_bottom(self.hints(
+                Scope::Trash,
+                &[(Action::Restore, "restore"), (Action::Purge, "delete forever")],
+            )),
+        );
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(
+            table,
+            area,
+            &mut TableState::default().with_selected(Some(self.trash_scroll)),
+        );
+    }
+    pub fn render_payees(&self, frame: &mut Frame, area: Rect) {
+        let header = Row::new(["payee", "visits", "total", "average", "last visit"])
+            .style(self.theme.header);
+        let rows = self.payee_summaries.iter().enumerate().map(|(i, x)| {
+            Row::new([
+                x.name.clone(),
+                x.visits.to_string(),
+                self.config.money(x.total),
+                self.config.money(x.average),
+                x.last_visit
+                    .as_ref()
+                    .map(|x| self.config.date(x))
+                    .unwrap_or_else(|| "never".to_string()),
+            ])
+            .style(self.theme.row(i))
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(2),
+            ],
+        )
+        .header(header)
+        .block(self.block(" payees ", false));
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(table, area);
+    }
+    pub fn render_details(&self, frame: &mut Frame, area: Rect) {
+        let Some(payment) = self.selected_payment() else {
+            return;
+        };
+        let [info, memo, files] = vertical![==6, ==3, *=1].split(area)[..] else {
+            unreachable!()
+        };
+
+        let details = Paragraph::new(vec![
+            Line::from(vec![
+                Span::styled(" kind: ", self.theme.header),
+                Span::raw(payment.kind.clone()),
+            ]),
+            Line::from(vec![
+                Span::styled(" amount: ", self.theme.header),
+                Span::raw(self.config.money(payment.amount)),
+            ]),
+            Line::from(vec![
+                Span::styled(" payee: ", self.theme.header),
+                Span::raw(payment.payee.clone().unwrap_or_default()),
+            ]),
+            Line::from(vec![
+                Span::styled(" date: ", self.theme.header),
+                Span::raw(self.config.date(&payment.day_of)),
+            ]),
+            Line::from(vec![
+                Span::styled(" uuid: ", self.theme.header),
+                Span::raw(payment.uuid.clone()),
+            ]),
+        ])
+        .block(self.block(format!(" payment {} ", payment.id), false));
+
+
+        let rows = self.attachments.iter().map(|x| Row::new([x.name.clone(), x.added_at.clone()]));
+        let attachments = Table::new(rows, [Constraint::Fill(2), Constraint::Fill(1)])
+            .row_highlight_style(self.theme.selected)
+            .block(self.block(" attachments ", false).title_bottom(self.hints(
+                Scope::Details,
+                &[(Action::EditMemo, "memo"), (Action::Attach, "attach"), (Action::Open, "open")],
+            )));
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(details, info);
+        match self.mode {
+            InputMode::Memo => {
+                let block = self.block(" memo ", true);
+                self.render_input(frame, &self.memo_input, String::new(), block, memo, true);
+            }
+            _ => frame.render_widget(
+                Paragraph::new(payment.memo.clone().unwrap_or_default())
+                    .block(self.block(" memo ", false)),
+                memo,
+            ),
+        }
+        frame.render_stateful_widget(
+            attachments,
+            files,
+            &mut TableState::default().with_selected(Some(self.attachment_scroll)),
         );
 
+        if self.mode == InputMode::Attaching {
+            let mid = vertical![*=1, ==3, *=1].split(files);
+            let block = self.with_error(self.block(" file to attach ", true), InputLocation::Path);
+            frame.render_widget(Clear, mid[1]);
+            self.render_input(frame, &self.attachment_path, String::new(), block, mid[1], true);
+        }
+    }
+    /// Keys that matter in the current mode, for the status bar
+    fn status_hints(&self) -> String {
+        let actions: &[(Action, &str)] = match self.mode {
+            InputMode::Normal => &[
+                (Action::AddPayment, "add"),
+                (Action::Details, "details"),
+                (Action::Search, "search"),
+                (Action::Filter, "filter"),
+                (Action::Undo, "undo"),
+                (Action::Help, "help"),
+                (Action::Quit, "quit"),
+            ],
+            InputMode::Details => &[
+                (Action::EditMemo, "memo"),
+                (Action::Attach, "attach"),
+                (Action::Open, "open"),
+                (Action::Help, "help"),
+                (Action::Close, "close"),
+            ],
+            InputMode::Trash => &[
+                (Action::Restore, "restore"),
+                (Action::Purge, "delete forever"),
+                (Action::Help, "help"),
+                (Action::Close, "close"),
+            ],
+            InputMode::Payees => &[(Action::Help, "help"), (Action::Close, "close")],
+            InputMode::Editing | InputMode::NewBudget | InputMode::Filtering => {
+                return " Enter: save  Esc: cancel  Tab: next field  F1: help ".to_string();
+            }
+            InputMode::Search => return " Enter: done  Esc: clear  F1: help ".to_string(),
+            InputMode::Deleting | InputMode::Memo | InputMode::Attaching => {
+                return " Enter: save  Esc: cancel  F1: help ".to_string();
+            }
+        };
+        self.hints(self.mode.scope().unwrap_or(Scope::Normal), actions)
+    }
+    pub fn render_status(&self, frame: &mut Frame, area: Rect) {
+        let month = self
+            .budget
+            .as_ref()
+            .map(|x| x.month.clone())
+            .unwrap_or_else(|| "no budget".to_string());
+        let message = self.message.clone().unwrap_or_default();
+        let width = message.chars().count() as u16 + 1;
+        let [left, right] = horizontal![*=1, ==width].split(area)[..] else {
+            unreachable!()
+        };
+
+        let status = Paragraph::new(Line::from(vec![
+            Span::styled(format!(" {} ", self.mode.name()), self.theme.chip),
+            Span::styled(format!(" {month} "), self.theme.title),
+            Span::styled(self.status_hints(), self.theme.dim),
+        ]));
+        frame.render_widget(status, left);
+        frame.render_widget(Span::styled(message, self.theme.focused), right);
+    }
+    /// Every binding of the current mode, opened with `?` or F1
+    pub fn render_help(&self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<(String, String)> = match self.mode.scope() {
+            Some(scope) => self
+                .keymap
+                .actions(scope)
+                .into_iter()
+                .map(|(keys, action)| (keys.join(", "), action.description().to_string()))
+                .collect(),
+            None => EDITING_KEYS
+                .into_iter()
+                .filter(|(key, _)| match *key {
+                    "Tab" => matches!(
+                        self.mode,
+                        InputMode::Editing | InputMode::NewBudget | InputMode::Filtering
+                    ),
+                    "Right" => self.mode == InputMode::Editing,
+                    _ => true,
+                })
+                .map(|(key, description)| (key.to_string(), description.to_string()))
+                .collect(),
+        };
+        let key_width = rows.iter().map(|(key, _)| key.chars().count()).max().unwrap_or(0);
+
+        let table = Table::new(
+            rows.into_iter().map(|(key, description)| Row::new([key, description])),
+            [Constraint::Length(key_width as u16 + 1), Constraint::Fill(1)],
+        )
+        .header(Row::new(["key", "action"]).style(self.theme.header))
+        .block(
+            self.block(format!(" keys: {} ", self.mode.name().to_lowercase()), false)
+                .title_bottom(Line::styled(" any key to close ", self.theme.dim)),
+        );
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(table, area);
+    }
+    /// Errors stacked in the top right corner, newest at the bottom
+    pub fn render_toasts(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.min(50);
+        let inner = width.saturating_sub(2).max(1) as usize;
+        let lines: Vec<Line> = self
+            .toasts
+            .iter()
+            .flat_map(|toast| {
+                toast
+                    .chars()
+                    .collect::<Vec<_>>()
+                    .chunks(inner)
+                    .map(|x| Line::raw(x.iter().collect::<String>()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let toast_area = Rect {
+            x: area.right().saturating_sub(width),
+            y: area.y,
+            width,
+            height,
+        };
+
+        let toasts = Paragraph::new(lines).style(self.theme.error).block(
+            Block::bordered()
+                .title(" error ")
+                .title_bottom(" any key to dismiss ")
+                .border_style(self.theme.error),
+        );
+        frame.render_widget(Clear, toast_area);
+        frame.render_widget(toasts, toast_area);
     }
     pub fn draw(&mut self, frame: &mut Frame) {
         let _layout = Layout::default()
@@ -454,6 +1346,9 @@ impl App {
 
         // self.render_main(frame, layout[0]);
 
+        let [main, status] = vertical![*=1, ==1].split(frame.area())[..] else {
+            unreachable!()
+        };
         let right_bar = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -461,10 +1356,11 @@ impl App {
                 Constraint::Min(3),
                 Constraint::Percentage(100),
             ])
-            .split(frame.area());
+            .split(main);
 
         self.render_budget(frame, right_bar[0]);
         self.render_add_payment_textbox(frame, right_bar[1]);
+        self.viewport.set_height(right_bar[2].height.saturating_sub(2) as usize);
         self.render_payments(frame, right_bar[2]);
         if self.mode == InputMode::Deleting {
             let center_of_right_bar = centered_rect(50, 50, right_bar[2]);
@@ -474,11 +1370,48 @@ impl App {
         if self.mode == InputMode::NewBudget {
             let center_of_right_bar = centered_rect(50, 50, frame.area());
             let mid = vertical![*=1, ==5, *= 1].split(center_of_right_bar);
-            self.render_adding_budget(frame, centered_rect(50, 50, mid[1]));
+            self.render_adding_budget(frame, centered_rect(80, 100, mid[1]));
+        }
+        if matches!(
+            self.mode,
+            InputMode::Details | InputMode::Memo | InputMode::Attaching
+        ) {
+            self.render_details(frame, centered_rect(70, 80, frame.area()));
+        }
+        if self.mode == InputMode::Filtering {
+            let center = centered_rect(50, 100, frame.area());
+            let mid = vertical![*=1, ==15, *=1].split(center);
+            self.render_filters(frame, mid[1]);
         }
+        if self.mode == InputMode::Trash {
+            self.render_trash(frame, centered_rect(80, 80, frame.area()));
+        }
+        if self.mode == InputMode::Payees {
+            self.render_payees(frame, centered_rect(80, 80, frame.area()));
+        }
+        if self.help {
+            self.render_help(frame, centered_rect(60, 80, main));
+        }
+        if !self.toasts.is_empty() {
+            self.render_toasts(frame, main);
+        }
+        self.render_status(frame, status);
     }
 }
 
+/// Reads an amount typed into the input at `location`
+fn parse_amount(value: &str, location: InputLocation) -> Result<f64, Box<dyn std::error::Error>> {
+    match value.trim().parse::<f64>() {
+        Ok(amount) if amount.is_finite() => Ok(amount),
+        _ => Err(Invalid::at(location, "expected a number")),
+    }
+}
+
+/// Width of a column fitting its widest cell
+fn width<'a>(cells: impl Iterator<Item = &'a String>) -> u16 {
+    cells.map(|x| x.chars().count()).max().unwrap_or(0) as u16 + 1
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     // Cut the given rectangle into three vertical pieces
     let popup_layout = Layout::default()
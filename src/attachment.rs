@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use sha2::{Digest, Sha256};
+use sqlx::{query, query_as, Pool, Sqlite};
+
+use crate::Result;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct Attachment {
+    pub id: i64,
+    pub uuid: String,
+    pub payment_id: i64,
+    pub name: String,
+    pub hash: String,
+    pub added_at: String,
+}
+
+impl Attachment {
+    /// Where the attachment's contents live inside `dir`, keeping the original extension
+    /// so that openers can figure out what kind of file it is
+    pub fn path(&self, dir: &Path) -> PathBuf {
+        stored_path(dir, &self.hash, &self.name)
+    }
+}
+
+/// Attachments are content addressed and kept next to the database
+pub fn attachments_dir(db_path: &Path) -> PathBuf {
+    db_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+        .join("budge-attachments")
+}
+
+fn stored_path(dir: &Path, hash: &str, name: &str) -> PathBuf {
+    match Path::new(name).extension() {
+        Some(ext) => dir.join(format!("{hash}.{}", ext.to_string_lossy())),
+        None => dir.join(hash),
+    }
+}
+
+pub async fn for_payment(
+    pool: &Pool<Sqlite>,
+    payment_id: i64,
+) -> Result<Vec<Attachment>> {
+    let attachments = query_as!(
+        Attachment,
+        r#"SELECT id, uuid AS "uuid!", payment_id, name, hash, added_at FROM attachments
+           WHERE payment_id = ? ORDER BY added_at"#,
+        payment_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(attachments)
+}
+
+/// Copies `file` into the attachment store and links it to the payment
+pub async fn attach(
+    pool: &Pool<Sqlite>,
+    dir: &Path,
+    payment_id: i64,
+    file: &Path,
+) -> Result<()> {
+    let contents = tokio::fs::read(file).await?;
+    let hash = format!("{:x}", Sha256::digest(&contents));
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| hash.clone());
+
+    tokio::fs::create_dir_all(dir).await?;
+    let stored = stored_path(dir, &hash, &name);
+    if !stored.exists() {
+        tokio::fs::write(&stored, &contents).await?;
+    }
+
+    query!(
+        "INSERT INTO attachments (payment_id, name, hash) VALUES (?, ?, ?)",
+        payment_id,
+        name,
+        hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Opens a stored attachment with `$OPENER`, falling back to the platform's default opener
+pub fn open(path: &Path) -> std::io::Result<()> {
+    let opener = std::env::var("OPENER").unwrap_or_else(|_| {
+        if cfg!(target_os = "macos") {
+            "open".to_string()
+        } else {
+            "xdg-open".to_string()
+        }
+    });
+
+    Command::new(opener)
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    Ok(())
+}
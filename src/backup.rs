@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::Utc;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, query_scalar, Pool, Sqlite};
+
+use crate::{budget, payment, Error, Result};
+
+/// Bumped whenever the layout of a backup changes. Restoring reads every version up to this one
+pub const VERSION: u32 = 2;
+
+/// Every budget, payee, payment and attachment, including the ones in the trash. The undo
+/// history isn't kept, and attachments are only listed, their files stay in `budge-attachments`.
+/// Version 2 added the uuids, which backups of version 1 don't have
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub version: u32,
+    /// When the backup was taken, in UTC
+    pub created_at: String,
+    pub budgets: Vec<BudgetRow>,
+    pub payees: Vec<PayeeRow>,
+    pub payments: Vec<PaymentRow>,
+    pub attachments: Vec<AttachmentRow>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BudgetRow {
+    pub id: i64,
+    #[serde(default)]
+    pub uuid: Option<String>,
+    pub amount: f64,
+    pub month: String,
+    pub deleted_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PayeeRow {
+    pub id: i64,
+    #[serde(default)]
+    pub uuid: Option<String>,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PaymentRow {
+    pub id: i64,
+    #[serde(default)]
+    pub uuid: Option<String>,
+    pub amount: f64,
+    pub kind: String,
+    pub budget_id: i64,
+    pub day_of: String,
+    pub payee_id: Option<i64>,
+    pub memo: Option<String>,
+    pub deleted_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AttachmentRow {
+    pub id: i64,
+    #[serde(default)]
+    pub uuid: Option<String>,
+    pub payment_id: i64,
+    pub name: String,
+    pub hash: String,
+    pub added_at: String,
+}
+
+/// What to do with a month that is already in the database, trashed or not
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conflict {
+    /// Restore nothing
+    #[default]
+    Fail,
+    /// Keep the month in the database and leave the backup's out
+    Skip,
+    /// Permanently delete the month in the database and its payments, then restore the backup's
+    Overwrite,
+}
+
+#[derive(Debug, Default)]
+pub struct Restored {
+    pub budgets: usize,
+    pub payments: usize,
+    pub skipped: Vec<String>,
+    pub overwritten: Vec<String>,
+}
+
+pub async fn backup(pool: &Pool<Sqlite>) -> Result<Backup> {
+    let budgets = query_as!(
+        BudgetRow,
+        "SELECT id, uuid, amount, month, deleted_at FROM budget ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await?;
+    let payees = query_as!(PayeeRow, "SELECT id, uuid, name FROM payees ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    let payments = query_as!(
+        PaymentRow,
+        r#"SELECT id AS "id!", uuid, amount, kind, budget_id, day_of, payee_id, memo, deleted_at
+           FROM payments ORDER BY id"#
+    )
+    .fetch_all(pool)
+    .await?;
+    let attachments = query_as!(
+        AttachmentRow,
+        "SELECT id, uuid, payment_id, name, hash, added_at FROM attachments ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Backup {
+        version: VERSION,
+        created_at: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        budgets,
+        payees,
+        payments,
+        attachments,
+    })
+}
+
+/// Reads and validates a backup written by this or an older budge
+pub fn read(json: &str) -> Result<Backup> {
+    #[derive(Deserialize)]
+    struct Header {
+        version: u32,
+    }
+    let invalid = |e: serde_json::Error| Error::Invalid(format!("not a budge backup: {e}"));
+    let header: Header = serde_json::from_str(json).map_err(invalid)?;
+    if header.version > VERSION {
+        return Err(Error::Invalid(format!(
+            "this backup has version {}, and this budge only reads up to {VERSION}, upgrade it first",
+            header.version
+        )));
+    }
+    let backup: Backup = serde_json::from_str(json).map_err(invalid)?;
+    validate(&backup)?;
+    Ok(backup)
+}
+
+/// Uuids are stored the way `budge` writes them, and a table can't have one twice
+fn check_uuid<'a>(
+    seen: &mut BTreeSet<&'a str>,
+    record: &str,
+    id: i64,
+    uuid: &'a Option<String>,
+) -> Result<()> {
+    let Some(uuid) = uuid else {
+        return Ok(());
+    };
+    if !uuid::Uuid::try_parse(uuid).is_ok_and(|x| x.to_string() == *uuid) {
+        return Err(Error::Invalid(format!("{record} {id} has the uuid {uuid:?}")));
+    }
+    if !seen.insert(uuid) {
+        return Err(Error::Invalid(format!("the uuid of {record} {id} appears twice")));
+    }
+    Ok(())
+}
+
+/// Checks that every record could be stored and that every reference points somewhere
+pub fn validate(backup: &Backup) -> Result<()> {
+    let invalid = |why: String| Err(Error::Invalid(why));
+    let mut uuids = BTreeSet::new();
+    let mut months = BTreeSet::new();
+    let mut budgets = BTreeSet::new();
+    for row in &backup.budgets {
+        if budget::parse_month(&row.month)? != row.month {
+            return invalid(format!("budget {} has the month {:?}", row.id, row.month));
+        }
+        if !row.amount.is_finite() {
+            return invalid(format!("budget {} has the amount {}", row.id, row.amount));
+        }
+        if !budgets.insert(row.id) || !months.insert(&row.month) {
+            return invalid(format!(
+                "budget {} or its month {} appears twice",
+                row.id, row.month
+            ));
+        }
+        check_uuid(&mut uuids, "budget", row.id, &row.uuid)?;
+    }
+    let mut uuids = BTreeSet::new();
+    let mut names = BTreeSet::new();
+    let mut payees = BTreeSet::new();
+    for row in &backup.payees {
+        if row.name.trim().is_empty() {
+            return invalid(format!("payee {} has no name", row.id));
+        }
+        if !payees.insert(row.id) || !names.insert(row.name.to_ascii_lowercase()) {
+            return invalid(format!(
+                "payee {} or its name {:?} appears twice",
+                row.id, row.name
+            ));
+        }
+        check_uuid(&mut uuids, "payee", row.id, &row.uuid)?;
+    }
+    let mut uuids = BTreeSet::new();
+    let mut payments = BTreeSet::new();
+    for row in &backup.payments {
+        payment::check(&row.kind, row.amount)
+            .map_err(|e| Error::Invalid(format!("payment {}: {e}", row.id)))?;
+        if !payments.insert(row.id) {
+            return invalid(format!("payment {} appears twice", row.id));
+        }
+        if !budgets.contains(&row.budget_id) {
+            return invalid(format!(
+                "payment {} has no budget {}",
+                row.id, row.budget_id
+            ));
+        }
+        if let Some(payee) = row.payee_id.filter(|x| !payees.contains(x)) {
+            return invalid(format!("payment {} has no payee {payee}", row.id));
+        }
+        check_uuid(&mut uuids, "payment", row.id, &row.uuid)?;
+    }
+    let mut uuids = BTreeSet::new();
+    let mut attachments = BTreeSet::new();
+    for row in &backup.attachments {
+        if !attachments.insert(row.id) || !payments.contains(&row.payment_id) {
+            return invalid(format!(
+                "attachment {} appears twice or has no payment",
+                row.id
+            ));
+        }
+        check_uuid(&mut uuids, "attachment", row.id, &row.uuid)?;
+    }
+    Ok(())
+}
+
+/// Loads a backup in a single transaction. Records keep their ids and uuids unless the database
+/// already uses them, and payees with the same name are shared
+pub async fn restore(pool: &Pool<Sqlite>, backup: &Backup, conflict: Conflict) -> Result<Restored> {
+    validate(backup)?;
+    let mut restored = Restored::default();
+    let mut tx = pool.begin().await?;
+
+    let mut skipped_budgets = BTreeSet::new();
+    let mut taken = Vec::new();
+    for row in &backup.budgets {
+        let existing = query_scalar!(
+            r#"SELECT id AS "id!" FROM budget WHERE month = ?"#,
+            row.month
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(id) = existing else { continue };
+        match conflict {
+            Conflict::Fail => taken.push(row.month.clone()),
+            Conflict::Skip => {
+                skipped_budgets.insert(row.id);
+                restored.skipped.push(row.month.clone());
+            }
+            Conflict::Overwrite => {
+                query!(
+                    "DELETE FROM journal WHERE payment_id IN (SELECT id FROM payments WHERE budget_id = ?)",
+                    id
+                )
+                .execute(&mut *tx)
+                .await?;
+                query!("DELETE FROM budget WHERE id = ?", id)
+                    .execute(&mut *tx)
+                    .await?;
+                restored.overwritten.push(row.month.clone());
+            }
+        }
+    }
+    if !taken.is_empty() {
+        return Err(Error::Invalid(format!(
+            "the database already has {}, pick what to do with --on-conflict skip or overwrite",
+            taken.join(", ")
+        )));
+    }
+
+    let mut budgets = BTreeMap::new();
+    for row in backup
+        .budgets
+        .iter()
+        .filter(|x| !skipped_budgets.contains(&x.id))
+    {
+        let free = query!(
+            r#"SELECT NOT EXISTS (SELECT 1 FROM budget WHERE id = ?) AS "id!: bool",
+                      NOT EXISTS (SELECT 1 FROM budget WHERE uuid = ?) AS "uuid!: bool""#,
+            row.id,
+            row.uuid
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let id = free.id.then_some(row.id);
+        let uuid = row.uuid.as_ref().filter(|_| free.uuid);
+        let id = query!(
+            "INSERT INTO budget (id, uuid, amount, month, deleted_at) VALUES (?, ?, ?, ?, ?)",
+            id,
+            uuid,
+            row.amount,
+            row.month,
+            row.deleted_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        budgets.insert(row.id, id);
+        restored.budgets += 1;
+    }
+
+    let mut payees = BTreeMap::new();
+    for row in &backup.payees {
+        let existing = query_scalar!(r#"SELECT id AS "id!" FROM payees WHERE name = ?"#, row.name)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let free = query!(
+                    r#"SELECT NOT EXISTS (SELECT 1 FROM payees WHERE id = ?) AS "id!: bool",
+                              NOT EXISTS (SELECT 1 FROM payees WHERE uuid = ?) AS "uuid!: bool""#,
+                    row.id,
+                    row.uuid
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+                let id = free.id.then_some(row.id);
+                let uuid = row.uuid.as_ref().filter(|_| free.uuid);
+                query!(
+                    "INSERT INTO payees (id, uuid, name) VALUES (?, ?, ?)",
+                    id,
+                    uuid,
+                    row.name
+                )
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid()
+            }
+        };
+        payees.insert(row.id, id);
+    }
+
+    let mut payments = BTreeMap::new();
+    for row in &backup.payments {
+        let Some(budget_id) = budgets.get(&row.budget_id) else {
+            continue;
+        };
+        let payee_id = row.payee_id.and_then(|x| payees.get(&x).copied());
+        let free = query!(
+            r#"SELECT NOT EXISTS (SELECT 1 FROM payments WHERE id = ?) AS "id!: bool",
+                      NOT EXISTS (SELECT 1 FROM payments WHERE uuid = ?) AS "uuid!: bool""#,
+            row.id,
+            row.uuid
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let id = free.id.then_some(row.id);
+        let uuid = row.uuid.as_ref().filter(|_| free.uuid);
+        let id = query!(
+            r#"INSERT INTO payments
+                   (id, uuid, amount, kind, budget_id, day_of, payee_id, memo, deleted_at)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+            id,
+            uuid,
+            row.amount,
+            row.kind,
+            budget_id,
+            row.day_of,
+            payee_id,
+            row.memo,
+            row.deleted_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        payments.insert(row.id, id);
+        restored.payments += 1;
+    }
+
+    for row in &backup.attachments {
+        let Some(payment_id) = payments.get(&row.payment_id) else {
+            continue;
+        };
+        let free = query_scalar!(
+            r#"SELECT NOT EXISTS (SELECT 1 FROM attachments WHERE uuid = ?) AS "free!: bool""#,
+            row.uuid
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let uuid = row.uuid.as_ref().filter(|_| free);
+        query!(
+            r#"INSERT INTO attachments (uuid, payment_id, name, hash, added_at)
+               VALUES (?, ?, ?, ?, ?)"#,
+            uuid,
+            payment_id,
+            row.name,
+            row.hash,
+            row.added_at
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(restored)
+}
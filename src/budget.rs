@@ -0,0 +1,96 @@
+use serde::Serialize;
+use sqlx::{query, query_as, query_scalar, Pool, Sqlite};
+
+use crate::{doctor, Error, Result};
+
+#[derive(Debug, Clone, Default, Serialize, sqlx::FromRow)]
+pub struct Budget {
+    pub id: i64,
+    /// Stays the same in every database the budget is backed up or synced to
+    pub uuid: String,
+    pub amount: f64,
+    pub month: String,
+}
+
+/// Every budget that isn't in the trash
+pub async fn list(pool: &Pool<Sqlite>) -> Result<Vec<Budget>> {
+    let budgets = query_as!(
+        Budget,
+        r#"SELECT id, uuid AS "uuid!", amount, month FROM budget WHERE deleted_at IS NULL"#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(budgets)
+}
+
+pub async fn get(pool: &Pool<Sqlite>, id: i64) -> Result<Option<Budget>> {
+    let budget = query_as!(
+        Budget,
+        r#"SELECT id, uuid AS "uuid!", amount, month FROM budget
+           WHERE id = ? AND deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(budget)
+}
+
+/// The id of the budget with `uuid`, even if it's in the trash
+pub async fn id_of(pool: &Pool<Sqlite>, uuid: &str) -> Result<Option<i64>> {
+    let id = query_scalar!(r#"SELECT id AS "id!" FROM budget WHERE uuid = ?"#, uuid)
+        .fetch_optional(pool)
+        .await?;
+    Ok(id)
+}
+
+/// Reads a month in any of the spellings `doctor` repairs, for every storage backend
+pub fn parse_month(month: &str) -> Result<String> {
+    doctor::normalize_month(month)
+        .ok_or_else(|| Error::Invalid(format!("{month:?} isn't a month, expected YYYY-MM")))
+}
+
+/// Creates the budget for `month`, which must not be taken by another budget, trashed or not
+pub async fn create(pool: &Pool<Sqlite>, amount: f64, month: &str) -> Result<i64> {
+    let month = parse_month(month)?;
+    let existing = query!(
+        r#"SELECT id AS "id!", deleted_at FROM budget WHERE month = ?"#,
+        month
+    )
+    .fetch_optional(pool)
+    .await?;
+    match existing {
+        Some(x) if x.deleted_at.is_some() => return Err(Error::TrashedMonth { month, id: x.id }),
+        Some(x) => return Err(Error::DuplicateMonth { month, id: x.id }),
+        None => {}
+    }
+
+    let id = query!("INSERT INTO budget (amount, month) VALUES (?, ?)", amount, month)
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+    Ok(id)
+}
+
+/// Returns the id of the budget for `month`, creating it with `amount` if there isn't one.
+/// Fails with [`Error::TrashedMonth`] when the month's budget is in the trash
+pub async fn find_or_create(pool: &Pool<Sqlite>, month: &str, amount: f64) -> Result<i64> {
+    let month = parse_month(month)?;
+    query!(
+        "INSERT OR IGNORE INTO budget (amount, month) VALUES (?, ?)",
+        amount,
+        month
+    )
+    .execute(pool)
+    .await?;
+
+    let budget = query!(
+        r#"SELECT id AS "id!", deleted_at FROM budget WHERE month = ?"#,
+        month
+    )
+    .fetch_one(pool)
+    .await?;
+    match budget.deleted_at {
+        Some(_) => Err(Error::TrashedMonth { month, id: budget.id }),
+        None => Ok(budget.id),
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Days, Local, NaiveDate, NaiveDateTime, Weekday};
+use dirs::{config_dir, data_dir, home_dir};
+use serde::{Deserialize, Serialize};
+
+use crate::keymap::Keymap;
+use crate::theme::Theme;
+
+/// A named database, so that separate sets of books can live side by side
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub db: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    Before,
+    After,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Currency {
+    pub symbol: String,
+    pub placement: Placement,
+}
+
+impl Default for Currency {
+    fn default() -> Currency {
+        Currency {
+            symbol: "$".to_string(),
+            placement: Placement::After,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Display {
+    pub decimals: usize,
+    pub decimal_separator: String,
+    pub thousands_separator: String,
+    /// strftime-style format used for payment dates
+    pub date_format: String,
+    pub first_day_of_week: Weekday,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display {
+            decimals: 2,
+            decimal_separator: ".".to_string(),
+            thousands_separator: String::new(),
+            date_format: "%Y-%m-%d %H:%M:%S".to_string(),
+            first_day_of_week: Weekday::Mon,
+        }
+    }
+}
+
+/// What running `budge` without a subcommand does
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Startup {
+    #[default]
+    Help,
+    /// Open the UI on this month's budget, creating it if it doesn't exist yet
+    CurrentMonth,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Defaults {
+    /// Amount for budgets that budge creates on its own, or when none is entered
+    pub budget_amount: f64,
+    pub startup: Startup,
+}
+
+impl Default for Defaults {
+    fn default() -> Defaults {
+        Defaults {
+            budget_amount: 1000.0,
+            startup: Startup::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Snapshots {
+    /// How many copies of the database to keep in `budge-snapshots`, 0 turns them off
+    pub keep: usize,
+}
+
+impl Default for Snapshots {
+    fn default() -> Snapshots {
+        Snapshots { keep: 10 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Encryption {
+    /// File holding the passphrase of an encrypted database, read instead of asking for it
+    pub key_file: Option<PathBuf>,
+}
+
+/// Everything read from `config.toml` in the budge config directory
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Profile used when no `--profile` is given
+    pub default_profile: Option<String>,
+    pub currency: Currency,
+    pub display: Display,
+    pub defaults: Defaults,
+    /// One of the built in themes or a name from `[themes]`, dark when unset
+    pub theme: Option<String>,
+    /// User themes, such as `[themes.mine]` with `base = "light"` and `border = "cyan"`
+    pub themes: BTreeMap<String, BTreeMap<String, String>>,
+    /// Extra key bindings per scope, such as `[keys.normal]` with `"C-n" = "scroll-down"`
+    pub keys: BTreeMap<String, BTreeMap<String, String>>,
+    pub profiles: BTreeMap<String, Profile>,
+    /// Accounts and commodity for `budge export`
+    pub export: budge::export::Options,
+    pub snapshots: Snapshots,
+    pub encryption: Encryption,
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let mut path = config_dir().ok_or("couldn't find a config directory")?;
+        path.push("budge");
+        path.push("config.toml");
+        Ok(path)
+    }
+
+    /// Reads the config file, falling back to the defaults when there isn't one
+    pub fn load() -> Result<Config, Box<dyn std::error::Error>> {
+        let path = Config::path()?;
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = std::fs::read_to_string(&path)?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("couldn't read {}: {e}", path.to_string_lossy()).into())
+    }
+
+    /// Sets a single, possibly dotted, key in the config file such as `currency.symbol`.
+    /// The value is read as TOML when possible and as a plain string otherwise
+    pub fn set(key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Config::path()?;
+        let mut table: toml::Table = match path.exists() {
+            true => toml::from_str(&std::fs::read_to_string(&path)?)?,
+            false => toml::Table::new(),
+        };
+        let value = toml::from_str::<toml::Table>(&format!("value = {value}"))
+            .ok()
+            .and_then(|mut x| x.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+        let mut keys: Vec<&str> = key.split('.').collect();
+        let last = keys.pop().ok_or("empty key")?;
+        let mut current = &mut table;
+        for key in keys {
+            current = current
+                .entry(key)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| format!("{key} isn't a table"))?;
+        }
+        current.insert(last.to_string(), value);
+
+        // Make sure the result still makes sense before writing it
+        let contents = toml::to_string_pretty(&table)?;
+        let config = toml::from_str::<Config>(&contents)
+            .map_err(|e| format!("invalid value for {key}: {e}"))?;
+        config.keymap().map_err(|e| format!("invalid value for {key}: {e}"))?;
+        config.theme().map_err(|e| format!("invalid value for {key}: {e}"))?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)?;
+        Ok(())
+    }
+
+    /// Builds the key bindings, with the configured ones on top of the defaults
+    pub fn keymap(&self) -> Result<Keymap, Box<dyn std::error::Error>> {
+        Keymap::from_config(&self.keys)
+    }
+
+    /// Builds the configured colour theme
+    pub fn theme(&self) -> Result<Theme, Box<dyn std::error::Error>> {
+        Theme::from_config(self.theme.as_deref().unwrap_or("dark"), &self.themes)
+    }
+
+    /// Formats an amount with the configured separators and currency symbol
+    pub fn money(&self, amount: f64) -> String {
+        let display = &self.display;
+        let formatted = format!("{:.*}", display.decimals, amount.abs());
+        let (whole, fraction) = match formatted.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (formatted.as_str(), None),
+        };
+
+        let mut grouped = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push_str(&display.thousands_separator);
+            }
+            grouped.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            grouped.push_str(&display.decimal_separator);
+            grouped.push_str(fraction);
+        }
+
+        let sign = if amount < 0.0 { "-" } else { "" };
+        match self.currency.placement {
+            Placement::Before => format!("{sign}{}{grouped}", self.currency.symbol),
+            Placement::After => format!("{sign}{grouped} {}", self.currency.symbol),
+        }
+    }
+
+    /// Formats a stored `day_of` timestamp, leaving it untouched if it can't be read
+    pub fn date(&self, day_of: &str) -> String {
+        let Ok(date) = NaiveDateTime::parse_from_str(day_of, "%Y-%m-%d %H:%M:%S") else {
+            return day_of.to_string();
+        };
+        let mut formatted = String::new();
+        match write!(formatted, "{}", date.format(&self.display.date_format)) {
+            Ok(_) => formatted,
+            Err(_) => day_of.to_string(),
+        }
+    }
+
+    /// The first and last day of the week containing `day`
+    pub fn week_of(&self, day: NaiveDate) -> (NaiveDate, NaiveDate) {
+        let start = day.week(self.display.first_day_of_week).first_day();
+        (start, start + Days::new(6))
+    }
+
+    /// This month in the `YYYY-MM` format budgets use
+    pub fn current_month() -> String {
+        let today = Local::now().date_naive();
+        format!("{:04}-{:02}", today.year(), today.month())
+    }
+
+    /// The database used when no profile or path is given
+    pub fn default_db() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let mut path = data_dir().ok_or("couldn't find a data directory, pass --db or set BUDGE_DB")?;
+        path.push("budge.db");
+        Ok(path)
+    }
+
+    /// Picks the database to open. An explicit `--db` wins, then `--profile`, then `BUDGE_DB`,
+    /// then the configured default profile and finally `budge.db` in the data directory
+    pub fn resolve_db(
+        &self,
+        db: Option<PathBuf>,
+        profile: Option<&str>,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        if let Some(db) = db {
+            return Ok(expand_home(&db));
+        }
+        if let Some(name) = profile {
+            return self.profile_db(name);
+        }
+        if let Some(db) = std::env::var_os("BUDGE_DB").filter(|x| !x.is_empty()) {
+            return Ok(expand_home(Path::new(&db)));
+        }
+        if let Some(name) = &self.default_profile {
+            return self.profile_db(name);
+        }
+        Config::default_db()
+    }
+
+    fn profile_db(&self, name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        match self.profiles.get(name) {
+            Some(profile) => Ok(expand_home(&profile.db)),
+            None => Err(format!("no profile named {name:?} in the config file").into()),
+        }
+    }
+}
+
+/// Expands a leading `~` so paths in the config file can be written the way they are in a shell
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        (_, _) => path.to_path_buf(),
+    }
+}
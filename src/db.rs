@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{migrate, query_scalar, Pool, Sqlite, SqlitePool};
+
+use crate::Result;
+
+pub type DbPool = Pool<Sqlite>;
+
+/// Opens the database, creating it if needed, and brings its schema up to date
+pub async fn connect(options: &str) -> Result<DbPool> {
+    open(options.parse()?).await
+}
+
+/// Like [`connect`], for a database encrypted with SQLCipher
+#[cfg(feature = "sqlcipher")]
+pub async fn connect_with_key(options: &str, key: &str) -> Result<DbPool> {
+    let db_opts: SqliteConnectOptions = options.parse()?;
+    open(db_opts.pragma("key", crate::encryption::literal(key)))
+        .await
+        .map_err(crate::encryption::wrong_key)
+}
+
+async fn open(db_opts: SqliteConnectOptions) -> Result<DbPool> {
+    // Without this, SQLite ignores the `ON DELETE CASCADE` and `REFERENCES` in the migrations
+    let pool = SqlitePool::connect_with(db_opts.create_if_missing(true).foreign_keys(true)).await?;
+
+    migrate!().run(&pool).await?;
+
+    Ok(pool)
+}
+
+/// Changes whenever another connection writes to the database
+pub async fn data_version(conn: &mut PoolConnection<Sqlite>) -> Result<i64> {
+    let version = query_scalar!(r#"PRAGMA data_version"#)
+        .fetch_one(&mut **conn)
+        .await?;
+    Ok(version.unwrap_or_default())
+}
+
+/// Removes the write-ahead log and shared memory files a database left behind, after it was
+/// replaced by another file
+pub(crate) fn remove_leftovers(db_path: &Path) -> Result<()> {
+    for suffix in ["-wal", "-shm"] {
+        let mut leftover = db_path.as_os_str().to_owned();
+        leftover.push(suffix);
+        if Path::new(&leftover).exists() {
+            std::fs::remove_file(leftover)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use sqlx::{query, query_as, Pool, Sqlite};
+
+use crate::Result;
+
+pub struct Problem {
+    pub description: String,
+    pub repairable: bool,
+}
+
+impl Problem {
+    fn new(description: String) -> Problem {
+        Problem {
+            description,
+            repairable: true,
+        }
+    }
+}
+
+struct MonthRow {
+    id: i64,
+    month: String,
+}
+
+struct DateRow {
+    id: i64,
+    day_of: String,
+    normalized: Option<String>,
+}
+
+/// Turns the month spellings older versions let through (`202501`, `2025-1`, `2025/01`)
+/// into the `YYYY-MM` form everything else expects
+pub fn normalize_month(month: &str) -> Option<String> {
+    let month = month.trim();
+    let (year, mon) = match month.split_once(['-', '/']) {
+        Some((year, mon)) => (year, mon),
+        None if month.len() == 6 && month.is_char_boundary(4) => month.split_at(4),
+        None => return None,
+    };
+    let year: u32 = year.parse().ok().filter(|_| year.len() == 4)?;
+    let mon: u32 = mon.parse().ok().filter(|m| (1..=12).contains(m))?;
+    Some(format!("{year:04}-{mon:02}"))
+}
+
+/// Looks for integrity problems, repairing what it can when `fix` is set
+pub async fn check(pool: &Pool<Sqlite>, fix: bool) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    let mut tx = pool.begin().await?;
+
+    let orphans = query!(
+        r#"SELECT id AS "id!", budget_id FROM payments
+           WHERE budget_id NOT IN (SELECT id FROM budget)"#
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for orphan in orphans {
+        problems.push(Problem::new(format!(
+            "payment {} belongs to budget {}, which doesn't exist",
+            orphan.id, orphan.budget_id
+        )));
+        if fix {
+            query!("DELETE FROM payments WHERE id = ?", orphan.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    let dangling_payees = query!(
+        r#"SELECT id AS "id!", payee_id AS "payee_id!" FROM payments
+           WHERE payee_id IS NOT NULL AND payee_id NOT IN (SELECT id FROM payees)"#
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for payment in dangling_payees {
+        problems.push(Problem::new(format!(
+            "payment {} refers to payee {}, which doesn't exist",
+            payment.id, payment.payee_id
+        )));
+        if fix {
+            query!("UPDATE payments SET payee_id = NULL WHERE id = ?", payment.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    let orphan_attachments = query!(
+        r#"SELECT id AS "id!", name FROM attachments
+           WHERE payment_id NOT IN (SELECT id FROM payments)"#
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for attachment in orphan_attachments {
+        problems.push(Problem::new(format!(
+            "attachment {} ({}) belongs to a payment that doesn't exist",
+            attachment.id, attachment.name
+        )));
+        if fix {
+            query!("DELETE FROM attachments WHERE id = ?", attachment.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    let budgets = query_as!(MonthRow, "SELECT id, month FROM budget ORDER BY id")
+        .fetch_all(&mut *tx)
+        .await?;
+    let mut by_month: HashMap<String, i64> = budgets
+        .iter()
+        .filter(|x| normalize_month(&x.month).as_deref() == Some(x.month.as_str()))
+        .map(|x| (x.month.clone(), x.id))
+        .collect();
+    for budget in budgets {
+        let normalized = normalize_month(&budget.month);
+        if normalized.as_deref() == Some(budget.month.as_str()) {
+            continue;
+        }
+        match normalized {
+            None => problems.push(Problem {
+                description: format!(
+                    "budget {} has month {:?}, which isn't in YYYY-MM format and can't be repaired",
+                    budget.id, budget.month
+                ),
+                repairable: false,
+            }),
+            Some(month) => match by_month.get(&month) {
+                Some(&keep) => {
+                    problems.push(Problem::new(format!(
+                        "budget {} ({}) duplicates budget {keep} ({month}), merging its payments",
+                        budget.id, budget.month
+                    )));
+                    if fix {
+                        query!(
+                            "UPDATE payments SET budget_id = ? WHERE budget_id = ?",
+                            keep,
+                            budget.id
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                        query!("DELETE FROM budget WHERE id = ?", budget.id)
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                }
+                None => {
+                    problems.push(Problem::new(format!(
+                        "budget {} has month {:?}, should be {month}",
+                        budget.id, budget.month
+                    )));
+                    if fix {
+                        query!("UPDATE budget SET month = ? WHERE id = ?", month, budget.id)
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                    by_month.insert(month, budget.id);
+                }
+            },
+        }
+    }
+
+    let dates = query_as!(
+        DateRow,
+        r#"SELECT id AS "id!", day_of, datetime(day_of) AS "normalized?: String" FROM payments
+           WHERE datetime(day_of) IS NULL OR datetime(day_of) != day_of"#
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for date in dates {
+        match &date.normalized {
+            Some(normalized) => problems.push(Problem::new(format!(
+                "payment {} has date {:?}, should be {normalized}",
+                date.id, date.day_of
+            ))),
+            None => problems.push(Problem::new(format!(
+                "payment {} has date {:?}, which isn't a date, moving it to the start of its month",
+                date.id, date.day_of
+            ))),
+        }
+        if fix {
+            query!(
+                r#"UPDATE payments SET day_of = COALESCE(
+                       datetime(day_of),
+                       (SELECT datetime(month || '-01') FROM budget WHERE budget.id = payments.budget_id),
+                       datetime('now'))
+                   WHERE id = ?"#,
+                date.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(problems)
+}
@@ -0,0 +1,81 @@
+use std::io::Read;
+use std::path::Path;
+
+#[cfg(feature = "sqlcipher")]
+use sqlx::{Pool, Sqlite};
+
+use crate::Result;
+#[cfg(feature = "sqlcipher")]
+use crate::{db, Error};
+
+/// How every unencrypted SQLite database starts. SQLCipher encrypts the header too
+const HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Whether the database at `path` is encrypted. Missing and empty files aren't
+pub fn is_encrypted(path: &Path) -> Result<bool> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let mut header = [0; HEADER.len()];
+    let read = file.take(HEADER.len() as u64).read(&mut header)?;
+    Ok(read > 0 && header[..read] != HEADER[..read])
+}
+
+/// A passphrase as an SQL string, which is how `PRAGMA key` takes it
+#[cfg(feature = "sqlcipher")]
+pub(crate) fn literal(key: &str) -> String {
+    format!("'{}'", key.replace('\'', "''"))
+}
+
+/// SQLCipher can't tell a wrong passphrase from a file that isn't a database at all
+#[cfg(feature = "sqlcipher")]
+pub(crate) fn wrong_key(e: Error) -> Error {
+    let not_a_database = |e: &sqlx::Error| matches!(e, sqlx::Error::Database(x) if x.code().as_deref() == Some("26"));
+    match &e {
+        Error::Database(x) | Error::Migrate(sqlx::migrate::MigrateError::Execute(x))
+            if not_a_database(x) =>
+        {
+            Error::Invalid("the passphrase doesn't open this database".to_string())
+        }
+        _ => e,
+    }
+}
+
+/// Writes the whole database out again, encrypted with `key`, or unencrypted when `key` is
+/// empty, and puts the copy in place of the database. This encrypts, rekeys and decrypts alike.
+/// The database is only replaced once the copy is complete. Nothing else should have it open
+#[cfg(feature = "sqlcipher")]
+pub async fn rewrite(pool: Pool<Sqlite>, db_path: &Path, key: &str) -> Result<()> {
+    let mut copy = db_path.as_os_str().to_owned();
+    copy.push(".rewrite");
+    let copy = Path::new(&copy);
+    if copy.exists() {
+        std::fs::remove_file(copy)?;
+    }
+
+    let mut conn = pool.acquire().await?;
+    sqlx::query("ATTACH DATABASE ? AS rewritten KEY ?")
+        .bind(copy.to_string_lossy())
+        .bind(key)
+        .execute(&mut *conn)
+        .await?;
+    let exported = sqlx::query("SELECT sqlcipher_export('rewritten')")
+        .execute(&mut *conn)
+        .await;
+    sqlx::query("DETACH DATABASE rewritten")
+        .execute(&mut *conn)
+        .await?;
+    if let Err(e) = exported {
+        std::fs::remove_file(copy)?;
+        return Err(e.into());
+    }
+    drop(conn);
+
+    // Closing the last connection folds the write-ahead log back into the database, which the
+    // copy already has
+    pool.close().await;
+    std::fs::rename(copy, db_path)?;
+    db::remove_leftovers(db_path)
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+
+/// Everything that can go wrong inside the library
+#[derive(Debug)]
+pub enum Error {
+    Database(sqlx::Error),
+    Migrate(sqlx::migrate::MigrateError),
+    Io(std::io::Error),
+    /// A budget or payment that doesn't exist, or only exists in the trash
+    NotFound { record: &'static str, id: i64 },
+    /// No budget or payment has the uuid, not even in the trash
+    UnknownUuid { record: &'static str, uuid: String },
+    /// The month already belongs to a budget
+    DuplicateMonth { month: String, id: i64 },
+    /// The month already belongs to a budget in the trash
+    TrashedMonth { month: String, id: i64 },
+    /// A value that can't be stored, such as a blank kind or a malformed month
+    Invalid(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Database(e) => write!(f, "database error: {e}"),
+            Error::Migrate(e) => write!(f, "couldn't migrate the database: {e}"),
+            Error::Io(e) => write!(f, "{e}"),
+            Error::NotFound { record, id } => write!(f, "no {record} with id {id}"),
+            Error::UnknownUuid { record, uuid } => write!(f, "no {record} with uuid {uuid}"),
+            Error::DuplicateMonth { month, id } => {
+                write!(f, "there's already a budget for {month}, with id {id}")
+            }
+            Error::TrashedMonth { month, id } => write!(
+                f,
+                "budget {id} for {month} is in the trash, restore it with `budge trash restore budget {id}` or purge it first"
+            ),
+            Error::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database(e) => Some(e),
+            Error::Migrate(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Error {
+        Error::Database(e)
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for Error {
+    fn from(e: sqlx::migrate::MigrateError) -> Error {
+        Error::Migrate(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::filter::{Filters, SortKey};
+use crate::{budget, Payment, Result, Storage};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ledger,
+    Hledger,
+    Beancount,
+}
+
+/// How payments turn into accounts, read from `[export]` in the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Options {
+    /// Pays for every expense and receives every income
+    pub asset_account: String,
+    /// Written after every amount. Beancount needs an uppercase code such as `USD`
+    pub commodity: String,
+    /// Accounts for particular kinds, such as `groceries = "Expenses:Food:Groceries"`. Other
+    /// kinds go to `Expenses:<Kind>`, or `Income:<Kind>` for money coming in
+    pub accounts: BTreeMap<String, String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            asset_account: "Assets:Checking".to_string(),
+            commodity: "USD".to_string(),
+            accounts: BTreeMap::new(),
+        }
+    }
+}
+
+impl Options {
+    /// The account a payment of `kind` is booked against
+    pub fn account(&self, kind: &str, amount: f64) -> String {
+        if let Some((_, account)) = self
+            .accounts
+            .iter()
+            .find(|(x, _)| x.eq_ignore_ascii_case(kind.trim()))
+        {
+            return account.clone();
+        }
+        let root = if amount < 0.0 { "Income" } else { "Expenses" };
+        let words: Vec<String> = kind
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|x| !x.is_empty())
+            .map(capitalize)
+            .collect();
+        match words.is_empty() {
+            true => format!("{root}:Uncategorized"),
+            false => format!("{root}:{}", words.join("-")),
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The shortest decimal that reads back as exactly the stored amount, with at least two
+/// decimals so the columns look like money
+pub fn amount(value: f64) -> String {
+    let value = if value == 0.0 { 0.0 } else { value };
+    let digits = value.to_string();
+    match digits.split_once('.') {
+        Some((_, fraction)) if fraction.len() >= 2 => digits,
+        Some(_) => format!("{digits}0"),
+        None => format!("{digits}.00"),
+    }
+}
+
+/// Keeps free text on a single line
+fn line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A description that starts with a parenthesis would be read as a transaction code, so those
+/// get an empty code in front
+fn described(text: &str) -> String {
+    match text.starts_with('(') {
+        true => format!("() {text}"),
+        false => text.to_string(),
+    }
+}
+
+fn quoted(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        line(text).replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Every payment in the budgets from `from` to `to`, both `YYYY-MM` and inclusive, as a journal
+/// of balanced transactions, oldest first
+pub async fn export(
+    storage: &dyn Storage,
+    format: Format,
+    from: Option<&str>,
+    to: Option<&str>,
+    options: &Options,
+) -> Result<String> {
+    let from = from.map(budget::parse_month).transpose()?;
+    let to = to.map(budget::parse_month).transpose()?;
+    let mut payments = Vec::new();
+    for budget in storage.budgets().await? {
+        if from.as_ref().is_some_and(|x| budget.month < *x)
+            || to.as_ref().is_some_and(|x| budget.month > *x)
+        {
+            continue;
+        }
+        payments.extend(
+            storage
+                .payments(budget.id, &Filters::default(), SortKey::Id, false)
+                .await?,
+        );
+    }
+    payments.sort_by(|a, b| a.day_of.cmp(&b.day_of).then(a.id.cmp(&b.id)));
+    Ok(write(format, &payments, options))
+}
+
+/// Formats payments that were already picked and sorted
+pub fn write(format: Format, payments: &[Payment], options: &Options) -> String {
+    let mut out = String::new();
+    let commodity = &options.commodity;
+    let accounts: BTreeSet<String> = payments
+        .iter()
+        .map(|x| options.account(&x.kind, x.amount))
+        .chain([options.asset_account.clone()])
+        .collect();
+
+    // `write!` to a String can't fail
+    let _ = writeln!(out, "; Exported by budge\n");
+    match format {
+        Format::Ledger | Format::Hledger => {
+            let _ = writeln!(out, "commodity {commodity}");
+            for account in &accounts {
+                let _ = writeln!(out, "account {account}");
+            }
+        }
+        Format::Beancount => {
+            let _ = writeln!(out, "option \"operating_currency\" \"{commodity}\"");
+            let opened = payments.first().map(date).unwrap_or("1970-01-01");
+            for account in &accounts {
+                let _ = writeln!(out, "{opened} open {account} {commodity}");
+            }
+        }
+    }
+
+    for payment in payments {
+        let _ = writeln!(out);
+        let kind = line(&payment.kind);
+        let payee = payment.payee.as_deref().map(line);
+        match format {
+            Format::Ledger => {
+                let description = payee.as_deref().unwrap_or(&kind);
+                let _ = writeln!(out, "{} * {}", date(payment), described(description));
+                let _ = writeln!(out, "    ; budge-id: {}", payment.id);
+                let _ = writeln!(out, "    ; budge-uuid: {}", payment.uuid);
+                if payee.is_some() {
+                    let _ = writeln!(out, "    ; kind: {kind}");
+                }
+            }
+            Format::Hledger => {
+                let description = match &payee {
+                    Some(payee) => format!("{payee} | {kind}"),
+                    None => kind.clone(),
+                };
+                let _ = writeln!(out, "{} * {}", date(payment), described(&description));
+                let _ = writeln!(out, "    ; budge-id: {}", payment.id);
+                let _ = writeln!(out, "    ; budge-uuid: {}", payment.uuid);
+            }
+            Format::Beancount => {
+                let strings = match &payee {
+                    Some(payee) => format!("{} {}", quoted(payee), quoted(&kind)),
+                    None => quoted(&kind),
+                };
+                let _ = writeln!(out, "{} * {strings}", date(payment));
+                let _ = writeln!(out, "  budge-id: {}", payment.id);
+                let _ = writeln!(out, "  budge-uuid: {}", quoted(&payment.uuid));
+            }
+        }
+        if let Some(memo) = payment.memo.as_deref().filter(|x| !x.trim().is_empty()) {
+            let _ = match format {
+                Format::Beancount => writeln!(out, "  memo: {}", quoted(memo)),
+                _ => writeln!(out, "    ; memo: {}", line(memo)),
+            };
+        }
+        let indent = if format == Format::Beancount {
+            "  "
+        } else {
+            "    "
+        };
+        let category = options.account(&payment.kind, payment.amount);
+        let _ = writeln!(
+            out,
+            "{indent}{category}  {} {commodity}",
+            amount(payment.amount)
+        );
+        let _ = writeln!(
+            out,
+            "{indent}{}  {} {commodity}",
+            options.asset_account,
+            amount(-payment.amount)
+        );
+    }
+    out
+}
+
+fn date(payment: &Payment) -> &str {
+    payment.day_of.get(..10).unwrap_or(&payment.day_of)
+}
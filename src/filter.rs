@@ -0,0 +1,98 @@
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Date,
+    Amount,
+    Kind,
+    Id,
+}
+
+impl SortKey {
+    pub fn next(self) -> SortKey {
+        match self {
+            SortKey::Date => SortKey::Amount,
+            SortKey::Amount => SortKey::Kind,
+            SortKey::Kind => SortKey::Id,
+            SortKey::Id => SortKey::Date,
+        }
+    }
+    /// The name the payments query switches on
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortKey::Date => "date",
+            SortKey::Amount => "amount",
+            SortKey::Kind => "kind",
+            SortKey::Id => "id",
+        }
+    }
+}
+
+/// Positive amounts are money spent, negative amounts are money coming in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flow {
+    #[default]
+    All,
+    Expenses,
+    Income,
+}
+
+impl Flow {
+    pub fn next(self) -> Flow {
+        match self {
+            Flow::All => Flow::Expenses,
+            Flow::Expenses => Flow::Income,
+            Flow::Income => Flow::All,
+        }
+    }
+    pub fn as_str(self) -> Option<&'static str> {
+        match self {
+            Flow::All => None,
+            Flow::Expenses => Some("expense"),
+            Flow::Income => Some("income"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub kind: Option<String>,
+    /// Inclusive, as `YYYY-MM-DD`
+    pub from: Option<String>,
+    /// Inclusive, as `YYYY-MM-DD`
+    pub to: Option<String>,
+    pub flow: Flow,
+}
+
+impl Filters {
+    /// Short labels for every active filter, shown in the payments table title
+    pub fn chips(&self) -> Vec<String> {
+        let mut chips = Vec::new();
+        match (self.min_amount, self.max_amount) {
+            (Some(min), Some(max)) => chips.push(format!("{min} ≤ amount ≤ {max}")),
+            (Some(min), None) => chips.push(format!("amount ≥ {min}")),
+            (None, Some(max)) => chips.push(format!("amount ≤ {max}")),
+            (None, None) => {}
+        }
+        if let Some(kind) = &self.kind {
+            chips.push(format!("kind: {kind}"));
+        }
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) => chips.push(format!("{from} → {to}")),
+            (Some(from), None) => chips.push(format!("since {from}")),
+            (None, Some(to)) => chips.push(format!("until {to}")),
+            (None, None) => {}
+        }
+        match self.flow {
+            Flow::All => {}
+            Flow::Expenses => chips.push("expenses".to_string()),
+            Flow::Income => chips.push("income".to_string()),
+        }
+        chips
+    }
+}
@@ -0,0 +1,622 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_scalar, Pool, Sqlite};
+
+use crate::{payment, Error, Result};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Also reads hledger journals
+    #[value(alias = "hledger")]
+    Ledger,
+    Beancount,
+}
+
+/// How accounts turn into kinds, read from the file given to `budge import --mapping`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Mapping {
+    /// Only amounts in this commodity, or without one, are imported. USD when unset
+    pub commodity: Option<String>,
+    /// Kinds for accounts and everything below them, such as `"Expenses:Food" = "groceries"`.
+    /// The longest match wins. Other accounts under `Expenses` or `Income` use their last part,
+    /// so `Expenses:Eating-Out` becomes `eating out`
+    pub kinds: BTreeMap<String, String>,
+}
+
+impl Mapping {
+    /// The kind for postings to `account`, or `None` for the accounts that money comes from,
+    /// such as assets and liabilities
+    pub fn kind(&self, account: &str) -> Option<String> {
+        let mapped = self
+            .kinds
+            .iter()
+            .filter(|(prefix, _)| {
+                account == *prefix
+                    || account
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|x| x.starts_with(':'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len());
+        if let Some((_, kind)) = mapped {
+            return Some(kind.clone());
+        }
+        let root = account.split(':').next()?;
+        if root != "Expenses" && root != "Income" {
+            return None;
+        }
+        let last = account.rsplit(':').next()?;
+        Some(last.replace(['-', '_'], " ").to_lowercase())
+    }
+}
+
+/// A payment read from a journal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Where the posting is in the journal, counting from 1
+    pub line: usize,
+    pub date: NaiveDate,
+    pub kind: String,
+    /// Positive for expenses, negative for income, as in the journal
+    pub amount: f64,
+    pub payee: Option<String>,
+    pub memo: Option<String>,
+    /// The `budge-uuid` of a payment that budge exported, which keeps it from being imported
+    /// twice
+    pub uuid: Option<String>,
+}
+
+/// A posting or directive that didn't become a payment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub payments: usize,
+    /// Budgets created for months that didn't have one
+    pub budgets: usize,
+    /// Payments left out because one with the same uuid is already in the database
+    pub existing: usize,
+    pub skipped: Vec<Skipped>,
+}
+
+#[derive(Debug, Default)]
+struct Transaction {
+    date: NaiveDate,
+    payee: Option<String>,
+    /// The payee is spelled out rather than guessed from a ledger description
+    certain: bool,
+    note: Option<String>,
+    memo: Option<String>,
+    uuid: Option<String>,
+    postings: Vec<Posting>,
+}
+
+#[derive(Debug)]
+struct Posting {
+    line: usize,
+    text: String,
+    account: String,
+    amount: Option<(f64, String)>,
+    problem: Option<String>,
+}
+
+/// Reads a journal, returning the payments in it and everything that had to be left out
+pub fn parse(format: Format, journal: &str, mapping: &Mapping) -> (Vec<Entry>, Vec<Skipped>) {
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    let transactions = match format {
+        Format::Ledger => ledger(journal, &mut skipped),
+        Format::Beancount => beancount(journal, &mut skipped),
+    };
+    for transaction in transactions {
+        translate(transaction, mapping, &mut entries, &mut skipped);
+    }
+    skipped.sort_by_key(|x| x.line);
+    (entries, skipped)
+}
+
+/// Adds the payments of a journal in a single transaction, creating a budget of
+/// `budget_amount` for every month that doesn't have one. Payments keep the uuids budge
+/// exported them with, and the ones already in the database are left out. Imported payments
+/// can't be undone with `budge undo`
+pub async fn import(
+    pool: &Pool<Sqlite>,
+    format: Format,
+    journal: &str,
+    mapping: &Mapping,
+    budget_amount: f64,
+) -> Result<Report> {
+    let (entries, skipped) = parse(format, journal, mapping);
+    let mut report = Report {
+        skipped,
+        ..Report::default()
+    };
+    let mut budgets = BTreeMap::new();
+    let mut tx = pool.begin().await?;
+    for entry in &entries {
+        if let Some(uuid) = &entry.uuid {
+            let existing = query_scalar!(r#"SELECT id AS "id!" FROM payments WHERE uuid = ?"#, uuid)
+                .fetch_optional(&mut *tx)
+                .await?;
+            if existing.is_some() {
+                report.existing += 1;
+                continue;
+            }
+        }
+        let month = entry.date.format("%Y-%m").to_string();
+        let budget_id = match budgets.get(&month) {
+            Some(id) => *id,
+            None => {
+                let existing = query!(
+                    r#"SELECT id AS "id!", deleted_at FROM budget WHERE month = ?"#,
+                    month
+                )
+                .fetch_optional(&mut *tx)
+                .await?;
+                let id = match existing {
+                    Some(x) if x.deleted_at.is_some() => {
+                        return Err(Error::TrashedMonth { month, id: x.id });
+                    }
+                    Some(x) => x.id,
+                    None => {
+                        report.budgets += 1;
+                        query!(
+                            "INSERT INTO budget (amount, month) VALUES (?, ?)",
+                            budget_amount,
+                            month
+                        )
+                        .execute(&mut *tx)
+                        .await?
+                        .last_insert_rowid()
+                    }
+                };
+                budgets.insert(month, id);
+                id
+            }
+        };
+        let payee_id = match &entry.payee {
+            Some(name) => {
+                query!("INSERT OR IGNORE INTO payees (name) VALUES (?)", name)
+                    .execute(&mut *tx)
+                    .await?;
+                query!(r#"SELECT id AS "id!" FROM payees WHERE name = ?"#, name)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map(|x| Some(x.id))?
+            }
+            None => None,
+        };
+        let day_of = entry.date.format("%Y-%m-%d 00:00:00").to_string();
+        query!(
+            r#"INSERT INTO payments (amount, budget_id, kind, day_of, payee_id, memo, uuid)
+               VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+            entry.amount,
+            budget_id,
+            entry.kind,
+            day_of,
+            payee_id,
+            entry.memo,
+            entry.uuid
+        )
+        .execute(&mut *tx)
+        .await?;
+        report.payments += 1;
+    }
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// Turns every posting to an expense or income account into a payment, paid for by the others
+fn translate(
+    mut transaction: Transaction,
+    mapping: &Mapping,
+    entries: &mut Vec<Entry>,
+    skipped: &mut Vec<Skipped>,
+) {
+    let commodity = mapping.commodity.as_deref().unwrap_or("USD");
+    let mut skip = |posting: &Posting, reason: &str| {
+        skipped.push(Skipped {
+            line: posting.line,
+            text: posting.text.clone(),
+            reason: reason.to_string(),
+        })
+    };
+
+    // One posting may leave out its amount, which is whatever balances the others
+    let open: Vec<usize> = (0..transaction.postings.len())
+        .filter(|x| {
+            let posting = &transaction.postings[*x];
+            posting.amount.is_none() && posting.problem.is_none()
+        })
+        .collect();
+    if let [index] = open[..] {
+        let mut sum = 0.0;
+        let mut units: Vec<&str> = Vec::new();
+        for (amount, unit) in transaction
+            .postings
+            .iter()
+            .filter(|x| x.problem.is_none())
+            .filter_map(|x| x.amount.as_ref())
+        {
+            sum += amount;
+            if !unit.is_empty() && !units.contains(&unit.as_str()) {
+                units.push(unit);
+            }
+        }
+        let filled = match units[..] {
+            [] => Ok((-sum, String::new())),
+            [unit] => Ok((-sum, unit.to_string())),
+            _ => Err("the others mix commodities".to_string()),
+        };
+        let posting = &mut transaction.postings[index];
+        match filled {
+            Ok(amount) => posting.amount = Some(amount),
+            Err(problem) => posting.problem = Some(problem),
+        }
+    } else {
+        for index in open {
+            let posting = &mut transaction.postings[index];
+            posting.problem = Some("more than one posting has no amount".to_string());
+        }
+    }
+
+    let uuid = transaction.uuid.take();
+    let categories: Vec<(&Posting, String)> = transaction
+        .postings
+        .iter()
+        .filter_map(|x| mapping.kind(&x.account).map(|kind| (x, kind)))
+        .collect();
+    // A uuid names a single payment, which budge's own exports always have
+    let uuid = uuid.filter(|_| categories.len() == 1);
+    if categories.is_empty() {
+        for posting in &transaction.postings {
+            skip(posting, "no expense or income account in this transaction");
+        }
+        return;
+    }
+
+    let same = |text: &Option<String>, kind: &str| {
+        text.as_deref()
+            .is_some_and(|x| x.eq_ignore_ascii_case(kind))
+    };
+    for (posting, kind) in categories {
+        if let Some(problem) = &posting.problem {
+            skip(posting, problem);
+            continue;
+        }
+        let Some((amount, of)) = &posting.amount else {
+            continue;
+        };
+        if !of.is_empty() && of != commodity {
+            skip(posting, &format!("in {of} rather than {commodity}"));
+            continue;
+        }
+        let kind = match payment::check(&kind, *amount) {
+            Ok(kind) => kind.to_string(),
+            Err(e) => {
+                skip(posting, &e.to_string());
+                continue;
+            }
+        };
+        let payee = match !transaction.certain && same(&transaction.payee, &kind) {
+            true => None,
+            false => transaction.payee.clone(),
+        };
+        let memo = match &transaction.memo {
+            Some(memo) => Some(memo.clone()),
+            None if same(&transaction.note, &kind) => None,
+            None => transaction.note.clone(),
+        };
+        entries.push(Entry {
+            line: posting.line,
+            date: transaction.date,
+            kind,
+            amount: *amount,
+            payee,
+            memo,
+            uuid: uuid.clone(),
+        });
+    }
+}
+
+/// Reads `2025-01-31`, `2025/1/31` or `2025.01.31`
+fn date(text: &str) -> Option<NaiveDate> {
+    let mut parts = text.split(['-', '/', '.']).map(|x| x.parse::<u32>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    NaiveDate::from_ymd_opt(year.try_into().ok()?, month, day)
+}
+
+/// Reads `42.50 USD`, `-$1,234.5`, `$-3` or `7`, returning the number and the commodity, which
+/// is empty when there isn't one. Commas are thousands separators
+fn amount(text: &str) -> Option<(f64, String)> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, text),
+    };
+    let start = text.find(|x: char| x.is_ascii_digit() || x == '-' || x == '+' || x == '.')?;
+    let end = text[start..]
+        .find(|x: char| !(x.is_ascii_digit() || ".,-+".contains(x)))
+        .map_or(text.len(), |x| start + x);
+    let number = text[start..end].replace(',', "");
+    let unit = format!("{} {}", &text[..start], &text[end..]);
+    let unit = unit.trim().trim_matches('"');
+    if unit.contains(char::is_whitespace) || !number.contains(|x: char| x.is_ascii_digit()) {
+        return None;
+    }
+    let value: f64 = number.parse().ok()?;
+    Some((if negative { -value } else { value }, unit.to_string()))
+}
+
+/// Splits a posting into its account and the rest, which needs two spaces or a tab in between
+fn split_posting(text: &str) -> (&str, &str) {
+    let end = [text.find("  "), text.find('\t')]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(text.len());
+    (&text[..end], text[end..].trim())
+}
+
+/// Reads the amount of a posting, leaving balance assertions aside
+fn posting(line: usize, text: &str, account: &str, rest: &str) -> Posting {
+    let rest = rest.split_once('=').map_or(rest, |(x, _)| x).trim();
+    let mut posting = Posting {
+        line,
+        text: text.to_string(),
+        account: account.to_string(),
+        amount: None,
+        problem: None,
+    };
+    if rest.contains(['@', '{']) {
+        posting.problem = Some("has a price or cost".to_string());
+    } else if !rest.is_empty() {
+        posting.amount = amount(rest);
+        if posting.amount.is_none() {
+            posting.problem = Some("can't read the amount".to_string());
+        }
+    }
+    posting
+}
+
+/// The uuid in a `budge-uuid` tag, spelled the way budge stores it
+fn parse_uuid(text: &str) -> Option<String> {
+    uuid::Uuid::try_parse(text).ok().map(|x| x.to_string())
+}
+
+/// Reads `key: value` from a comment
+fn tag(comment: &str) -> Option<(&str, &str)> {
+    let (key, value) = comment.trim().split_once(':')?;
+    let key = key.trim();
+    match !key.is_empty() && !key.contains(char::is_whitespace) {
+        true => Some((key, value.trim())),
+        false => None,
+    }
+}
+
+fn ledger(journal: &str, skipped: &mut Vec<Skipped>) -> Vec<Transaction> {
+    let mut transactions: Vec<Transaction> = Vec::new();
+    let mut current = false;
+    let mut block: Option<&str> = None;
+    for (index, text) in journal.lines().enumerate() {
+        let line = index + 1;
+        let mut skip = |reason: &str| {
+            skipped.push(Skipped {
+                line,
+                text: text.trim().to_string(),
+                reason: reason.to_string(),
+            })
+        };
+        if let Some(end) = block {
+            if text.trim() == end {
+                block = None;
+            }
+            continue;
+        }
+        if text.trim().is_empty() {
+            current = false;
+            continue;
+        }
+
+        if text.starts_with([' ', '\t']) {
+            let Some(transaction) = transactions.last_mut().filter(|_| current) else {
+                continue;
+            };
+            let trimmed = text.trim();
+            if let Some(comment) = trimmed.strip_prefix(';') {
+                if transaction.postings.is_empty() {
+                    match tag(comment) {
+                        Some(("memo", value)) => transaction.memo = Some(value.to_string()),
+                        Some(("kind", _)) => transaction.certain = true,
+                        Some(("budge-uuid", value)) => transaction.uuid = parse_uuid(value),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            let body = trimmed.split(';').next().unwrap_or_default().trim_end();
+            let body = body
+                .strip_prefix(['*', '!'])
+                .map_or(body, |x| x.trim_start());
+            let (account, rest) = split_posting(body);
+            if account.starts_with(['(', '[']) {
+                skip("virtual postings aren't imported");
+                continue;
+            }
+            transaction
+                .postings
+                .push(posting(line, trimmed, account, rest));
+            continue;
+        }
+
+        current = false;
+        let first = text.split_whitespace().next().unwrap_or_default();
+        match first {
+            "comment" | "test" => {
+                block = Some(if first == "comment" {
+                    "end comment"
+                } else {
+                    "end test"
+                })
+            }
+            "include" => skip("included files aren't read, import them on their own"),
+            _ if first.starts_with(['=', '~']) => {
+                skip("automated and periodic transactions aren't imported")
+            }
+            _ if first.starts_with(|x: char| x.is_ascii_digit()) => {
+                let Some(day) = date(first.split('=').next().unwrap_or_default()) else {
+                    skip("can't read the date");
+                    continue;
+                };
+                let rest = text[first.len()..].trim_start();
+                let rest = rest
+                    .strip_prefix(['*', '!'])
+                    .map_or(rest, |x| x.trim_start());
+                let rest = match rest.strip_prefix('(') {
+                    Some(code) => code.split_once(')').map_or("", |(_, x)| x.trim_start()),
+                    None => rest,
+                };
+                // hledger ends the description at any semicolon, ledger needs a space first
+                let description = rest.split(';').next().unwrap_or_default().trim();
+                let mut transaction = Transaction {
+                    date: day,
+                    ..Transaction::default()
+                };
+                match description.split_once('|') {
+                    Some((payee, note)) => {
+                        transaction.payee =
+                            Some(payee.trim().to_string()).filter(|x| !x.is_empty());
+                        transaction.note = Some(note.trim().to_string()).filter(|x| !x.is_empty());
+                        transaction.certain = true;
+                    }
+                    None => {
+                        transaction.payee = Some(description.to_string()).filter(|x| !x.is_empty())
+                    }
+                }
+                transactions.push(transaction);
+                current = true;
+            }
+            _ => {}
+        }
+    }
+    transactions
+}
+
+/// Reads the quoted strings at the start of `text`, undoing escapes, and returns them with
+/// whatever follows
+fn strings(mut text: &str) -> Option<(Vec<String>, &str)> {
+    let mut found = Vec::new();
+    while let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next()? {
+                (_, '\\') => value.push(chars.next()?.1),
+                (i, '"') => break i,
+                (_, x) => value.push(x),
+            }
+        };
+        found.push(value);
+        text = rest[end + 1..].trim_start();
+    }
+    Some((found, text))
+}
+
+fn beancount(journal: &str, skipped: &mut Vec<Skipped>) -> Vec<Transaction> {
+    let mut transactions: Vec<Transaction> = Vec::new();
+    let mut current = false;
+    for (index, text) in journal.lines().enumerate() {
+        let line = index + 1;
+        let mut skip = |reason: &str| {
+            skipped.push(Skipped {
+                line,
+                text: text.trim().to_string(),
+                reason: reason.to_string(),
+            })
+        };
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        if text.starts_with([' ', '\t']) {
+            let Some(transaction) = transactions.last_mut().filter(|_| current) else {
+                continue;
+            };
+            let trimmed = text.trim();
+            if trimmed.starts_with(';') {
+                continue;
+            }
+            let body = trimmed.split(';').next().unwrap_or_default().trim_end();
+            if body.starts_with(|x: char| x.is_ascii_lowercase()) {
+                if let Some((key, value)) = tag(body).filter(|_| transaction.postings.is_empty()) {
+                    let value = strings(value)
+                        .and_then(|(x, _)| x.into_iter().next())
+                        .unwrap_or_else(|| value.to_string());
+                    match key {
+                        "memo" => transaction.memo = Some(value),
+                        "kind" => transaction.certain = true,
+                        "budge-uuid" => transaction.uuid = parse_uuid(&value),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            let body = body
+                .strip_prefix(['*', '!'])
+                .map_or(body, |x| x.trim_start());
+            let (account, rest) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+            transaction
+                .postings
+                .push(posting(line, trimmed, account, rest));
+            continue;
+        }
+
+        current = false;
+        let mut words = text.split_whitespace();
+        let first = words.next().unwrap_or_default();
+        if first == "include" {
+            skip("included files aren't read, import them on their own");
+            continue;
+        }
+        let Some(day) = date(first).filter(|_| first.len() == 10) else {
+            continue;
+        };
+        if !matches!(words.next(), Some("*" | "!" | "txn")) {
+            continue;
+        }
+        let flag = text[first.len()..].trim_start();
+        let rest = flag[flag.find(char::is_whitespace).unwrap_or(flag.len())..].trim_start();
+        let Some((strings, _)) = strings(rest) else {
+            skip("can't read the payee and narration");
+            continue;
+        };
+        let mut transaction = Transaction {
+            date: day,
+            certain: true,
+            ..Transaction::default()
+        };
+        match &strings[..] {
+            [payee, note] => {
+                transaction.payee = Some(payee.clone()).filter(|x| !x.is_empty());
+                transaction.note = Some(note.clone()).filter(|x| !x.is_empty());
+            }
+            [note] => transaction.note = Some(note.clone()).filter(|x| !x.is_empty()),
+            _ => {}
+        }
+        transactions.push(transaction);
+        current = true;
+    }
+    transactions
+}
@@ -0,0 +1,256 @@
+use sqlx::{query, query_as, Pool, Sqlite, SqliteConnection};
+
+use crate::{trash, Error, Result};
+
+/// A single recorded change to a payment, along with everything needed to reverse it.
+/// `before_*` is filled in for edits and deletes, `after_*` for adds and edits
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    pub id: i64,
+    pub action: String,
+    pub payment_id: i64,
+    pub before_amount: Option<f64>,
+    pub before_kind: Option<String>,
+    pub before_budget_id: Option<i64>,
+    pub before_day_of: Option<String>,
+    pub before_payee_id: Option<i64>,
+    pub before_memo: Option<String>,
+    pub after_amount: Option<f64>,
+    pub after_kind: Option<String>,
+    pub after_budget_id: Option<i64>,
+    pub after_day_of: Option<String>,
+    pub after_payee_id: Option<i64>,
+    pub after_memo: Option<String>,
+    pub undone: i64,
+    pub created_at: String,
+    pub before_uuid: Option<String>,
+    pub after_uuid: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    /// Missing from entries recorded before the journal kept uuids
+    uuid: Option<String>,
+    amount: f64,
+    kind: String,
+    budget_id: i64,
+    day_of: String,
+    payee_id: Option<i64>,
+    memo: Option<String>,
+}
+
+impl Entry {
+    fn before(&self) -> Option<Snapshot> {
+        Some(Snapshot {
+            uuid: self.before_uuid.clone(),
+            amount: self.before_amount?,
+            kind: self.before_kind.clone()?,
+            budget_id: self.before_budget_id?,
+            day_of: self.before_day_of.clone()?,
+            payee_id: self.before_payee_id,
+            memo: self.before_memo.clone(),
+        })
+    }
+    fn after(&self) -> Option<Snapshot> {
+        Some(Snapshot {
+            uuid: self.after_uuid.clone(),
+            amount: self.after_amount?,
+            kind: self.after_kind.clone()?,
+            budget_id: self.after_budget_id?,
+            day_of: self.after_day_of.clone()?,
+            payee_id: self.after_payee_id,
+            memo: self.after_memo.clone(),
+        })
+    }
+    pub fn describe(&self) -> String {
+        format!("{} of payment {}", self.action, self.payment_id)
+    }
+}
+
+/// Recording something new makes everything that was undone unreachable
+async fn forget_redo(conn: &mut SqliteConnection) -> Result<()> {
+    query!("DELETE FROM journal WHERE undone = 1")
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Drops the entries of payments whose budget is gone for good, since they can't be brought
+/// back and would stop everything older from being undone
+pub(crate) async fn forget_purged(conn: &mut SqliteConnection) -> Result<()> {
+    query!(
+        r#"DELETE FROM journal
+           WHERE coalesce(before_budget_id, after_budget_id) NOT IN (SELECT id FROM budget)"#
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Call after the payment has been inserted, in the same transaction
+pub async fn record_add(conn: &mut SqliteConnection, payment_id: i64) -> Result<()> {
+    forget_redo(&mut *conn).await?;
+    query!(
+        r#"INSERT INTO journal (action, payment_id, after_uuid, after_amount, after_kind,
+                                after_budget_id, after_day_of, after_payee_id, after_memo)
+           SELECT 'add', id, uuid, amount, kind, budget_id, day_of, payee_id, memo
+           FROM payments WHERE id = ?"#,
+        payment_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Call before the payment is deleted, in the same transaction
+pub async fn record_delete(conn: &mut SqliteConnection, payment_id: i64) -> Result<()> {
+    forget_redo(&mut *conn).await?;
+    query!(
+        r#"INSERT INTO journal (action, payment_id, before_uuid, before_amount, before_kind,
+                                before_budget_id, before_day_of, before_payee_id, before_memo)
+           SELECT 'delete', id, uuid, amount, kind, budget_id, day_of, payee_id, memo
+           FROM payments WHERE id = ?"#,
+        payment_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Call before the payment is edited, then hand the returned id to `finish_edit` afterwards.
+/// All three belong in one transaction, so that an edit is never recorded only halfway
+pub async fn begin_edit(conn: &mut SqliteConnection, payment_id: i64) -> Result<i64> {
+    forget_redo(&mut *conn).await?;
+    let id = query!(
+        r#"INSERT INTO journal (action, payment_id, before_uuid, before_amount, before_kind,
+                                before_budget_id, before_day_of, before_payee_id, before_memo)
+           SELECT 'edit', id, uuid, amount, kind, budget_id, day_of, payee_id, memo
+           FROM payments WHERE id = ?"#,
+        payment_id
+    )
+    .execute(conn)
+    .await?
+    .last_insert_rowid();
+    Ok(id)
+}
+
+pub async fn finish_edit(conn: &mut SqliteConnection, journal_id: i64) -> Result<()> {
+    query!(
+        r#"UPDATE journal SET (after_uuid, after_amount, after_kind, after_budget_id,
+                               after_day_of, after_payee_id, after_memo) =
+               (SELECT uuid, amount, kind, budget_id, day_of, payee_id, memo
+                FROM payments WHERE payments.id = journal.payment_id)
+           WHERE id = ?"#,
+        journal_id
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Brings a payment back, whether it was only trashed or has since been purged. A purged one
+/// keeps its uuid, unless another payment has taken it in the meantime
+async fn restore(conn: &mut SqliteConnection, id: i64, snapshot: Snapshot) -> Result<()> {
+    query!(
+        r#"INSERT INTO payments (id, uuid, amount, kind, budget_id, day_of, payee_id, memo)
+           VALUES (?, (SELECT ? WHERE NOT EXISTS (SELECT 1 FROM payments WHERE uuid = ?)),
+                   ?, ?, ?, ?, ?, ?)
+           ON CONFLICT (id) DO UPDATE SET deleted_at = NULL"#,
+        id,
+        snapshot.uuid,
+        snapshot.uuid,
+        snapshot.amount,
+        snapshot.kind,
+        snapshot.budget_id,
+        snapshot.day_of,
+        snapshot.payee_id,
+        snapshot.memo
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn overwrite(conn: &mut SqliteConnection, id: i64, snapshot: Snapshot) -> Result<()> {
+    query!(
+        r#"UPDATE payments SET amount = ?, kind = ?, budget_id = ?, day_of = ?, payee_id = ?, memo = ?
+           WHERE id = ?"#,
+        snapshot.amount,
+        snapshot.kind,
+        snapshot.budget_id,
+        snapshot.day_of,
+        snapshot.payee_id,
+        snapshot.memo,
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn remove(conn: &mut SqliteConnection, id: i64) -> Result<()> {
+    query!("DELETE FROM payments WHERE id = ?", id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Reverses the most recent change that hasn't been undone yet, returning it
+pub async fn undo(pool: &Pool<Sqlite>) -> Result<Option<Entry>> {
+    let mut tx = pool.begin().await?;
+    // Budgets also go for good through sync and the doctor
+    forget_purged(&mut tx).await?;
+    let Some(entry) = query_as!(
+        Entry,
+        "SELECT * FROM journal WHERE undone = 0 ORDER BY id DESC LIMIT 1"
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    match (entry.action.as_str(), entry.before()) {
+        ("add", _) => remove(&mut tx, entry.payment_id).await?,
+        ("delete", Some(before)) => restore(&mut tx, entry.payment_id, before).await?,
+        ("edit", Some(before)) => overwrite(&mut tx, entry.payment_id, before).await?,
+        (_, _) => return Err(Error::Invalid(format!("journal entry {} is incomplete", entry.id))),
+    }
+    query!("UPDATE journal SET undone = 1 WHERE id = ?", entry.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Some(entry))
+}
+
+/// Re-applies the change that was undone most recently, returning it
+pub async fn redo(pool: &Pool<Sqlite>) -> Result<Option<Entry>> {
+    let mut tx = pool.begin().await?;
+    forget_purged(&mut tx).await?;
+    let Some(entry) = query_as!(
+        Entry,
+        "SELECT * FROM journal WHERE undone = 1 ORDER BY id ASC LIMIT 1"
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    match (entry.action.as_str(), entry.after()) {
+        ("add", Some(after)) => restore(&mut tx, entry.payment_id, after).await?,
+        ("delete", _) => {
+            trash::trash_payment(&mut tx, entry.payment_id).await?;
+        }
+        ("edit", Some(after)) => overwrite(&mut tx, entry.payment_id, after).await?,
+        (_, _) => return Err(Error::Invalid(format!("journal entry {} is incomplete", entry.id))),
+    }
+    query!("UPDATE journal SET undone = 0 WHERE id = ?", entry.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Some(entry))
+}
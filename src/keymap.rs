@@ -0,0 +1,540 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Everything a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Close,
+    AddPayment,
+    NewBudget,
+    Delete,
+    ScrollDown,
+    ScrollUp,
+    ScrollTop,
+    ScrollBottom,
+    PageDown,
+    PageUp,
+    Details,
+    Payees,
+    Search,
+    NextMatch,
+    PrevMatch,
+    Undo,
+    Redo,
+    CycleSort,
+    ReverseSort,
+    Filter,
+    CycleFlow,
+    ThisWeek,
+    ClearFilters,
+    Trash,
+    EditMemo,
+    Attach,
+    Open,
+    Restore,
+    Purge,
+    Help,
+}
+
+impl Action {
+    const ALL: [Action; 31] = [
+        Action::Quit,
+        Action::Close,
+        Action::AddPayment,
+        Action::NewBudget,
+        Action::Delete,
+        Action::ScrollDown,
+        Action::ScrollUp,
+        Action::ScrollTop,
+        Action::ScrollBottom,
+        Action::PageDown,
+        Action::PageUp,
+        Action::Details,
+        Action::Payees,
+        Action::Search,
+        Action::NextMatch,
+        Action::PrevMatch,
+        Action::Undo,
+        Action::Redo,
+        Action::CycleSort,
+        Action::ReverseSort,
+        Action::Filter,
+        Action::CycleFlow,
+        Action::ThisWeek,
+        Action::ClearFilters,
+        Action::Trash,
+        Action::EditMemo,
+        Action::Attach,
+        Action::Open,
+        Action::Restore,
+        Action::Purge,
+        Action::Help,
+    ];
+
+    /// The name used for the action in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Close => "close",
+            Action::AddPayment => "add-payment",
+            Action::NewBudget => "new-budget",
+            Action::Delete => "delete",
+            Action::ScrollDown => "scroll-down",
+            Action::ScrollUp => "scroll-up",
+            Action::ScrollTop => "scroll-top",
+            Action::ScrollBottom => "scroll-bottom",
+            Action::PageDown => "page-down",
+            Action::PageUp => "page-up",
+            Action::Details => "details",
+            Action::Payees => "payees",
+            Action::Search => "search",
+            Action::NextMatch => "next-match",
+            Action::PrevMatch => "prev-match",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::CycleSort => "cycle-sort",
+            Action::ReverseSort => "reverse-sort",
+            Action::Filter => "filter",
+            Action::CycleFlow => "cycle-flow",
+            Action::ThisWeek => "this-week",
+            Action::ClearFilters => "clear-filters",
+            Action::Trash => "trash",
+            Action::EditMemo => "edit-memo",
+            Action::Attach => "attach",
+            Action::Open => "open",
+            Action::Restore => "restore",
+            Action::Purge => "purge",
+            Action::Help => "help",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Close => "Close the popup",
+            Action::AddPayment => "Add a new payment",
+            Action::NewBudget => "Add a new budget",
+            Action::Delete => "Delete a payment by id or uuid",
+            Action::ScrollDown => "Scroll down",
+            Action::ScrollUp => "Scroll up",
+            Action::ScrollTop => "Jump to the top",
+            Action::ScrollBottom => "Jump to the bottom",
+            Action::PageDown => "Scroll down a page",
+            Action::PageUp => "Scroll up a page",
+            Action::Details => "Show details of a payment",
+            Action::Payees => "Show payee history",
+            Action::Search => "Search payments",
+            Action::NextMatch => "Jump to the next match",
+            Action::PrevMatch => "Jump to the previous match",
+            Action::Undo => "Undo the last change",
+            Action::Redo => "Redo the last undone change",
+            Action::CycleSort => "Cycle the sort column",
+            Action::ReverseSort => "Reverse the sort order",
+            Action::Filter => "Filter payments",
+            Action::CycleFlow => "Cycle expenses/income/all",
+            Action::ThisWeek => "Only show this week",
+            Action::ClearFilters => "Clear all filters",
+            Action::Trash => "Show the trash",
+            Action::EditMemo => "Edit the memo",
+            Action::Attach => "Attach a file",
+            Action::Open => "Open the attachment",
+            Action::Restore => "Restore from the trash",
+            Action::Purge => "Delete forever",
+            Action::Help => "Show the keys for the current mode",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Action, String> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name() == s)
+            .ok_or_else(|| format!("unknown action {s:?}"))
+    }
+}
+
+/// The parts of the UI that have their own bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Normal,
+    Details,
+    Trash,
+    Payees,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [Scope::Normal, Scope::Details, Scope::Trash, Scope::Payees];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Normal => "normal",
+            Scope::Details => "details",
+            Scope::Trash => "trash",
+            Scope::Payees => "payees",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Scope, String> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.name() == s)
+            .ok_or_else(|| format!("unknown key scope {s:?}"))
+    }
+}
+
+/// A single key press, written like `a`, `C-r`, `M-x`, `Del` or `Enter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    const NAMED: [(&'static str, KeyCode); 14] = [
+        ("Enter", KeyCode::Enter),
+        ("Esc", KeyCode::Esc),
+        ("Tab", KeyCode::Tab),
+        ("BackTab", KeyCode::BackTab),
+        ("Del", KeyCode::Delete),
+        ("Backspace", KeyCode::Backspace),
+        ("Up", KeyCode::Up),
+        ("Down", KeyCode::Down),
+        ("Left", KeyCode::Left),
+        ("Right", KeyCode::Right),
+        ("Home", KeyCode::Home),
+        ("End", KeyCode::End),
+        ("PageUp", KeyCode::PageUp),
+        ("PageDown", KeyCode::PageDown),
+    ];
+
+    /// Shift is already part of an upper case character, so it's dropped to make `N` match `N`
+    pub fn from_event(event: &KeyEvent) -> Key {
+        let modifiers = match event.code {
+            KeyCode::Char(_) => event.modifiers - KeyModifiers::SHIFT,
+            _ => event.modifiers,
+        };
+        Key {
+            code: event.code,
+            modifiers,
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Key, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        while let Some((prefix, tail)) = rest.split_once('-').filter(|(_, tail)| !tail.is_empty()) {
+            modifiers |= match prefix {
+                "C" => KeyModifiers::CONTROL,
+                "M" => KeyModifiers::ALT,
+                "S" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier {prefix:?} in {s:?}")),
+            };
+            rest = tail;
+        }
+
+        let code = match Key::NAMED.iter().find(|(name, _)| *name == rest) {
+            Some((_, code)) => *code,
+            None if rest == "Space" => KeyCode::Char(' '),
+            None if rest.starts_with('F') && rest.len() > 1 => rest[1..]
+                .parse()
+                .map(KeyCode::F)
+                .map_err(|_| format!("unknown key {s:?}"))?,
+            None => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    (_, _) => return Err(format!("unknown key {s:?}")),
+                }
+            }
+        };
+        Ok(Key { code, modifiers })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, prefix) in [
+            (KeyModifiers::CONTROL, "C-"),
+            (KeyModifiers::ALT, "M-"),
+            (KeyModifiers::SHIFT, "S-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{prefix}")?;
+            }
+        }
+        match (self.code, Key::NAMED.iter().find(|(_, code)| *code == self.code)) {
+            (_, Some((name, _))) => write!(f, "{name}"),
+            (KeyCode::Char(' '), _) => write!(f, "Space"),
+            (KeyCode::Char(c), _) => write!(f, "{c}"),
+            (KeyCode::F(n), _) => write!(f, "F{n}"),
+            (code, _) => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// One or more keys pressed one after the other, written separated by spaces like `g g`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord(pub Vec<Key>);
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Chord, String> {
+        let keys = s
+            .split_whitespace()
+            .map(Key::from_str)
+            .collect::<Result<Vec<Key>, String>>()?;
+        match keys.is_empty() {
+            true => Err("empty key binding".to_string()),
+            false => Ok(Chord(keys)),
+        }
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.0.iter().map(Key::to_string).collect();
+        write!(f, "{}", keys.join(" "))
+    }
+}
+
+/// What a key press turned out to mean
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolved {
+    Action(Action),
+    /// The keys so far are the start of a longer chord
+    Pending,
+    Unbound,
+}
+
+/// Keys of the text inputs, which aren't configurable since everything else is typed
+pub const EDITING_KEYS: [(&str, &str); 5] = [
+    ("Tab", "Change focus while editing"),
+    ("Right", "Accept payee autocompletion"),
+    ("Esc", "Exits editing without saving"),
+    ("Enter", "Finalizes edits and submits"),
+    ("F1", "Show the keys for the current mode"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: BTreeMap<Scope, Vec<(Chord, Action)>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let defaults: [(Scope, &[(&str, Action)]); 4] = [
+            (
+                Scope::Normal,
+                &[
+                    ("a", Action::AddPayment),
+                    ("b", Action::NewBudget),
+                    ("Del", Action::Delete),
+                    ("j", Action::ScrollDown),
+                    ("k", Action::ScrollUp),
+                    ("Down", Action::ScrollDown),
+                    ("Up", Action::ScrollUp),
+                    ("g g", Action::ScrollTop),
+                    ("G", Action::ScrollBottom),
+                    ("PageDown", Action::PageDown),
+                    ("C-d", Action::PageDown),
+                    ("PageUp", Action::PageUp),
+                    ("C-u", Action::PageUp),
+                    ("Enter", Action::Details),
+                    ("p", Action::Payees),
+                    ("/", Action::Search),
+                    ("n", Action::NextMatch),
+                    ("N", Action::PrevMatch),
+                    ("s", Action::CycleSort),
+                    ("r", Action::ReverseSort),
+                    ("f", Action::Filter),
+                    ("e", Action::CycleFlow),
+                    ("w", Action::ThisWeek),
+                    ("F", Action::ClearFilters),
+                    ("t", Action::Trash),
+                    ("u", Action::Undo),
+                    ("C-r", Action::Redo),
+                    ("?", Action::Help),
+                    ("q", Action::Quit),
+                ],
+            ),
+            (
+                Scope::Details,
+                &[
+                    ("m", Action::EditMemo),
+                    ("f", Action::Attach),
+                    ("j", Action::ScrollDown),
+                    ("k", Action::ScrollUp),
+                    ("o", Action::Open),
+                    ("Enter", Action::Open),
+                    ("?", Action::Help),
+                    ("Esc", Action::Close),
+                    ("q", Action::Close),
+                ],
+            ),
+            (
+                Scope::Trash,
+                &[
+                    ("j", Action::ScrollDown),
+                    ("k", Action::ScrollUp),
+                    ("r", Action::Restore),
+                    ("Enter", Action::Restore),
+                    ("Del", Action::Purge),
+                    ("?", Action::Help),
+                    ("Esc", Action::Close),
+                    ("t", Action::Close),
+                    ("q", Action::Close),
+                ],
+            ),
+            (
+                Scope::Payees,
+                &[
+                    ("?", Action::Help),
+                    ("Esc", Action::Close),
+                    ("p", Action::Close),
+                    ("q", Action::Close),
+                ],
+            ),
+        ];
+
+        let bindings = defaults
+            .into_iter()
+            .map(|(scope, keys)| {
+                let keys = keys
+                    .iter()
+                    .map(|(chord, action)| (chord.parse().expect("default keys parse"), *action))
+                    .collect();
+                (scope, keys)
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    /// The default bindings with the `[keys.<scope>]` tables from the config file on top.
+    /// Binding a key to `"none"` removes it
+    pub fn from_config(
+        keys: &BTreeMap<String, BTreeMap<String, String>>,
+    ) -> Result<Keymap, Box<dyn std::error::Error>> {
+        let mut keymap = Keymap::default();
+        for (scope, table) in keys {
+            let scope: Scope = scope.parse()?;
+            let bindings = keymap.bindings.entry(scope).or_default();
+            for (chord, action) in table {
+                let chord: Chord = chord.parse()?;
+                bindings.retain(|(existing, _)| *existing != chord);
+                if action != "none" {
+                    bindings.push((chord, action.parse()?));
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn bindings(&self, scope: Scope) -> &[(Chord, Action)] {
+        self.bindings.get(&scope).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The keys bound to `action`, for showing hints
+    pub fn keys_for(&self, scope: Scope, action: Action) -> Vec<String> {
+        self.bindings(scope)
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(chord, _)| chord.to_string())
+            .collect()
+    }
+
+    /// Adds `key` to the keys typed so far and looks the result up, clearing `pending`
+    /// once it either matches a binding or can't become one
+    pub fn resolve(&self, scope: Scope, pending: &mut Vec<Key>, key: Key) -> Resolved {
+        pending.push(key);
+        let bindings = self.bindings(scope);
+
+        if let Some((_, action)) = bindings.iter().find(|(chord, _)| chord.0 == *pending) {
+            pending.clear();
+            return Resolved::Action(*action);
+        }
+        if bindings
+            .iter()
+            .any(|(chord, _)| chord.0.len() > pending.len() && chord.0.starts_with(pending))
+        {
+            return Resolved::Pending;
+        }
+
+        // A failed chord shouldn't swallow the key that broke it
+        let retry = pending.len() > 1;
+        pending.clear();
+        match retry {
+            true => self.resolve(scope, pending, key),
+            false => Resolved::Unbound,
+        }
+    }
+
+    /// Every action bound in `scope` with all of its keys, in the order they were bound
+    pub fn actions(&self, scope: Scope) -> Vec<(Vec<String>, Action)> {
+        let mut actions: Vec<Action> = Vec::new();
+        for (_, action) in self.bindings(scope) {
+            if !actions.contains(action) {
+                actions.push(*action);
+            }
+        }
+        actions
+            .into_iter()
+            .map(|action| (self.keys_for(scope, action), action))
+            .collect()
+    }
+
+    /// A markdown table of every binding, as found in the README
+    pub fn markdown(&self) -> String {
+        let mut rows: Vec<(String, String)> = Vec::new();
+        for scope in Scope::ALL {
+            for (keys, action) in self.actions(scope) {
+                let keys: Vec<String> = keys.iter().map(|key| format!("`{key}`")).collect();
+                let description = match scope {
+                    Scope::Normal => action.description().to_string(),
+                    scope => format!("{} ({})", action.description(), scope.name()),
+                };
+                rows.push((keys.join(", "), description));
+            }
+        }
+        for (key, description) in EDITING_KEYS {
+            rows.push((format!("`{key}`"), description.to_string()));
+        }
+
+        let key_width = rows.iter().map(|(key, _)| key.chars().count()).max().unwrap_or(3).max(3);
+        let action_width = rows
+            .iter()
+            .map(|(_, description)| description.chars().count())
+            .max()
+            .unwrap_or(6)
+            .max(6);
+
+        let mut table = format!(
+            "| {:key_width$} | {:action_width$} |\n|{}|{}|\n",
+            "Key",
+            "Action",
+            "-".repeat(key_width + 2),
+            "-".repeat(action_width + 2)
+        );
+        for (key, description) in rows {
+            table.push_str(&format!("| {key:key_width$} | {description:action_width$} |\n"));
+        }
+        table
+    }
+}
@@ -0,0 +1,32 @@
+//! Budgets, payments and everything around them, stored in SQLite. The `budge` binary's
+//! command line and terminal UI are both built on top of this. The budget and payment
+//! operations are also available through [`Storage`], which runs in memory too and, with the
+//! `postgres` feature, on PostgreSQL
+pub mod attachment;
+pub mod backup;
+pub mod budget;
+pub mod db;
+pub mod doctor;
+pub mod encryption;
+mod error;
+pub mod export;
+pub mod filter;
+pub mod import;
+pub mod journal;
+pub mod memory;
+pub mod payee;
+pub mod payment;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod search;
+pub mod serve;
+pub mod snapshot;
+pub mod storage;
+pub mod sync;
+pub mod trash;
+pub mod viewport;
+
+pub use budget::Budget;
+pub use error::{Error, Result};
+pub use payment::Payment;
+pub use storage::Storage;
@@ -1,90 +1,782 @@
 mod app;
-use std::path::PathBuf;
+mod config;
+mod keymap;
+mod theme;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 
-use dirs::data_dir;
+use budge::storage::Ref;
+use budge::trash::Record;
+use budge::{
+    attachment, backup, budget, db, doctor, encryption, export, import, journal, payee, search,
+    serve, snapshot, storage, sync, trash, Storage,
+};
 use ratatui::{init, restore};
-use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::{migrate, Pool, Sqlite, SqlitePool};
-use sqlx::{query, query_as};
-
 
 use crate::app::App;
-pub type DbPool = Pool<Sqlite>;
-
-pub async fn create_database_pool(options: &str) -> Result<DbPool, Box<dyn std::error::Error>> {
-    let db_opts: SqliteConnectOptions = options.parse()?;
-
-    let pool = SqlitePool::connect_with(db_opts.create_if_missing(true)).await?;
+use crate::config::{Config, Startup};
+use clap::{CommandFactory, Parser, Subcommand};
 
-    migrate!().run(&pool).await?;
-
-    Ok(pool)
+/// Every command, grouped by what it needs to run
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    #[command(flatten)]
+    Books(Mode),
+    #[command(flatten)]
+    Settings(SettingsCommand),
 }
-use clap::{Parser, Subcommand};
 
+/// Commands that work on the database
 #[derive(Subcommand, Clone, Debug)]
 enum Mode {
+    #[command(flatten)]
+    Storage(StorageCommand),
+    /// Load a budget
+    Load {
+        budget_id: Ref,
+    },
+    /// List all payees with their lifetime totals
+    Payees,
+    /// Search payments across all budgets by kind, memo or payee
+    Search {
+        query: Vec<String>,
+    },
+    /// Undo the most recent change to a payment
+    Undo,
+    /// Redo the most recently undone change to a payment
+    Redo,
+    /// Manage deleted budgets and payments
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
+    },
+    /// Check the database for orphaned payments, duplicate months and malformed dates
+    Doctor {
+        /// Repair the problems that were found
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Attach a file, such as a receipt, to a payment
+    Attach {
+        payment_id: Ref,
+        file: PathBuf,
+    },
+    /// Serve a JSON API for budgets, payments and reports, described at /openapi.json
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
+        /// Clients send it as `Authorization: Bearer <token>`, defaults to `BUDGE_TOKEN`
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Add the transactions of a ledger, hledger or beancount journal as payments
+    Import {
+        format: import::Format,
+        file: PathBuf,
+        /// TOML file with the kinds for accounts, such as `"Expenses:Food" = "groceries"` under
+        /// `[kinds]`, and the `commodity` to import
+        #[arg(long)]
+        mapping: Option<PathBuf>,
+    },
+    /// Write every budget, payee and payment, trashed ones too, as versioned JSON
+    Backup {
+        /// Where to write it, instead of printing it
+        file: Option<PathBuf>,
+    },
+    /// Load a backup into the database, which may be new or already have budgets
+    Restore {
+        file: PathBuf,
+        /// What to do with months that are already in the database
+        #[arg(long, value_enum, default_value_t)]
+        on_conflict: backup::Conflict,
+    },
+    /// Copies of the database taken every day and before anything that can't be undone
+    Snapshots {
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+    /// Encrypt the database with a passphrase, in a budge built with `--features sqlcipher`
+    Encrypt,
+    /// Change the passphrase of an encrypted database
+    Rekey {
+        /// File holding the new passphrase, instead of BUDGE_NEW_KEY or asking for it
+        #[arg(long)]
+        new_key_file: Option<PathBuf>,
+    },
+    /// Store an encrypted database unencrypted again
+    Decrypt,
+    /// Merge budgets and payments with another database, or through a shared folder
+    Sync {
+        /// A database, created if it doesn't exist, or a folder
+        path: PathBuf,
+    },
+}
+
+/// Commands that only need the budget and payment operations, so they work on any storage
+/// backend
+#[derive(Subcommand, Clone, Debug)]
+enum StorageCommand {
     /// Add a new budget
     Create {
         amount: f64,
         month: String
     },
-    /// Remove a budget, supplying the ID
+    /// Move a budget to the trash, supplying its id or uuid
     Remove {
-        id: i64,
+        id: Ref,
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
     },
     /// List all budgets
     List,
-    /// Load a budget
-    Load {
-        budget_id: i64,
+    /// Set or clear the memo on a payment
+    Memo {
+        payment_id: Ref,
+        memo: Option<String>,
+    },
+    /// Print payments as balanced transactions for ledger, hledger or beancount
+    Export {
+        format: export::Format,
+        /// First month to include, as YYYY-MM
+        #[arg(long)]
+        from: Option<String>,
+        /// Last month to include, as YYYY-MM
+        #[arg(long)]
+        to: Option<String>,
     },
 }
 
+/// Commands that only read or write the config file, without opening a database
+#[derive(Subcommand, Clone, Debug)]
+enum SettingsCommand {
+    /// List the databases of every configured profile
+    Profiles,
+    /// Print the active key bindings as a markdown table
+    Keys,
+    /// Show or change settings in the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum TrashCommand {
+    /// List everything in the trash
+    List,
+    /// Bring a budget or payment back out of the trash
+    Restore {
+        record: Record,
+        id: Ref,
+    },
+    /// Permanently delete everything in the trash
+    Purge {
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum SnapshotCommand {
+    /// List the snapshots of the database, newest first
+    List,
+    /// Replace the database with a snapshot, after taking one of it as it is now
+    Restore { name: String },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum ConfigCommand {
+    /// Print the settings in effect, including defaults
+    Show,
+    /// Change a setting, such as `currency.symbol €` or `defaults.startup current-month`
+    Set { key: String, value: String },
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no
+fn confirm(prompt: &str) -> std::io::Result<bool> {
+    print!("{prompt} [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Runs the commands that only touch the config file
+fn settings(command: SettingsCommand, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        SettingsCommand::Profiles => {
+            // Whatever budge opens without --db or --profile is marked, even when BUDGE_DB
+            // points somewhere else entirely
+            let default = config.resolve_db(None, None).ok();
+            let mark = |path: &Path| match default.as_deref() == Some(path) {
+                true => " (default)",
+                false => "",
+            };
+            let built_in = Config::default_db()?;
+            let mut marked = default.as_ref() == Some(&built_in);
+            println!("built in{}: {}", mark(&built_in), built_in.to_string_lossy());
+            for (name, profile) in &config.profiles {
+                let db = config::expand_home(&profile.db);
+                marked |= default.as_ref() == Some(&db);
+                println!("{name}{}: {}", mark(&db), db.to_string_lossy());
+            }
+            if let Some(default) = default.filter(|_| !marked) {
+                println!("BUDGE_DB (default): {}", default.to_string_lossy());
+            }
+        }
+        SettingsCommand::Keys => print!("{}", config.keymap()?.markdown()),
+        SettingsCommand::Config { command } => match command {
+            ConfigCommand::Show => {
+                println!("# {}", Config::path()?.to_string_lossy());
+                print!("{}", toml::to_string_pretty(config)?);
+            }
+            ConfigCommand::Set { key, value } => {
+                Config::set(&key, &value)?;
+                println!("Set {key} to {value}");
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Runs the commands that work on any storage backend
+async fn run_on(
+    storage: &dyn Storage,
+    command: StorageCommand,
+    config: &Config,
+    db: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        StorageCommand::Create { amount, month } => {
+            storage.create_budget(amount, &month).await?;
+            println!("Budget created successfully")
+        }
+        StorageCommand::List => {
+            println!("Hosted at: {db}");
+            for budget in storage.budgets().await? {
+                println!(
+                    "{}: {} - {} [{}]",
+                    budget.id,
+                    config.money(budget.amount),
+                    budget.month,
+                    budget.uuid
+                );
+            }
+        }
+        StorageCommand::Memo { payment_id, memo } => {
+            let id = storage.resolve(Record::Payment, &payment_id).await?;
+            storage.set_memo(id, memo.as_deref()).await?;
+            println!("Updated memo on payment {id}")
+        }
+        StorageCommand::Remove { id, .. } => {
+            let id = storage.resolve(Record::Budget, &id).await?;
+            match storage.remove_budget(id).await? {
+                true => println!("Moved budget with id {id} to the trash"),
+                false => println!("No budget with id {id}"),
+            }
+        }
+        StorageCommand::Export { format, from, to } => {
+            let journal =
+                export::export(storage, format, from.as_deref(), to.as_deref(), &config.export)
+                    .await?;
+            print!("{journal}")
+        }
+    }
+    Ok(())
+}
+
+/// Why a snapshot is taken before running `mode`, if it changes things that can't be undone
+fn snapshot_reason(mode: &Mode) -> Option<&'static str> {
+    match mode {
+        Mode::Storage(StorageCommand::Remove { .. }) => Some("remove"),
+        Mode::Trash {
+            command: TrashCommand::Purge { .. },
+        } => Some("purge"),
+        Mode::Doctor { fix: true } => Some("doctor"),
+        Mode::Restore { .. } => Some("restore"),
+        Mode::Import { .. } => Some("import"),
+        Mode::Sync { .. } => Some("sync"),
+        _ => None,
+    }
+}
+
+/// The API is reachable by anyone on the network it binds to, so it never runs without a token
+fn serve_token(token: Option<String>) -> Result<String, Box<dyn std::error::Error>> {
+    token
+        .or_else(|| std::env::var("BUDGE_TOKEN").ok())
+        .filter(|x| !x.is_empty())
+        .ok_or_else(|| "pass --token or set BUDGE_TOKEN, clients have to send it".into())
+}
+
+#[cfg(feature = "postgres")]
+async fn connect_postgres(url: &str) -> Result<Box<dyn Storage>, Box<dyn std::error::Error>> {
+    Ok(Box::new(budge::postgres::connect(url).await?))
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn connect_postgres(_: &str) -> Result<Box<dyn Storage>, Box<dyn std::error::Error>> {
+    Err("this budge was built without PostgreSQL support, rebuild it with `--features postgres`".into())
+}
+
+fn print_sync(place: &str, report: &sync::Report) {
+    println!("Received {} changes {place}", report.received);
+    for x in &report.updates {
+        println!("  {} {} {}: {}", x.action.as_str(), x.record, x.id, x.label);
+    }
+}
+
+#[cfg(feature = "sqlcipher")]
+async fn connect_sqlite(
+    db_path: &Path,
+    db_url: &str,
+    config: &Config,
+) -> Result<db::DbPool, Box<dyn std::error::Error>> {
+    match encryption::is_encrypted(db_path)? {
+        true => Ok(db::connect_with_key(db_url, &passphrase(config)?).await?),
+        false => Ok(db::connect(db_url).await?),
+    }
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+async fn connect_sqlite(
+    db_path: &Path,
+    db_url: &str,
+    _: &Config,
+) -> Result<db::DbPool, Box<dyn std::error::Error>> {
+    if encryption::is_encrypted(db_path)? {
+        return Err("this database is encrypted, and this budge was built without SQLCipher, rebuild it with `--features sqlcipher`".into());
+    }
+    Ok(db::connect(db_url).await?)
+}
+
+/// The passphrase of an encrypted database, from BUDGE_KEY, the configured key file or a prompt
+#[cfg(feature = "sqlcipher")]
+fn passphrase(config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(key) = std::env::var("BUDGE_KEY").ok().filter(|x| !x.is_empty()) {
+        return Ok(key);
+    }
+    if let Some(file) = &config.encryption.key_file {
+        return read_key(&config::expand_home(file));
+    }
+    ask("Passphrase: ")
+}
+
+#[cfg(feature = "sqlcipher")]
+fn ask(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    rpassword::prompt_password(prompt).map_err(|e| {
+        format!("couldn't ask for the passphrase ({e}), set BUDGE_KEY or `key_file` under [encryption]").into()
+    })
+}
+
+/// A passphrase to encrypt with, from a file, an environment variable or a prompt that asks
+/// twice, since a typo would lock the database
+#[cfg(feature = "sqlcipher")]
+fn new_passphrase(file: Option<&Path>, var: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(file) = file {
+        return read_key(file);
+    }
+    if let Some(key) = std::env::var(var).ok().filter(|x| !x.is_empty()) {
+        return Ok(key);
+    }
+    let key = ask("New passphrase: ")?;
+    if key.is_empty() {
+        return Err("the passphrase can't be empty".into());
+    }
+    if ask("Repeat it: ")? != key {
+        return Err("the passphrases don't match".into());
+    }
+    Ok(key)
+}
+
+/// Key files may end with a newline, which isn't part of the passphrase
+#[cfg(feature = "sqlcipher")]
+fn read_key(file: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let key = std::fs::read_to_string(file)?;
+    let key = key.trim_end_matches(['\r', '\n']);
+    if key.is_empty() {
+        return Err(format!("{} is empty", file.to_string_lossy()).into());
+    }
+    Ok(key.to_string())
+}
+
+/// Encrypts, rekeys or decrypts the database. Its snapshots are deleted afterwards, as they'd
+/// still open with the old passphrase, or without one
+#[cfg(feature = "sqlcipher")]
+async fn change_key(
+    mode: Mode,
+    pool: db::DbPool,
+    db_path: &Path,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let encrypted = encryption::is_encrypted(db_path)?;
+    let key_file = config.encryption.key_file.as_deref().map(config::expand_home);
+    let (key, done) = match &mode {
+        Mode::Encrypt if encrypted => {
+            return Err("the database is already encrypted, change its passphrase with `budge rekey`".into())
+        }
+        Mode::Rekey { .. } | Mode::Decrypt if !encrypted => {
+            return Err("the database isn't encrypted, encrypt it with `budge encrypt`".into())
+        }
+        Mode::Encrypt => (new_passphrase(key_file.as_deref(), "BUDGE_KEY")?, "Encrypted"),
+        Mode::Rekey { new_key_file } => (
+            new_passphrase(new_key_file.as_deref(), "BUDGE_NEW_KEY")?,
+            "Changed the passphrase of",
+        ),
+        _ => (String::new(), "Decrypted"),
+    };
+    encryption::rewrite(pool, db_path, &key).await?;
+    println!("{done} {}", db_path.to_string_lossy());
+
+    let snapshots = snapshot::list(db_path)?;
+    for x in &snapshots {
+        std::fs::remove_file(&x.path)?;
+    }
+    if !snapshots.is_empty() {
+        println!("Deleted {} snapshots taken before", snapshots.len());
+    }
+    if let (Mode::Rekey { new_key_file: None }, Some(file)) = (&mode, key_file) {
+        println!("Remember to put the new passphrase in {}", file.to_string_lossy());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+async fn change_key(
+    _: Mode,
+    _: db::DbPool,
+    _: &Path,
+    _: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("this budge was built without SQLCipher, rebuild it with `--features sqlcipher`".into())
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     #[command(subcommand)]
-    mode: Mode,
+    mode: Option<Command>,
+    /// Database to use instead of the default, also read from BUDGE_DB
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+    /// Use the database of a profile from the config file
+    #[arg(long, global = true)]
+    profile: Option<String>,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
-    let mut db_path:PathBuf = data_dir().unwrap();
-    db_path.push("budge.db");
+    let config = Config::load()?;
+
+    let mode = match args.mode {
+        Some(Command::Settings(command)) => return settings(command, &config),
+        Some(Command::Books(mode)) => Some(mode),
+        None if config.defaults.startup == Startup::Help => {
+            Args::command().print_help()?;
+            return Ok(());
+        }
+        None => None,
+    };
+
+    // Ask before touching anything, so that saying no doesn't cost a snapshot
+    let confirmed = match &mode {
+        Some(Mode::Storage(StorageCommand::Remove { id, yes: false })) => {
+            confirm(&format!("Move budget {id} and its payments to the trash?"))?
+        }
+        Some(Mode::Trash {
+            command: TrashCommand::Purge { yes: false },
+        }) => confirm("Permanently delete everything in the trash?")?,
+        _ => true,
+    };
+    if !confirmed {
+        return Ok(());
+    }
+
+    let db_path = config.resolve_db(args.db, args.profile.as_deref())?;
     let db_url = format!("{}", db_path.to_string_lossy());
 
+    if storage::is_postgres(&db_url) {
+        let storage = connect_postgres(&db_url).await?;
+        return match mode {
+            Some(Mode::Storage(command)) => run_on(&*storage, command, &config, &db_url).await,
+            Some(Mode::Serve { bind, token }) => {
+                serve::run(Arc::from(storage), bind, serve_token(token)?).await
+            }
+            _ => Err(
+                "only create, list, remove, memo, export and serve work on a PostgreSQL database".into(),
+            ),
+        };
+    }
+
     if !db_path.exists() {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
         std::fs::File::create(db_path.clone())?;
     }
 
-    let pool = create_database_pool(&db_url).await?;
+    let pool = connect_sqlite(&db_path, &db_url, &config).await?;
+    let keep = config.snapshots.keep;
+    // Restoring takes a snapshot of its own, and the daily one could rotate away the one asked for
+    let daily = match mode {
+        Some(Mode::Snapshots { .. }) => Ok(None),
+        _ => snapshot::daily(&pool, &db_path, keep).await,
+    };
+    if let Err(e) = daily {
+        eprintln!("Couldn't take today's snapshot: {e}");
+    }
 
-    match args.mode {
+    let mode = match mode {
+        Some(mode) => mode,
+        None => Mode::Load {
+            budget_id: Ref::Id(
+                budget::find_or_create(
+                    &pool,
+                    &Config::current_month(),
+                    config.defaults.budget_amount,
+                )
+                .await?,
+            ),
+        },
+    };
+
+    if let Some(reason) = snapshot_reason(&mode) {
+        snapshot::take(&pool, &db_path, reason, keep).await?;
+    }
+    match mode {
+        Mode::Storage(command) => run_on(&pool, command, &config, &db_url).await?,
         Mode::Load { budget_id } => {
+            let budget_id = pool.resolve(Record::Budget, &budget_id).await?;
+            if pool.budget(budget_id).await?.is_none() {
+                return Err(budge::Error::NotFound {
+                    record: "budget",
+                    id: budget_id,
+                }
+                .into());
+            }
+            let keymap = config.keymap()?;
+            let theme = config.theme()?;
             let mut terminal = init();
 
-            let res = App::new(pool, budget_id).run(&mut terminal).await;
+            let attachments_dir = attachment::attachments_dir(&db_path);
+            let res = App::new(pool, budget_id, attachments_dir, config, keymap, theme)
+                .run(&mut terminal)
+                .await;
 
             restore();
             res?;
         }
-        Mode::Create { amount, month } => {
-            query!("INSERT INTO budget (amount, month) VALUES (?, ?)", amount, month).execute(&pool).await?;
-            println!("Budget created successfully")
+        Mode::Payees => {
+            for payee in payee::summaries(&pool).await? {
+                println!(
+                    "{}: {} - {} visits, {} total, {} average, last visit {} [{}]",
+                    payee.id,
+                    payee.name,
+                    payee.visits,
+                    config.money(payee.total),
+                    config.money(payee.average),
+                    payee
+                        .last_visit
+                        .map(|x| config.date(&x))
+                        .unwrap_or_else(|| "never".to_string()),
+                    payee.uuid
+                );
+            }
+        }
+        Mode::Attach { payment_id, file } => {
+            let payment_id = pool.resolve(Record::Payment, &payment_id).await?;
+            attachment::attach(&pool, &attachment::attachments_dir(&db_path), payment_id, &file)
+                .await?;
+            println!("Attached {} to payment {payment_id}", file.to_string_lossy())
+        }
+        Mode::Undo => match journal::undo(&pool).await? {
+            Some(entry) => println!("Undid {}", entry.describe()),
+            None => println!("Nothing to undo"),
+        },
+        Mode::Redo => match journal::redo(&pool).await? {
+            Some(entry) => println!("Redid {}", entry.describe()),
+            None => println!("Nothing to redo"),
+        },
+        Mode::Search { query } => {
+            for hit in search::search(&pool, &query.join(" ")).await? {
+                println!(
+                    "{}: {} {} - {} {}{} [{}]",
+                    hit.id,
+                    hit.month,
+                    config.date(&hit.day_of),
+                    config.money(hit.amount),
+                    hit.kind,
+                    match (hit.payee, hit.memo) {
+                        (Some(payee), Some(memo)) => format!(" at {payee} ({memo})"),
+                        (Some(payee), None) => format!(" at {payee}"),
+                        (None, Some(memo)) => format!(" ({memo})"),
+                        (None, None) => String::new(),
+                    },
+                    hit.uuid
+                );
+            }
+        }
+        Mode::Serve { bind, token } => {
+            serve::run(Arc::new(pool), bind, serve_token(token)?).await?
         }
-        Mode::List => {
-            println!("Hosted at: {}", db_path.to_string_lossy());
-            let budgets = query_as!(crate::app::Budget, "SELECT * FROM budget").fetch_all(&pool).await?;
-            for budget in budgets {
-                println!("{}: {} - {}", budget.id, budget.amount, budget.month);
+        Mode::Backup { file } => {
+            let json = serde_json::to_string_pretty(&backup::backup(&pool).await?)?;
+            match file {
+                Some(file) => std::fs::write(file, json + "\n")?,
+                None => println!("{json}"),
             }
         }
-        Mode::Remove { id } => {
-            query!("DELETE FROM budget WHERE id = ?", id).execute(&pool).await?;
-            println!("Removed budget with id {id} successfully")
+        Mode::Restore { file, on_conflict } => {
+            let backup = backup::read(&std::fs::read_to_string(&file)?)?;
+            let restored = backup::restore(&pool, &backup, on_conflict).await?;
+            println!(
+                "Restored {} budgets and {} payments from {}",
+                restored.budgets, restored.payments, backup.created_at
+            );
+            if !restored.skipped.is_empty() {
+                println!("Kept the budgets already here for {}", restored.skipped.join(", "));
+            }
+            if !restored.overwritten.is_empty() {
+                println!("Replaced the budgets for {}", restored.overwritten.join(", "));
+            }
+        }
+        Mode::Import { format, file, mapping } => {
+            let mut mapping: import::Mapping = match mapping {
+                Some(path) => toml::from_str(&std::fs::read_to_string(&path)?)
+                    .map_err(|e| format!("couldn't read {}: {e}", path.to_string_lossy()))?,
+                None => Default::default(),
+            };
+            mapping
+                .commodity
+                .get_or_insert_with(|| config.export.commodity.clone());
+            let journal = std::fs::read_to_string(&file)?;
+            let report =
+                import::import(&pool, format, &journal, &mapping, config.defaults.budget_amount)
+                    .await?;
+            println!(
+                "Imported {} payments, creating {} budgets",
+                report.payments, report.budgets
+            );
+            if report.existing > 0 {
+                println!("Left out {} payments that were already here", report.existing);
+            }
+            if !report.skipped.is_empty() {
+                println!("Left out {} lines that couldn't be translated:", report.skipped.len());
+            }
+            for skipped in &report.skipped {
+                println!(
+                    "  {}:{}: {} - {}",
+                    file.to_string_lossy(),
+                    skipped.line,
+                    skipped.text,
+                    skipped.reason
+                );
+            }
         }
+        Mode::Snapshots { command } => match command {
+            SnapshotCommand::List => {
+                for x in snapshot::list(&db_path)? {
+                    println!(
+                        "{} - {}, taken {}, {} KiB",
+                        x.name,
+                        x.reason,
+                        config.date(&x.taken_at.format("%Y-%m-%d %H:%M:%S").to_string()),
+                        x.size.div_ceil(1024)
+                    );
+                }
+            }
+            SnapshotCommand::Restore { name } => {
+                let restored = snapshot::restore(pool, &db_path, &name, keep).await?;
+                println!(
+                    "Restored the database from {}, the newest snapshot has it as it was before",
+                    restored.name
+                );
+            }
+        },
+        Mode::Sync { path } => {
+            let place = path.to_string_lossy();
+            let (here, there) = if path.is_dir() {
+                (sync::with_folder(&pool, &path).await?, None)
+            } else {
+                if !path.exists() {
+                    std::fs::File::create(&path)?;
+                }
+                let other = connect_sqlite(&path, &place, &config).await?;
+                let (here, there) = sync::with_database(&pool, &other).await?;
+                (here, Some(there))
+            };
+            print_sync("here", &here);
+            if let Some(device) = &here.new_device {
+                println!(
+                    "This database is a copy of one syncing through {place}, now it syncs as {device}"
+                );
+            }
+            if let Some(there) = there {
+                print_sync(&format!("in {place}"), &there);
+            }
+            for x in &here.conflicts {
+                println!(
+                    "Both sides changed {} {}, kept the change from {}",
+                    x.record,
+                    x.label,
+                    if x.kept_ours { "here" } else { &place }
+                );
+            }
+        }
+        mode @ (Mode::Encrypt | Mode::Rekey { .. } | Mode::Decrypt) => {
+            change_key(mode, pool, &db_path, &config).await?
+        }
+        Mode::Doctor { fix } => {
+            let problems = doctor::check(&pool, fix).await?;
+            for problem in &problems {
+                println!("{}", problem.description);
+            }
+            let repairable = problems.iter().filter(|x| x.repairable).count();
+            match (problems.len(), fix) {
+                (0, _) => println!("No problems found"),
+                (_, true) => println!("Repaired {repairable} of {} problems", problems.len()),
+                (n, false) => println!(
+                    "Found {n} problems, run `budge doctor --fix` to repair {repairable} of them"
+                ),
+            }
+        }
+        Mode::Trash { command } => match command {
+            TrashCommand::List => {
+                for item in trash::list(&pool).await? {
+                    println!(
+                        "{} {}: {} - deleted {} [{}]",
+                        item.record.as_str(),
+                        item.id, item.description, item.deleted_at, item.uuid
+                    );
+                }
+            }
+            TrashCommand::Restore { record, id } => {
+                let id = pool.resolve(record, &id).await?;
+                match trash::restore(&pool, record, id).await? {
+                    true => println!("Restored {} {id}", record.as_str()),
+                    false => println!("There's no {} {id} in the trash", record.as_str()),
+                }
+            }
+            TrashCommand::Purge { .. } => {
+                let purged = trash::purge(&pool).await?;
+                println!("Permanently deleted {purged} records")
+            }
+        },
     }
 
     Ok(())
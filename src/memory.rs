@@ -0,0 +1,260 @@
+use std::cmp::Ordering;
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::filter::{Filters, Flow, SortKey};
+use crate::storage::Storage;
+use crate::trash::Record;
+use crate::{budget, payment, Budget, Error, Payment, Result};
+
+#[derive(Debug, Default)]
+struct State {
+    /// Paired with whether the budget is in the trash
+    budgets: Vec<(Budget, bool)>,
+    payments: Vec<(Payment, bool)>,
+    /// A payee's id is its position plus one
+    payees: Vec<String>,
+}
+
+/// Keeps everything in memory and forgets it when dropped, for tests and trying things out.
+/// Behaves like the SQLite backend, except that nothing is recorded for `budge undo`
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: Mutex<State>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // Nothing panics while holding the lock, but if something did the data is still usable
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl State {
+    fn live_payment(&mut self, id: i64) -> Result<&mut Payment> {
+        self.payments
+            .iter_mut()
+            .find(|(x, deleted)| x.id == id && !deleted)
+            .map(|(x, _)| x)
+            .ok_or(Error::NotFound { record: "payment", id })
+    }
+
+    /// Same as the `COLLATE NOCASE` on payee names in SQLite
+    fn payee_id(&mut self, name: &str) -> i64 {
+        let position = match self.payees.iter().position(|x| x.eq_ignore_ascii_case(name)) {
+            Some(i) => i,
+            None => {
+                self.payees.push(name.to_string());
+                self.payees.len() - 1
+            }
+        };
+        position as i64 + 1
+    }
+}
+
+/// Mirrors the `WHERE` clause of `payment::list`
+fn passes(payment: &Payment, filters: &Filters) -> bool {
+    let date = payment.day_of.get(..10).unwrap_or(&payment.day_of);
+    filters.min_amount.is_none_or(|min| payment.amount >= min)
+        && filters.max_amount.is_none_or(|max| payment.amount <= max)
+        && filters.kind.as_ref().is_none_or(|kind| {
+            payment.kind.to_ascii_lowercase().contains(&kind.to_ascii_lowercase())
+        })
+        && filters.from.as_deref().is_none_or(|from| date >= from)
+        && filters.to.as_deref().is_none_or(|to| date <= to)
+        && match filters.flow {
+            Flow::All => true,
+            Flow::Expenses => payment.amount > 0.0,
+            Flow::Income => payment.amount < 0.0,
+        }
+}
+
+fn compare(a: &Payment, b: &Payment, sort: SortKey) -> Ordering {
+    match sort {
+        SortKey::Date => a.day_of.cmp(&b.day_of),
+        SortKey::Amount => a.amount.total_cmp(&b.amount),
+        SortKey::Kind => a.kind.cmp(&b.kind),
+        SortKey::Id => a.id.cmp(&b.id),
+    }
+}
+
+fn new_uuid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+fn next_id<T>(rows: &[(T, bool)], id: impl Fn(&T) -> i64) -> i64 {
+    rows.iter().map(|(x, _)| id(x)).max().unwrap_or(0) + 1
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn budgets(&self) -> Result<Vec<Budget>> {
+        let state = self.state();
+        Ok(state
+            .budgets
+            .iter()
+            .filter(|(_, trashed)| !trashed)
+            .map(|(x, _)| x.clone())
+            .collect())
+    }
+
+    async fn budget(&self, id: i64) -> Result<Option<Budget>> {
+        let state = self.state();
+        Ok(state
+            .budgets
+            .iter()
+            .find(|(x, trashed)| x.id == id && !trashed)
+            .map(|(x, _)| x.clone()))
+    }
+
+    async fn create_budget(&self, amount: f64, month: &str) -> Result<i64> {
+        let month = budget::parse_month(month)?;
+        let mut state = self.state();
+        match state.budgets.iter().find(|(x, _)| x.month == month) {
+            Some((x, true)) => return Err(Error::TrashedMonth { month, id: x.id }),
+            Some((x, false)) => return Err(Error::DuplicateMonth { month, id: x.id }),
+            None => {}
+        }
+        let id = next_id(&state.budgets, |x| x.id);
+        let uuid = new_uuid();
+        state.budgets.push((Budget { id, uuid, amount, month }, false));
+        Ok(id)
+    }
+
+    async fn find_or_create_budget(&self, month: &str, amount: f64) -> Result<i64> {
+        let month = budget::parse_month(month)?;
+        let mut state = self.state();
+        match state.budgets.iter().find(|(x, _)| x.month == month) {
+            Some((x, true)) => return Err(Error::TrashedMonth { month, id: x.id }),
+            Some((x, false)) => return Ok(x.id),
+            None => {}
+        }
+        let id = next_id(&state.budgets, |x| x.id);
+        let uuid = new_uuid();
+        state.budgets.push((Budget { id, uuid, amount, month }, false));
+        Ok(id)
+    }
+
+    async fn remove_budget(&self, id: i64) -> Result<bool> {
+        let mut state = self.state();
+        match state.budgets.iter_mut().find(|(x, trashed)| x.id == id && !*trashed) {
+            Some((_, trashed)) => {
+                *trashed = true;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn payments(
+        &self,
+        budget_id: i64,
+        filters: &Filters,
+        sort: SortKey,
+        descending: bool,
+    ) -> Result<Vec<Payment>> {
+        let state = self.state();
+        let mut payments: Vec<Payment> = state
+            .payments
+            .iter()
+            .filter(|(x, deleted)| x.budget_id == budget_id && !deleted && passes(x, filters))
+            .map(|(x, _)| x.clone())
+            .collect();
+        payments.sort_by(|a, b| {
+            let order = compare(a, b, sort);
+            match descending {
+                true => order.reverse(),
+                false => order,
+            }
+            .then(b.id.cmp(&a.id))
+        });
+        Ok(payments)
+    }
+
+    async fn payment(&self, id: i64) -> Result<Option<Payment>> {
+        let mut state = self.state();
+        Ok(state.live_payment(id).ok().cloned())
+    }
+
+    async fn total(&self, budget_id: i64) -> Result<f64> {
+        let state = self.state();
+        Ok(state
+            .payments
+            .iter()
+            .filter(|(x, deleted)| x.budget_id == budget_id && !deleted)
+            .map(|(x, _)| x.amount)
+            .sum())
+    }
+
+    async fn add_payment(
+        &self,
+        budget_id: i64,
+        kind: &str,
+        amount: f64,
+        payee: Option<&str>,
+    ) -> Result<i64> {
+        let kind = payment::check(kind, amount)?;
+        let mut state = self.state();
+        if !state.budgets.iter().any(|(x, trashed)| x.id == budget_id && !trashed) {
+            return Err(Error::NotFound { record: "budget", id: budget_id });
+        }
+        let payee = payee.map(str::trim).filter(|x| !x.is_empty());
+        let payee_id = payee.map(|name| state.payee_id(name));
+        let payee = payee_id.map(|x| state.payees[x as usize - 1].clone());
+        let id = next_id(&state.payments, |x| x.id);
+        state.payments.push((
+            Payment {
+                id,
+                uuid: new_uuid(),
+                amount,
+                budget_id,
+                kind: kind.to_string(),
+                day_of: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                payee_id,
+                payee,
+                memo: None,
+            },
+            false,
+        ));
+        Ok(id)
+    }
+
+    async fn set_memo(&self, id: i64, memo: Option<&str>) -> Result<()> {
+        let mut state = self.state();
+        state.live_payment(id)?.memo = memo.map(str::to_string);
+        Ok(())
+    }
+
+    async fn delete_payment(&self, id: i64) -> Result<()> {
+        let mut state = self.state();
+        match state.payments.iter_mut().find(|(x, deleted)| x.id == id && !*deleted) {
+            Some((_, deleted)) => {
+                *deleted = true;
+                Ok(())
+            }
+            None => Err(Error::NotFound { record: "payment", id }),
+        }
+    }
+
+    async fn id_of(&self, record: Record, uuid: &str) -> Result<Option<i64>> {
+        let state = self.state();
+        Ok(match record {
+            Record::Budget => state
+                .budgets
+                .iter()
+                .find(|(x, _)| x.uuid == uuid)
+                .map(|(x, _)| x.id),
+            Record::Payment => state
+                .payments
+                .iter()
+                .find(|(x, _)| x.uuid == uuid)
+                .map(|(x, _)| x.id),
+        })
+    }
+}
@@ -0,0 +1,76 @@
+use sqlx::{query, query_as, query_scalar, Pool, Sqlite};
+
+use crate::Result;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct Payee {
+    pub id: i64,
+    pub name: String,
+}
+
+/// Lifetime statistics for a single payee, across every budget
+#[derive(Debug, Clone, Default)]
+pub struct PayeeSummary {
+    pub id: i64,
+    pub uuid: String,
+    pub name: String,
+    pub visits: i64,
+    pub total: f64,
+    pub average: f64,
+    pub last_visit: Option<String>,
+}
+
+/// Returns the id of the payee called `name`, creating it if it doesn't exist yet
+pub async fn find_or_create(pool: &Pool<Sqlite>, name: &str) -> Result<i64> {
+    query!("INSERT OR IGNORE INTO payees (name) VALUES (?)", name)
+        .execute(pool)
+        .await?;
+
+    let id = query_scalar!(r#"SELECT id AS "id!" FROM payees WHERE name = ?"#, name)
+        .fetch_one(pool)
+        .await?;
+    Ok(id)
+}
+
+pub async fn names(pool: &Pool<Sqlite>) -> Result<Vec<String>> {
+    let names = query_scalar!("SELECT name FROM payees ORDER BY name")
+        .fetch_all(pool)
+        .await?;
+    Ok(names)
+}
+
+pub async fn summaries(pool: &Pool<Sqlite>) -> Result<Vec<PayeeSummary>> {
+    let summaries = query_as!(
+        PayeeSummary,
+        r#"SELECT payees.id AS "id!",
+                  payees.uuid AS "uuid!",
+                  payees.name AS "name!",
+                  COUNT(payments.id) AS "visits!: i64",
+                  COALESCE(SUM(payments.amount), 0.0) AS "total!: f64",
+                  COALESCE(AVG(payments.amount), 0.0) AS "average!: f64",
+                  MAX(payments.day_of) AS "last_visit?: String"
+           FROM payees
+           LEFT JOIN payments ON payments.payee_id = payees.id AND payments.deleted_at IS NULL
+           GROUP BY payees.id
+           ORDER BY 5 DESC"#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(summaries)
+}
+
+/// Finds the first known payee that starts with `prefix`, ignoring case
+pub fn complete<'a>(names: &'a [String], prefix: &str) -> Option<&'a str> {
+    if prefix.is_empty() {
+        return None;
+    }
+    let prefix = prefix.to_lowercase();
+    names
+        .iter()
+        .find(|name| {
+            let name = name.to_lowercase();
+            name.starts_with(&prefix) && name.len() > prefix.len()
+        })
+        .map(|name| name.as_str())
+}
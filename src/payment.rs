@@ -0,0 +1,188 @@
+use serde::Serialize;
+use sqlx::{query, query_as, query_scalar, Pool, Sqlite};
+
+use crate::filter::{Filters, SortKey};
+use crate::{budget, journal, payee, trash, Error, Result};
+
+#[derive(Default, Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Payment {
+    pub id: i64,
+    /// Stays the same in every database the payment is exported, backed up or synced to
+    pub uuid: String,
+    pub amount: f64,
+    pub budget_id: i64,
+    pub kind: String,
+    pub day_of: String,
+    pub payee_id: Option<i64>,
+    pub payee: Option<String>,
+    pub memo: Option<String>,
+}
+
+/// The payments of a budget that pass `filters`, ordered by `sort`
+pub async fn list(
+    pool: &Pool<Sqlite>,
+    budget_id: i64,
+    filters: &Filters,
+    sort: SortKey,
+    descending: bool,
+) -> Result<Vec<Payment>> {
+    let sort = sort.as_str();
+    let flow = filters.flow.as_str();
+    let payments = query_as!(
+        Payment,
+        r#"SELECT payments.id, payments.uuid AS "uuid!", payments.amount, payments.budget_id,
+                  payments.kind, payments.day_of, payments.payee_id, payees.name AS "payee?",
+                  payments.memo
+           FROM payments
+           LEFT JOIN payees ON payees.id = payments.payee_id
+           WHERE budget_id = ?1 AND payments.deleted_at IS NULL
+             AND (?2 IS NULL OR payments.amount >= ?2)
+             AND (?3 IS NULL OR payments.amount <= ?3)
+             AND (?4 IS NULL OR payments.kind LIKE '%' || ?4 || '%')
+             AND (?5 IS NULL OR date(payments.day_of) >= ?5)
+             AND (?6 IS NULL OR date(payments.day_of) <= ?6)
+             AND (?7 IS NULL OR (?7 = 'expense' AND payments.amount > 0)
+                             OR (?7 = 'income' AND payments.amount < 0))
+           ORDER BY
+             CASE WHEN ?9 THEN NULL ELSE
+               CASE ?8 WHEN 'date' THEN payments.day_of WHEN 'amount' THEN payments.amount
+                       WHEN 'kind' THEN payments.kind ELSE payments.id END
+             END ASC,
+             CASE WHEN ?9 THEN
+               CASE ?8 WHEN 'date' THEN payments.day_of WHEN 'amount' THEN payments.amount
+                       WHEN 'kind' THEN payments.kind ELSE payments.id END
+             END DESC,
+             payments.id DESC"#,
+        budget_id,
+        filters.min_amount,
+        filters.max_amount,
+        filters.kind,
+        filters.from,
+        filters.to,
+        flow,
+        sort,
+        descending
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(payments)
+}
+
+pub async fn get(pool: &Pool<Sqlite>, id: i64) -> Result<Option<Payment>> {
+    let payment = query_as!(
+        Payment,
+        r#"SELECT payments.id, payments.uuid AS "uuid!", payments.amount, payments.budget_id,
+                  payments.kind, payments.day_of, payments.payee_id, payees.name AS "payee?",
+                  payments.memo
+           FROM payments
+           LEFT JOIN payees ON payees.id = payments.payee_id
+           WHERE payments.id = ? AND payments.deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(payment)
+}
+
+/// Sum of every payment in the budget, regardless of any filters
+pub async fn total(pool: &Pool<Sqlite>, budget_id: i64) -> Result<f64> {
+    let total = query_scalar!(
+        r#"SELECT COALESCE(SUM(amount), 0.0) AS "total!: f64" FROM payments
+           WHERE budget_id = ? AND deleted_at IS NULL"#,
+        budget_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(total)
+}
+
+/// Whether the payment exists and isn't in the trash
+pub async fn exists(pool: &Pool<Sqlite>, id: i64) -> Result<bool> {
+    let found = query_scalar!(
+        r#"SELECT id AS "id!" FROM payments WHERE id = ? AND deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(found.is_some())
+}
+
+/// The id of the payment with `uuid`, even if it's in the trash
+pub async fn id_of(pool: &Pool<Sqlite>, uuid: &str) -> Result<Option<i64>> {
+    let id = query_scalar!(r#"SELECT id AS "id!" FROM payments WHERE uuid = ?"#, uuid)
+        .fetch_optional(pool)
+        .await?;
+    Ok(id)
+}
+
+/// Records a payment, creating the payee if it's new. Positive amounts are expenses and
+/// negative ones income
+pub async fn add(
+    pool: &Pool<Sqlite>,
+    budget_id: i64,
+    kind: &str,
+    amount: f64,
+    payee: Option<&str>,
+) -> Result<i64> {
+    let kind = check(kind, amount)?;
+    if budget::get(pool, budget_id).await?.is_none() {
+        return Err(Error::NotFound { record: "budget", id: budget_id });
+    }
+    let payee_id = match payee.map(str::trim) {
+        None | Some("") => None,
+        Some(name) => Some(payee::find_or_create(pool, name).await?),
+    };
+    let mut tx = pool.begin().await?;
+    let id = query!(
+        r#"INSERT INTO payments (amount, budget_id, kind, payee_id) VALUES (?, ?, ?, ?)"#,
+        amount,
+        budget_id,
+        kind,
+        payee_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    journal::record_add(&mut tx, id).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// Checks a new payment the same way for every storage backend, returning the trimmed kind
+pub fn check(kind: &str, amount: f64) -> Result<&str> {
+    let kind = kind.trim();
+    if kind.is_empty() {
+        return Err(Error::Invalid("a payment needs a kind".to_string()));
+    }
+    if !amount.is_finite() {
+        return Err(Error::Invalid(format!("{amount} isn't an amount")));
+    }
+    Ok(kind)
+}
+
+/// Sets or, with `None`, clears the memo
+pub async fn set_memo(pool: &Pool<Sqlite>, id: i64, memo: Option<&str>) -> Result<()> {
+    if !exists(pool, id).await? {
+        return Err(Error::NotFound { record: "payment", id });
+    }
+    let mut tx = pool.begin().await?;
+    let entry = journal::begin_edit(&mut tx, id).await?;
+    query!("UPDATE payments SET memo = ? WHERE id = ?", memo, id)
+        .execute(&mut *tx)
+        .await?;
+    journal::finish_edit(&mut tx, entry).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Moves the payment to the trash, where `budge undo` or the trash can bring it back
+pub async fn delete(pool: &Pool<Sqlite>, id: i64) -> Result<()> {
+    if !exists(pool, id).await? {
+        return Err(Error::NotFound { record: "payment", id });
+    }
+    let mut tx = pool.begin().await?;
+    journal::record_delete(&mut tx, id).await?;
+    trash::trash_payment(&mut tx, id).await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use async_trait::async_trait;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{query, query_as, query_scalar, Pool, Postgres};
+
+use crate::filter::{Filters, SortKey};
+use crate::storage::Storage;
+use crate::trash::Record;
+use crate::{budget, payment, Budget, Error, Payment, Result};
+
+/// Same format SQLite's `datetime('now')` writes
+const NOW: &str = "to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')";
+
+/// Connects to the server and brings its schema up to date
+pub async fn connect(url: &str) -> Result<Pool<Postgres>> {
+    let pool = PgPoolOptions::new().connect(url).await?;
+    sqlx::migrate!("./migrations-postgres").run(&pool).await?;
+    Ok(pool)
+}
+
+/// Unlike SQLite, PostgreSQL won't order by a `CASE` that mixes text and numbers, so the
+/// column is picked here instead
+fn order_by(sort: SortKey, descending: bool) -> String {
+    let column = match sort {
+        SortKey::Date => "payments.day_of",
+        SortKey::Amount => "payments.amount",
+        SortKey::Kind => "payments.kind",
+        SortKey::Id => "payments.id",
+    };
+    let direction = if descending { "DESC" } else { "ASC" };
+    format!("ORDER BY {column} {direction}, payments.id DESC")
+}
+
+const PAYMENTS: &str = r#"SELECT payments.id, payments.uuid, payments.amount, payments.budget_id,
+                                 payments.kind, payments.day_of, payments.payee_id,
+                                 payees.name AS payee, payments.memo
+                          FROM payments
+                          LEFT JOIN payees ON payees.id = payments.payee_id"#;
+
+/// For a household server that several people share. Nothing is recorded for `budge undo`
+#[async_trait]
+impl Storage for Pool<Postgres> {
+    async fn budgets(&self) -> Result<Vec<Budget>> {
+        let budgets = query_as(
+            "SELECT id, uuid, amount, month FROM budget WHERE deleted_at IS NULL ORDER BY id",
+        )
+        .fetch_all(self)
+        .await?;
+        Ok(budgets)
+    }
+
+    async fn budget(&self, id: i64) -> Result<Option<Budget>> {
+        let budget = query_as(
+            "SELECT id, uuid, amount, month FROM budget WHERE id = $1 AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+        Ok(budget)
+    }
+
+    async fn create_budget(&self, amount: f64, month: &str) -> Result<i64> {
+        let month = budget::parse_month(month)?;
+        let existing: Option<(i64, Option<String>)> =
+            query_as("SELECT id, deleted_at FROM budget WHERE month = $1")
+                .bind(&month)
+                .fetch_optional(self)
+                .await?;
+        match existing {
+            Some((id, Some(_))) => return Err(Error::TrashedMonth { month, id }),
+            Some((id, None)) => return Err(Error::DuplicateMonth { month, id }),
+            None => {}
+        }
+
+        let id = query_scalar("INSERT INTO budget (amount, month) VALUES ($1, $2) RETURNING id")
+            .bind(amount)
+            .bind(&month)
+            .fetch_one(self)
+            .await?;
+        Ok(id)
+    }
+
+    async fn find_or_create_budget(&self, month: &str, amount: f64) -> Result<i64> {
+        let month = budget::parse_month(month)?;
+        query("INSERT INTO budget (amount, month) VALUES ($1, $2) ON CONFLICT (month) DO NOTHING")
+            .bind(amount)
+            .bind(&month)
+            .execute(self)
+            .await?;
+
+        let (id, deleted_at): (i64, Option<String>) =
+            query_as("SELECT id, deleted_at FROM budget WHERE month = $1")
+                .bind(&month)
+                .fetch_one(self)
+                .await?;
+        match deleted_at {
+            Some(_) => Err(Error::TrashedMonth { month, id }),
+            None => Ok(id),
+        }
+    }
+
+    async fn remove_budget(&self, id: i64) -> Result<bool> {
+        let res = query(&format!(
+            "UPDATE budget SET deleted_at = {NOW} WHERE id = $1 AND deleted_at IS NULL"
+        ))
+        .bind(id)
+        .execute(self)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn payments(
+        &self,
+        budget_id: i64,
+        filters: &Filters,
+        sort: SortKey,
+        descending: bool,
+    ) -> Result<Vec<Payment>> {
+        let sql = format!(
+            r#"{PAYMENTS}
+               WHERE budget_id = $1 AND payments.deleted_at IS NULL
+                 AND ($2::float8 IS NULL OR payments.amount >= $2)
+                 AND ($3::float8 IS NULL OR payments.amount <= $3)
+                 AND ($4::text IS NULL OR payments.kind ILIKE '%' || $4 || '%')
+                 AND ($5::text IS NULL OR left(payments.day_of, 10) >= $5)
+                 AND ($6::text IS NULL OR left(payments.day_of, 10) <= $6)
+                 AND ($7::text IS NULL OR ($7 = 'expense' AND payments.amount > 0)
+                                       OR ($7 = 'income' AND payments.amount < 0))
+               {}"#,
+            order_by(sort, descending)
+        );
+        let payments = query_as(&sql)
+            .bind(budget_id)
+            .bind(filters.min_amount)
+            .bind(filters.max_amount)
+            .bind(&filters.kind)
+            .bind(&filters.from)
+            .bind(&filters.to)
+            .bind(filters.flow.as_str())
+            .fetch_all(self)
+            .await?;
+        Ok(payments)
+    }
+
+    async fn payment(&self, id: i64) -> Result<Option<Payment>> {
+        let payment = query_as(&format!(
+            "{PAYMENTS} WHERE payments.id = $1 AND payments.deleted_at IS NULL"
+        ))
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+        Ok(payment)
+    }
+
+    async fn total(&self, budget_id: i64) -> Result<f64> {
+        let total = query_scalar(
+            "SELECT COALESCE(SUM(amount), 0.0) FROM payments
+             WHERE budget_id = $1 AND deleted_at IS NULL",
+        )
+        .bind(budget_id)
+        .fetch_one(self)
+        .await?;
+        Ok(total)
+    }
+
+    async fn add_payment(
+        &self,
+        budget_id: i64,
+        kind: &str,
+        amount: f64,
+        payee: Option<&str>,
+    ) -> Result<i64> {
+        let kind = payment::check(kind, amount)?;
+        if self.budget(budget_id).await?.is_none() {
+            return Err(Error::NotFound { record: "budget", id: budget_id });
+        }
+        let mut tx = self.begin().await?;
+        let payee_id: Option<i64> = match payee.map(str::trim) {
+            None | Some("") => None,
+            Some(name) => {
+                query("INSERT INTO payees (name) VALUES ($1) ON CONFLICT DO NOTHING")
+                    .bind(name)
+                    .execute(&mut *tx)
+                    .await?;
+                query_scalar("SELECT id FROM payees WHERE lower(name) = lower($1)")
+                    .bind(name)
+                    .fetch_one(&mut *tx)
+                    .await?
+            }
+        };
+        let id = query_scalar(
+            "INSERT INTO payments (amount, budget_id, kind, payee_id)
+             VALUES ($1, $2, $3, $4) RETURNING id",
+        )
+        .bind(amount)
+        .bind(budget_id)
+        .bind(kind)
+        .bind(payee_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn set_memo(&self, id: i64, memo: Option<&str>) -> Result<()> {
+        let res = query("UPDATE payments SET memo = $1 WHERE id = $2 AND deleted_at IS NULL")
+            .bind(memo)
+            .bind(id)
+            .execute(self)
+            .await?;
+        match res.rows_affected() {
+            0 => Err(Error::NotFound { record: "payment", id }),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_payment(&self, id: i64) -> Result<()> {
+        let res = query(&format!(
+            "UPDATE payments SET deleted_at = {NOW} WHERE id = $1 AND deleted_at IS NULL"
+        ))
+        .bind(id)
+        .execute(self)
+        .await?;
+        match res.rows_affected() {
+            0 => Err(Error::NotFound { record: "payment", id }),
+            _ => Ok(()),
+        }
+    }
+
+    async fn id_of(&self, record: Record, uuid: &str) -> Result<Option<i64>> {
+        let sql = match record {
+            Record::Budget => "SELECT id FROM budget WHERE uuid = $1",
+            Record::Payment => "SELECT id FROM payments WHERE uuid = $1",
+        };
+        let id = query_scalar(sql).bind(uuid).fetch_optional(self).await?;
+        Ok(id)
+    }
+}
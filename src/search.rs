@@ -0,0 +1,75 @@
+use sqlx::{query_as, query_scalar, Pool, Sqlite};
+
+use crate::Result;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchHit {
+    pub id: i64,
+    pub uuid: String,
+    pub amount: f64,
+    pub kind: String,
+    pub day_of: String,
+    pub month: String,
+    pub payee: Option<String>,
+    pub memo: Option<String>,
+}
+
+/// Turns what the user typed into an FTS5 query, treating every word as a quoted prefix so
+/// that punctuation can't produce a syntax error and results show up while still typing
+pub fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+
+    match terms.is_empty() {
+        true => None,
+        false => Some(terms.join(" ")),
+    }
+}
+
+/// Searches payments in every budget, best matches first
+pub async fn search(pool: &Pool<Sqlite>, input: &str) -> Result<Vec<SearchHit>> {
+    let Some(fts) = fts_query(input) else {
+        return Ok(Vec::new());
+    };
+    let hits = query_as!(
+        SearchHit,
+        r#"SELECT payments.id AS "id!", payments.uuid AS "uuid!", payments.amount, payments.kind,
+                  payments.day_of, budget.month, payees.name AS "payee?", payments.memo
+           FROM payments_fts
+           JOIN payments ON payments.id = payments_fts.rowid
+           JOIN budget ON budget.id = payments.budget_id
+           LEFT JOIN payees ON payees.id = payments.payee_id
+           WHERE payments_fts MATCH ?
+             AND payments.deleted_at IS NULL AND budget.deleted_at IS NULL
+           ORDER BY payments_fts.rank"#,
+        fts
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(hits)
+}
+
+/// Ids of the payments in a single budget that match `input`
+pub async fn matching_ids(
+    pool: &Pool<Sqlite>,
+    input: &str,
+    budget_id: i64,
+) -> Result<Vec<i64>> {
+    let Some(fts) = fts_query(input) else {
+        return Ok(Vec::new());
+    };
+    let ids = query_scalar!(
+        r#"SELECT payments.id AS "id!"
+           FROM payments_fts
+           JOIN payments ON payments.id = payments_fts.rowid
+           WHERE payments_fts MATCH ? AND payments.budget_id = ?
+             AND payments.deleted_at IS NULL"#,
+        fts,
+        budget_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(ids)
+}
@@ -0,0 +1,429 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header::{AUTHORIZATION, ETAG, IF_MATCH, IF_NONE_MATCH, LOCATION, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+use crate::filter::{Filters, Flow, SortKey};
+use crate::storage::Ref;
+use crate::trash::Record;
+use crate::{Budget, Payment, Storage};
+
+const OPENAPI: &str = include_str!("../openapi.json");
+
+struct Server {
+    storage: Arc<dyn Storage>,
+    token: String,
+    /// Held from checking `If-Match` until the change is written, so that two requests based
+    /// on the same version can't both go through
+    writes: Mutex<()>,
+}
+
+type Shared = State<Arc<Server>>;
+
+/// An error response, always a JSON object with an `error` message
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<crate::Error> for ApiError {
+    fn from(e: crate::Error) -> ApiError {
+        let status = match e {
+            crate::Error::NotFound { .. } | crate::Error::UnknownUuid { .. } => {
+                StatusCode::NOT_FOUND
+            }
+            crate::Error::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            crate::Error::DuplicateMonth { .. } | crate::Error::TrashedMonth { .. } => {
+                StatusCode::CONFLICT
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, e.to_string())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> ApiError {
+        ApiError(e.status(), e.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(e: QueryRejection) -> ApiError {
+        ApiError(e.status(), e.body_text())
+    }
+}
+
+type ApiResult = Result<Response, ApiError>;
+
+/// The JSON API's routes, every one but `/openapi.json` behind `token`
+pub fn router(storage: Arc<dyn Storage>, token: String) -> Router {
+    let server = Arc::new(Server {
+        storage,
+        token,
+        writes: Mutex::new(()),
+    });
+    Router::new()
+        .route("/budgets", get(budgets).post(create_budget))
+        .route("/budgets/{id}", get(budget).delete(remove_budget))
+        .route("/budgets/{id}/payments", get(payments).post(add_payment))
+        .route("/budgets/{id}/report", get(report))
+        .route(
+            "/payments/{id}",
+            get(payment).patch(update_payment).delete(delete_payment),
+        )
+        .route("/categories", get(categories))
+        .route("/reports", get(reports))
+        .route_layer(middleware::from_fn_with_state(server.clone(), authorize))
+        .route("/openapi.json", get(openapi))
+        .with_state(server)
+}
+
+/// Serves the JSON API until interrupted
+pub async fn run(
+    storage: Arc<dyn Storage>,
+    bind: SocketAddr,
+    token: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let app = router(storage, token);
+    let listener = TcpListener::bind(bind).await?;
+    println!("Serving on http://{}", listener.local_addr()?);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await?;
+    Ok(())
+}
+
+/// Every route but the OpenAPI document needs `Authorization: Bearer <token>`
+async fn authorize(State(server): Shared, request: Request, next: Next) -> Response {
+    let given = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "));
+    match given {
+        Some(token) if same(token.as_bytes(), server.token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => (
+            [(WWW_AUTHENTICATE, "Bearer")],
+            ApiError(StatusCode::UNAUTHORIZED, "missing or wrong token".to_string()),
+        )
+            .into_response(),
+    }
+}
+
+/// Compares without stopping at the first difference, so timing doesn't give the token away
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn etag<T: Serialize>(value: &T) -> String {
+    let body = serde_json::to_vec(value).unwrap_or_default();
+    format!("\"{:.32x}\"", Sha256::digest(body))
+}
+
+/// A single record with its ETag, or 304 when the client already has this version
+fn tagged<T: Serialize>(headers: &HeaderMap, value: &T, status: StatusCode) -> Response {
+    let tag = etag(value);
+    if headers.get(IF_NONE_MATCH).is_some_and(|x| x.as_bytes() == tag.as_bytes()) {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, tag)]).into_response();
+    }
+    (status, [(ETAG, tag.clone())], Json(value)).into_response()
+}
+
+/// Changes and deletes must name the version they were based on in `If-Match`, so two people
+/// editing the same record can't silently overwrite each other
+fn check_version<T: Serialize>(headers: &HeaderMap, current: &T) -> Result<(), ApiError> {
+    let Some(given) = headers.get(IF_MATCH) else {
+        return Err(ApiError(
+            StatusCode::PRECONDITION_REQUIRED,
+            "send the ETag of the record in If-Match".to_string(),
+        ));
+    };
+    match given.as_bytes() == etag(current).as_bytes() || given == "*" {
+        true => Ok(()),
+        false => Err(ApiError(
+            StatusCode::PRECONDITION_FAILED,
+            "the record changed since it was read".to_string(),
+        )),
+    }
+}
+
+fn not_found(record: &'static str, id: i64) -> ApiError {
+    crate::Error::NotFound { record, id }.into()
+}
+
+/// Routes take either the id or the uuid of a budget or payment
+async fn resolve(server: &Server, record: Record, id: &str) -> Result<i64, ApiError> {
+    let reference = id
+        .parse::<Ref>()
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok(server.storage.resolve(record, &reference).await?)
+}
+
+async fn openapi() -> impl IntoResponse {
+    ([("content-type", "application/json")], OPENAPI)
+}
+
+async fn budgets(State(server): Shared) -> ApiResult {
+    Ok(Json(server.storage.budgets().await?).into_response())
+}
+
+async fn budget(State(server): Shared, Path(id): Path<String>, headers: HeaderMap) -> ApiResult {
+    let id = resolve(&server, Record::Budget, &id).await?;
+    let budget = server.storage.budget(id).await?.ok_or(not_found("budget", id))?;
+    Ok(tagged(&headers, &budget, StatusCode::OK))
+}
+
+#[derive(Deserialize)]
+struct NewBudget {
+    amount: f64,
+    month: String,
+}
+
+async fn create_budget(
+    State(server): Shared,
+    headers: HeaderMap,
+    new: Result<Json<NewBudget>, JsonRejection>,
+) -> ApiResult {
+    let Json(new) = new?;
+    let id = server.storage.create_budget(new.amount, &new.month).await?;
+    let budget = server.storage.budget(id).await?.ok_or(not_found("budget", id))?;
+    let mut response = tagged(&headers, &budget, StatusCode::CREATED);
+    if let Ok(location) = format!("/budgets/{id}").parse() {
+        response.headers_mut().insert(LOCATION, location);
+    }
+    Ok(response)
+}
+
+async fn remove_budget(
+    State(server): Shared,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let id = resolve(&server, Record::Budget, &id).await?;
+    let _writing = server.writes.lock().await;
+    let budget = server.storage.budget(id).await?.ok_or(not_found("budget", id))?;
+    check_version(&headers, &budget)?;
+    server.storage.remove_budget(id).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Same filters as the UI, with dates as `YYYY-MM-DD`
+#[derive(Deserialize)]
+struct PaymentQuery {
+    min_amount: Option<f64>,
+    max_amount: Option<f64>,
+    kind: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    flow: Option<Flow>,
+    sort: Option<SortKey>,
+    descending: Option<bool>,
+}
+
+fn date(value: Option<String>, name: &str) -> Result<Option<String>, ApiError> {
+    match value {
+        Some(x) if NaiveDate::parse_from_str(&x, "%Y-%m-%d").is_err() => Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("{name} should be YYYY-MM-DD"),
+        )),
+        value => Ok(value),
+    }
+}
+
+async fn payments(
+    State(server): Shared,
+    Path(id): Path<String>,
+    query: Result<Query<PaymentQuery>, QueryRejection>,
+) -> ApiResult {
+    let id = resolve(&server, Record::Budget, &id).await?;
+    let Query(query) = query?;
+    server.storage.budget(id).await?.ok_or(not_found("budget", id))?;
+    let filters = Filters {
+        min_amount: query.min_amount,
+        max_amount: query.max_amount,
+        kind: query.kind,
+        from: date(query.from, "from")?,
+        to: date(query.to, "to")?,
+        flow: query.flow.unwrap_or_default(),
+    };
+    let payments = server
+        .storage
+        .payments(
+            id,
+            &filters,
+            query.sort.unwrap_or_default(),
+            query.descending.unwrap_or(true),
+        )
+        .await?;
+    Ok(Json(payments).into_response())
+}
+
+#[derive(Deserialize)]
+struct NewPayment {
+    kind: String,
+    /// Positive for money spent, negative for money coming in
+    amount: f64,
+    payee: Option<String>,
+}
+
+async fn add_payment(
+    State(server): Shared,
+    Path(budget_id): Path<String>,
+    headers: HeaderMap,
+    new: Result<Json<NewPayment>, JsonRejection>,
+) -> ApiResult {
+    let Json(new) = new?;
+    let budget_id = resolve(&server, Record::Budget, &budget_id).await?;
+    let id = server
+        .storage
+        .add_payment(budget_id, &new.kind, new.amount, new.payee.as_deref())
+        .await?;
+    let payment = server.storage.payment(id).await?.ok_or(not_found("payment", id))?;
+    let mut response = tagged(&headers, &payment, StatusCode::CREATED);
+    if let Ok(location) = format!("/payments/{id}").parse() {
+        response.headers_mut().insert(LOCATION, location);
+    }
+    Ok(response)
+}
+
+async fn payment(State(server): Shared, Path(id): Path<String>, headers: HeaderMap) -> ApiResult {
+    let id = resolve(&server, Record::Payment, &id).await?;
+    let payment = server.storage.payment(id).await?.ok_or(not_found("payment", id))?;
+    Ok(tagged(&headers, &payment, StatusCode::OK))
+}
+
+#[derive(Deserialize)]
+struct PaymentChanges {
+    /// `null` clears the memo
+    memo: Option<String>,
+}
+
+async fn update_payment(
+    State(server): Shared,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    changes: Result<Json<PaymentChanges>, JsonRejection>,
+) -> ApiResult {
+    let id = resolve(&server, Record::Payment, &id).await?;
+    let Json(changes) = changes?;
+    let _writing = server.writes.lock().await;
+    let payment = server.storage.payment(id).await?.ok_or(not_found("payment", id))?;
+    check_version(&headers, &payment)?;
+    let memo = changes.memo.as_deref().map(str::trim).filter(|x| !x.is_empty());
+    server.storage.set_memo(id, memo).await?;
+    let payment = server.storage.payment(id).await?.ok_or(not_found("payment", id))?;
+    Ok(tagged(&HeaderMap::new(), &payment, StatusCode::OK))
+}
+
+async fn delete_payment(
+    State(server): Shared,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let id = resolve(&server, Record::Payment, &id).await?;
+    let _writing = server.writes.lock().await;
+    let payment = server.storage.payment(id).await?.ok_or(not_found("payment", id))?;
+    check_version(&headers, &payment)?;
+    server.storage.delete_payment(id).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Payments grouped by kind, which is what budge uses as a category
+#[derive(Serialize, Default)]
+struct Category {
+    kind: String,
+    count: usize,
+    total: f64,
+}
+
+fn by_kind<'a>(payments: impl Iterator<Item = &'a Payment>) -> Vec<Category> {
+    let mut kinds: BTreeMap<&str, Category> = BTreeMap::new();
+    for payment in payments {
+        let category = kinds.entry(&payment.kind).or_insert_with(|| Category {
+            kind: payment.kind.clone(),
+            ..Category::default()
+        });
+        category.count += 1;
+        category.total += payment.amount;
+    }
+    let mut categories: Vec<Category> = kinds.into_values().collect();
+    categories.sort_by(|a, b| b.total.total_cmp(&a.total));
+    categories
+}
+
+async fn all_payments(storage: &dyn Storage, budget: &Budget) -> crate::Result<Vec<Payment>> {
+    storage
+        .payments(budget.id, &Filters::default(), SortKey::default(), true)
+        .await
+}
+
+async fn categories(State(server): Shared) -> ApiResult {
+    let mut payments = Vec::new();
+    for budget in server.storage.budgets().await? {
+        payments.extend(all_payments(&*server.storage, &budget).await?);
+    }
+    Ok(Json(by_kind(payments.iter())).into_response())
+}
+
+#[derive(Serialize)]
+struct Report {
+    #[serde(flatten)]
+    budget: Budget,
+    spent: f64,
+    income: f64,
+    /// What's left of the budget, negative once it's overspent
+    remaining: f64,
+    categories: Vec<Category>,
+}
+
+async fn summarize(storage: &dyn Storage, budget: Budget) -> crate::Result<Report> {
+    let payments = all_payments(storage, &budget).await?;
+    let spent: f64 = payments.iter().map(|x| x.amount).filter(|x| *x > 0.0).sum();
+    let income: f64 = -payments.iter().map(|x| x.amount).filter(|x| *x < 0.0).sum::<f64>();
+    Ok(Report {
+        remaining: budget.amount - (spent - income),
+        categories: by_kind(payments.iter()),
+        budget,
+        spent,
+        income,
+    })
+}
+
+async fn report(State(server): Shared, Path(id): Path<String>) -> ApiResult {
+    let id = resolve(&server, Record::Budget, &id).await?;
+    let budget = server.storage.budget(id).await?.ok_or(not_found("budget", id))?;
+    Ok(Json(summarize(&*server.storage, budget).await?).into_response())
+}
+
+/// One report per month, oldest first
+async fn reports(State(server): Shared) -> ApiResult {
+    let mut budgets = server.storage.budgets().await?;
+    budgets.sort_by(|a, b| a.month.cmp(&b.month));
+    let mut reports = Vec::new();
+    for budget in budgets {
+        reports.push(summarize(&*server.storage, budget).await?);
+    }
+    Ok(Json(reports).into_response())
+}
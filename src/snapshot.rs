@@ -0,0 +1,166 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDateTime};
+use sqlx::{Pool, Sqlite};
+
+use crate::{Error, Result};
+
+const STAMP: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// A copy of the whole database, taken with `VACUUM INTO`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The file name, which is what `budge snapshots restore` takes
+    pub name: String,
+    pub path: PathBuf,
+    /// Local time
+    pub taken_at: NaiveDateTime,
+    /// What budge was about to do, or `daily`
+    pub reason: String,
+    pub size: u64,
+}
+
+/// Snapshots are kept next to the database, named after it so that profiles sharing a
+/// directory don't mix
+pub fn snapshots_dir(db_path: &Path) -> PathBuf {
+    db_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+        .join("budge-snapshots")
+}
+
+fn stem(db_path: &Path) -> String {
+    db_path
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "budge".to_string())
+}
+
+/// Every snapshot of the database, newest first
+pub fn list(db_path: &Path) -> Result<Vec<Snapshot>> {
+    let dir = snapshots_dir(db_path);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let prefix = format!("{}-", stem(db_path));
+    let mut snapshots = Vec::new();
+    for file in std::fs::read_dir(dir)? {
+        let file = file?;
+        let name = file.file_name().to_string_lossy().into_owned();
+        let Some(rest) = name
+            .strip_prefix(&prefix)
+            .and_then(|x| x.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        let mut parts = rest.splitn(3, '-');
+        let (Some(day), Some(time), Some(reason)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let Ok(taken_at) = NaiveDateTime::parse_from_str(&format!("{day}-{time}"), STAMP) else {
+            continue;
+        };
+        let metadata = file.metadata()?;
+        let snapshot = Snapshot {
+            path: file.path(),
+            taken_at,
+            reason: reason.to_string(),
+            size: metadata.len(),
+            name,
+        };
+        // Names only go down to the millisecond
+        snapshots.push((metadata.modified()?, snapshot));
+    }
+    snapshots.sort_by(|(a, x), (b, y)| y.taken_at.cmp(&x.taken_at).then(b.cmp(a)));
+    Ok(snapshots.into_iter().map(|(_, x)| x).collect())
+}
+
+/// Copies the database before something that can't be undone, keeping only the `keep` newest
+/// snapshots. Takes nothing when `keep` is 0
+pub async fn take(
+    pool: &Pool<Sqlite>,
+    db_path: &Path,
+    reason: &str,
+    keep: usize,
+) -> Result<Option<Snapshot>> {
+    if keep == 0 {
+        return Ok(None);
+    }
+    let name = copy(pool, db_path, reason).await?;
+    rotate(db_path, keep)?;
+    let snapshot = list(db_path)?.into_iter().find(|x| x.name == name);
+    Ok(snapshot)
+}
+
+async fn copy(pool: &Pool<Sqlite>, db_path: &Path, reason: &str) -> Result<String> {
+    let dir = snapshots_dir(db_path);
+    std::fs::create_dir_all(&dir)?;
+    let taken_at = Local::now().naive_local();
+    let name = format!("{}-{}-{reason}.db", stem(db_path), taken_at.format(STAMP));
+    let path = dir.join(&name);
+    // Another snapshot for the same reason within the same millisecond has the same contents
+    if !path.exists() {
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy())
+            .execute(pool)
+            .await?;
+    }
+    Ok(name)
+}
+
+fn rotate(db_path: &Path, keep: usize) -> Result<()> {
+    for old in list(db_path)?.iter().skip(keep) {
+        std::fs::remove_file(&old.path)?;
+    }
+    Ok(())
+}
+
+/// Takes the day's snapshot, unless there already is one
+pub async fn daily(pool: &Pool<Sqlite>, db_path: &Path, keep: usize) -> Result<Option<Snapshot>> {
+    let today = Local::now().date_naive();
+    let taken = list(db_path)?
+        .iter()
+        .any(|x| x.reason == "daily" && x.taken_at.date() == today);
+    match taken {
+        true => Ok(None),
+        false => take(pool, db_path, "daily", keep).await,
+    }
+}
+
+/// Replaces the database with a snapshot, after taking one of the current state so that the
+/// restore can be reverted too. Nothing else should have the database open
+pub async fn restore(
+    pool: Pool<Sqlite>,
+    db_path: &Path,
+    name: &str,
+    keep: usize,
+) -> Result<Snapshot> {
+    let snapshot = list(db_path)?
+        .into_iter()
+        .find(|x| x.name == name || x.name.strip_suffix(".db") == Some(name))
+        .ok_or_else(|| {
+            Error::Invalid(format!(
+                "no snapshot called {name}, see `budge snapshots list`"
+            ))
+        })?;
+    copy(&pool, db_path, "restore").await?;
+
+    // The snapshot is copied next to the database first and renamed over it, so that a copy
+    // that fails halfway leaves the database as it was
+    let mut restored = db_path.as_os_str().to_owned();
+    restored.push(".restore");
+    let restored = Path::new(&restored);
+    if let Err(e) = std::fs::copy(&snapshot.path, restored) {
+        let _ = std::fs::remove_file(restored);
+        return Err(e.into());
+    }
+
+    // Closing the last connection folds the write-ahead log back into the database
+    pool.close().await;
+    std::fs::rename(restored, db_path)?;
+    crate::db::remove_leftovers(db_path)?;
+    rotate(db_path, keep.max(1))?;
+    Ok(snapshot)
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use sqlx::{Pool, Sqlite};
+
+use crate::filter::{Filters, SortKey};
+use crate::trash::Record;
+use crate::{budget, payment, trash, Budget, Error, Payment, Result};
+
+/// How a budget or payment is named on the command line: by its id, which is short but only
+/// means something in one database, or by its uuid, which is the same everywhere
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ref {
+    Id(i64),
+    Uuid(String),
+}
+
+impl FromStr for Ref {
+    type Err = Error;
+
+    /// Numbers are ids. Uuids are read in any of their usual spellings
+    fn from_str(text: &str) -> Result<Ref> {
+        let text = text.trim();
+        if let Ok(id) = text.parse() {
+            return Ok(Ref::Id(id));
+        }
+        match uuid::Uuid::try_parse(text) {
+            Ok(uuid) => Ok(Ref::Uuid(uuid.to_string())),
+            Err(_) => Err(Error::Invalid(format!("{text:?} is neither an id nor a uuid"))),
+        }
+    }
+}
+
+impl fmt::Display for Ref {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ref::Id(id) => write!(f, "{id}"),
+            Ref::Uuid(uuid) => write!(f, "{uuid}"),
+        }
+    }
+}
+
+/// The budget and payment operations, so that the same code runs on SQLite, in memory or on
+/// PostgreSQL. Every backend validates input and reports missing records the same way
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Every budget that isn't in the trash
+    async fn budgets(&self) -> Result<Vec<Budget>>;
+    async fn budget(&self, id: i64) -> Result<Option<Budget>>;
+    async fn create_budget(&self, amount: f64, month: &str) -> Result<i64>;
+    /// Returns the id of the budget for `month`, creating it with `amount` if there isn't one.
+    /// Fails with [`Error::TrashedMonth`] when the month's budget is in the trash
+    async fn find_or_create_budget(&self, month: &str, amount: f64) -> Result<i64>;
+    /// Moves a budget to the trash, returning whether there was one to move
+    async fn remove_budget(&self, id: i64) -> Result<bool>;
+
+    /// The payments of a budget that pass `filters`, ordered by `sort`
+    async fn payments(
+        &self,
+        budget_id: i64,
+        filters: &Filters,
+        sort: SortKey,
+        descending: bool,
+    ) -> Result<Vec<Payment>>;
+    async fn payment(&self, id: i64) -> Result<Option<Payment>>;
+    /// Sum of every payment in the budget, regardless of any filters
+    async fn total(&self, budget_id: i64) -> Result<f64>;
+    async fn add_payment(
+        &self,
+        budget_id: i64,
+        kind: &str,
+        amount: f64,
+        payee: Option<&str>,
+    ) -> Result<i64>;
+    async fn set_memo(&self, id: i64, memo: Option<&str>) -> Result<()>;
+    /// Moves a payment to the trash
+    async fn delete_payment(&self, id: i64) -> Result<()>;
+
+    /// The id of the budget or payment with `uuid`, even if it's in the trash
+    async fn id_of(&self, record: Record, uuid: &str) -> Result<Option<i64>>;
+
+    /// The id `reference` stands for. Ids are taken as they are, whether or not they exist
+    async fn resolve(&self, record: Record, reference: &Ref) -> Result<i64> {
+        match reference {
+            Ref::Id(id) => Ok(*id),
+            Ref::Uuid(uuid) => self.id_of(record, uuid).await?.ok_or_else(|| {
+                Error::UnknownUuid {
+                    record: record.as_str(),
+                    uuid: uuid.clone(),
+                }
+            }),
+        }
+    }
+}
+
+/// Whether `db` names a PostgreSQL server rather than a SQLite file
+pub fn is_postgres(db: &str) -> bool {
+    db.starts_with("postgres://") || db.starts_with("postgresql://")
+}
+
+/// The default backend, which also records payment changes for `budge undo`
+#[async_trait]
+impl Storage for Pool<Sqlite> {
+    async fn budgets(&self) -> Result<Vec<Budget>> {
+        budget::list(self).await
+    }
+
+    async fn budget(&self, id: i64) -> Result<Option<Budget>> {
+        budget::get(self, id).await
+    }
+
+    async fn create_budget(&self, amount: f64, month: &str) -> Result<i64> {
+        budget::create(self, amount, month).await
+    }
+
+    async fn find_or_create_budget(&self, month: &str, amount: f64) -> Result<i64> {
+        budget::find_or_create(self, month, amount).await
+    }
+
+    async fn remove_budget(&self, id: i64) -> Result<bool> {
+        trash::trash_budget(self, id).await
+    }
+
+    async fn payments(
+        &self,
+        budget_id: i64,
+        filters: &Filters,
+        sort: SortKey,
+        descending: bool,
+    ) -> Result<Vec<Payment>> {
+        payment::list(self, budget_id, filters, sort, descending).await
+    }
+
+    async fn payment(&self, id: i64) -> Result<Option<Payment>> {
+        payment::get(self, id).await
+    }
+
+    async fn total(&self, budget_id: i64) -> Result<f64> {
+        payment::total(self, budget_id).await
+    }
+
+    async fn add_payment(
+        &self,
+        budget_id: i64,
+        kind: &str,
+        amount: f64,
+        payee: Option<&str>,
+    ) -> Result<i64> {
+        payment::add(self, budget_id, kind, amount, payee).await
+    }
+
+    async fn set_memo(&self, id: i64, memo: Option<&str>) -> Result<()> {
+        payment::set_memo(self, id, memo).await
+    }
+
+    async fn delete_payment(&self, id: i64) -> Result<()> {
+        payment::delete(self, id).await
+    }
+
+    async fn id_of(&self, record: Record, uuid: &str) -> Result<Option<i64>> {
+        match record {
+            Record::Budget => budget::id_of(self, uuid).await,
+            Record::Payment => payment::id_of(self, uuid).await,
+        }
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_scalar, Pool, Sqlite, SqliteConnection};
+
+use crate::{Error, Result};
+
+/// One state a budget or payment went through, as kept in the `changes` table. Every database
+/// logs its own changes, and syncing hands the logs around until every database has them all.
+/// The newest change of a record wins, by `at`, then `device`, then `id`, so that every
+/// database picks the same one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub id: String,
+    /// The database it was made in
+    pub device: String,
+    /// Milliseconds since the epoch, later than every change its database had seen by then
+    pub at: i64,
+    /// `budget` or `payment`
+    pub record: String,
+    /// The month of a budget, the uuid of a payment
+    pub key: String,
+    /// `put` or `delete`
+    pub op: String,
+    /// The whole record after a put
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct BudgetData {
+    uuid: String,
+    amount: f64,
+    month: String,
+    deleted_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PaymentData {
+    uuid: String,
+    amount: f64,
+    kind: String,
+    month: String,
+    day_of: String,
+    payee: Option<String>,
+    memo: Option<String>,
+    deleted_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Added,
+    Changed,
+    Removed,
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Added => "added",
+            Action::Changed => "changed",
+            Action::Removed => "removed",
+        }
+    }
+}
+
+/// A record that changed in this database because of a sync
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    /// `budget` or `payment`
+    pub record: String,
+    /// The id in this database
+    pub id: i64,
+    /// The month of a budget, the kind and date of a payment
+    pub label: String,
+    pub action: Action,
+}
+
+/// A record that both sides changed since they last synced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub record: String,
+    pub label: String,
+    /// Whether the change made here won
+    pub kept_ours: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Changes this database didn't have yet
+    pub received: usize,
+    pub updates: Vec<Update>,
+    pub conflicts: Vec<Conflict>,
+    /// The id this database signs its changes with from now on, when it turned out to be a copy
+    /// of another database that syncs through the same folder
+    pub new_device: Option<String>,
+}
+
+/// The id this database signs its changes with
+pub async fn device(pool: &Pool<Sqlite>) -> Result<String> {
+    let id = query_scalar!("SELECT id FROM sync_device")
+        .fetch_one(pool)
+        .await?;
+    Ok(id)
+}
+
+/// Gives this database an id of its own, for when it was copied from another one
+async fn new_device(pool: &Pool<Sqlite>) -> Result<String> {
+    let id = query_scalar!(
+        r#"UPDATE sync_device SET id = lower(hex(randomblob(16))) RETURNING id AS "id!""#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(id)
+}
+
+/// Every change this database knows of, oldest first
+pub async fn changes(pool: &Pool<Sqlite>) -> Result<Vec<Change>> {
+    let rows = query!(
+        r#"SELECT id AS "id!", device, at, record, key, op, data
+           FROM changes ORDER BY at, device, id"#
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|x| {
+            let data = x
+                .data
+                .map(|data| serde_json::from_str(&data))
+                .transpose()
+                .map_err(|e| Error::Invalid(format!("change {} is broken: {e}", x.id)))?;
+            Ok(Change {
+                id: x.id,
+                device: x.device,
+                at: x.at,
+                record: x.record,
+                key: x.key,
+                op: x.op,
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Checks a change from elsewhere before it goes into the log
+fn check(change: &Change) -> Result<()> {
+    let invalid = |why: &str| Err(Error::Invalid(format!("change {} {why}", change.id)));
+    let data = change.data.clone().unwrap_or_default();
+    let key = match (change.record.as_str(), change.op.as_str()) {
+        (_, "delete") => return Ok(()),
+        ("budget", "put") => serde_json::from_value::<BudgetData>(data).map(|x| x.month),
+        ("payment", "put") => serde_json::from_value::<PaymentData>(data).map(|x| x.uuid),
+        _ => return invalid("is neither a put nor a delete of a budget or payment"),
+    };
+    match key {
+        Ok(key) if key == change.key => Ok(()),
+        Ok(_) => invalid("has data for another record"),
+        Err(e) => invalid(&format!("has broken data: {e}")),
+    }
+}
+
+/// Adds the changes this database doesn't have yet, in a single transaction, and brings every
+/// record they touch to the state of its newest change
+pub async fn merge(pool: &Pool<Sqlite>, incoming: &[Change]) -> Result<Report> {
+    for change in incoming {
+        check(change)?;
+    }
+    let known: BTreeSet<&str> = incoming.iter().map(|x| x.id.as_str()).collect();
+    // What was changed here since the other side last heard from this database
+    let ours: BTreeSet<(String, String)> = changes(pool)
+        .await?
+        .into_iter()
+        .filter(|x| !known.contains(x.id.as_str()))
+        .map(|x| (x.record, x.key))
+        .collect();
+
+    let mut report = Report::default();
+    let mut tx = pool.begin().await?;
+    query!("UPDATE sync_device SET applying = 1")
+        .execute(&mut *tx)
+        .await?;
+
+    let mut touched = BTreeSet::new();
+    for change in incoming {
+        let data = change.data.as_ref().map(|x| x.to_string());
+        let added = query!(
+            r#"INSERT OR IGNORE INTO changes (id, device, at, record, key, op, data)
+               VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+            change.id,
+            change.device,
+            change.at,
+            change.record,
+            change.key,
+            change.op,
+            data
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if added > 0 {
+            report.received += 1;
+            touched.insert((change.record.clone(), change.key.clone()));
+        }
+    }
+
+    let mut winners = Vec::new();
+    for (record, key) in &touched {
+        let winner = query!(
+            r#"SELECT id AS "id!", op, data FROM changes WHERE record = ? AND key = ?
+               ORDER BY at DESC, device DESC, id DESC LIMIT 1"#,
+            record,
+            key
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if ours.contains(&(record.clone(), key.clone())) {
+            // A deleted payment is named after how it looked last
+            let last = query_scalar!(
+                r#"SELECT data AS "data!" FROM changes
+                   WHERE record = 'payment' AND key = ? AND data IS NOT NULL
+                   ORDER BY at DESC, device DESC, id DESC LIMIT 1"#,
+                key
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+            let label = match (record.as_str(), last) {
+                ("payment", Some(data)) => {
+                    let data: PaymentData = parse(&winner.id, &data)?;
+                    label(&data.kind, &data.day_of)
+                }
+                _ => key.clone(),
+            };
+            report.conflicts.push(Conflict {
+                record: record.clone(),
+                label,
+                kept_ours: !known.contains(winner.id.as_str()),
+            });
+        }
+        winners.push((record.as_str(), key.as_str(), winner));
+    }
+    // Budgets come first, since payments need their month. Deleted months go before the
+    // others, so that a budget that moved to another month can take its uuid along
+    winners.sort_by_key(|(record, _, winner)| (*record == "payment", winner.op == "put"));
+
+    for (record, key, winner) in winners {
+        let update = match (record, winner.data) {
+            ("budget", Some(data)) => {
+                Vec::from_iter(put_budget(&mut tx, parse(&winner.id, &data)?).await?)
+            }
+            ("budget", None) => delete_budget(&mut tx, key).await?,
+            (_, Some(data)) => {
+                Vec::from_iter(put_payment(&mut tx, parse(&winner.id, &data)?).await?)
+            }
+            (_, None) => Vec::from_iter(delete_payment(&mut tx, key).await?),
+        };
+        report.updates.extend(update);
+    }
+
+    query!("UPDATE sync_device SET applying = 0")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(report)
+}
+
+fn parse<T: serde::de::DeserializeOwned>(id: &str, data: &str) -> Result<T> {
+    serde_json::from_str(data).map_err(|e| Error::Invalid(format!("change {id} is broken: {e}")))
+}
+
+fn label(kind: &str, day_of: &str) -> String {
+    format!("{kind} on {}", day_of.get(..10).unwrap_or(day_of))
+}
+
+async fn put_budget(conn: &mut SqliteConnection, data: BudgetData) -> Result<Option<Update>> {
+    let current = query!(
+        r#"SELECT id AS "id!", uuid, amount, deleted_at FROM budget WHERE month = ?"#,
+        data.month
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    // Budgets made for the same month on two devices take the uuid of the newest change
+    let taken = query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM budget WHERE uuid = ? AND month <> ?)",
+        data.uuid,
+        data.month
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let uuid = (taken == 0).then_some(&data.uuid);
+    let update = |id, action| Update {
+        record: "budget".to_string(),
+        id,
+        label: data.month.clone(),
+        action,
+    };
+    match current {
+        Some(current) => {
+            query!(
+                "UPDATE budget SET uuid = coalesce(?, uuid) WHERE id = ?",
+                uuid,
+                current.id
+            )
+            .execute(&mut *conn)
+            .await?;
+            if current.amount == data.amount && current.deleted_at == data.deleted_at {
+                return Ok(None);
+            }
+            query!(
+                "UPDATE budget SET amount = ?, deleted_at = ? WHERE id = ?",
+                data.amount,
+                data.deleted_at,
+                current.id
+            )
+            .execute(&mut *conn)
+            .await?;
+            Ok(Some(update(current.id, Action::Changed)))
+        }
+        None => {
+            let id = query!(
+                "INSERT INTO budget (uuid, amount, month, deleted_at) VALUES (?, ?, ?, ?)",
+                uuid,
+                data.amount,
+                data.month,
+                data.deleted_at
+            )
+            .execute(&mut *conn)
+            .await?
+            .last_insert_rowid();
+            Ok(Some(update(id, Action::Added)))
+        }
+    }
+}
+
+/// Takes the month's payments along
+async fn delete_budget(conn: &mut SqliteConnection, month: &str) -> Result<Vec<Update>> {
+    let Some(id) = query_scalar!(r#"SELECT id AS "id!" FROM budget WHERE month = ?"#, month)
+        .fetch_optional(&mut *conn)
+        .await?
+    else {
+        return Ok(Vec::new());
+    };
+    let mut updates: Vec<Update> = query!(
+        r#"SELECT id AS "id!", kind, day_of FROM payments WHERE budget_id = ? ORDER BY id"#,
+        id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|x| Update {
+        record: "payment".to_string(),
+        id: x.id,
+        label: label(&x.kind, &x.day_of),
+        action: Action::Removed,
+    })
+    .collect();
+    query!("DELETE FROM budget WHERE id = ?", id)
+        .execute(&mut *conn)
+        .await?;
+    updates.insert(
+        0,
+        Update {
+            record: "budget".to_string(),
+            id,
+            label: month.to_string(),
+            action: Action::Removed,
+        },
+    );
+    Ok(updates)
+}
+
+/// A payment whose month was deleted goes with it
+async fn put_payment(conn: &mut SqliteConnection, data: PaymentData) -> Result<Option<Update>> {
+    let Some(budget_id) = query_scalar!(
+        r#"SELECT id AS "id!" FROM budget WHERE month = ?"#,
+        data.month
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return delete_payment(conn, &data.uuid).await;
+    };
+    let payee_id = match &data.payee {
+        Some(name) => {
+            query!("INSERT OR IGNORE INTO payees (name) VALUES (?)", name)
+                .execute(&mut *conn)
+                .await?;
+            query_scalar!(r#"SELECT id AS "id!" FROM payees WHERE name = ?"#, name)
+                .fetch_optional(&mut *conn)
+                .await?
+        }
+        None => None,
+    };
+    let current = query!(
+        r#"SELECT id AS "id!", amount, kind, budget_id, day_of, payee_id, memo, deleted_at
+           FROM payments WHERE uuid = ?"#,
+        data.uuid
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    let update = |id, action| Update {
+        record: "payment".to_string(),
+        id,
+        label: label(&data.kind, &data.day_of),
+        action,
+    };
+    match current {
+        Some(x)
+            if (
+                x.amount,
+                &x.kind,
+                x.budget_id,
+                &x.day_of,
+                x.payee_id,
+                &x.memo,
+                &x.deleted_at,
+            ) == (
+                data.amount,
+                &data.kind,
+                budget_id,
+                &data.day_of,
+                payee_id,
+                &data.memo,
+                &data.deleted_at,
+            ) =>
+        {
+            Ok(None)
+        }
+        Some(x) => {
+            query!(
+                r#"UPDATE payments SET amount = ?, kind = ?, budget_id = ?, day_of = ?,
+                                       payee_id = ?, memo = ?, deleted_at = ?
+                   WHERE id = ?"#,
+                data.amount,
+                data.kind,
+                budget_id,
+                data.day_of,
+                payee_id,
+                data.memo,
+                data.deleted_at,
+                x.id
+            )
+            .execute(&mut *conn)
+            .await?;
+            Ok(Some(update(x.id, Action::Changed)))
+        }
+        None => {
+            let id = query!(
+                r#"INSERT INTO payments (uuid, amount, kind, budget_id, day_of, payee_id, memo,
+                                         deleted_at)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+                data.uuid,
+                data.amount,
+                data.kind,
+                budget_id,
+                data.day_of,
+                payee_id,
+                data.memo,
+                data.deleted_at
+            )
+            .execute(&mut *conn)
+            .await?
+            .last_insert_rowid();
+            Ok(Some(update(id, Action::Added)))
+        }
+    }
+}
+
+async fn delete_payment(conn: &mut SqliteConnection, uuid: &str) -> Result<Option<Update>> {
+    let Some(x) = query!(
+        r#"SELECT id AS "id!", kind, day_of FROM payments WHERE uuid = ?"#,
+        uuid
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+    query!("DELETE FROM payments WHERE id = ?", x.id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(Some(Update {
+        record: "payment".to_string(),
+        id: x.id,
+        label: label(&x.kind, &x.day_of),
+        action: Action::Removed,
+    }))
+}
+
+/// Merges two databases both ways, so that they end up with the same budgets and payments.
+/// Returns what changed here, then what changed in `other`
+pub async fn with_database(pool: &Pool<Sqlite>, other: &Pool<Sqlite>) -> Result<(Report, Report)> {
+    let theirs = changes(other).await?;
+    let ours = changes(pool).await?;
+    let here = merge(pool, &theirs).await?;
+    let there = merge(other, &ours).await?;
+    Ok((here, there))
+}
+
+/// Where a database keeps its changes in a shared folder
+pub fn changes_file(dir: &Path, device: &str) -> PathBuf {
+    dir.join(format!("budge-{device}.jsonl"))
+}
+
+/// Merges the changes every database left in a shared folder, such as a synced directory or a
+/// git checkout, then writes the ones made here to this database's own file there. No two
+/// databases write the same file, so the folder itself never conflicts. A database copied from
+/// another one starts out with the same file, and takes a new one when that file has changes
+/// it doesn't know of
+pub async fn with_folder(pool: &Pool<Sqlite>, dir: &Path) -> Result<Report> {
+    let mut device = device(pool).await?;
+    let known: BTreeSet<String> = changes(pool).await?.into_iter().map(|x| x.id).collect();
+    let mut incoming: Vec<Change> = Vec::new();
+    // What the other files have, and what this database's own file had
+    let mut elsewhere = BTreeSet::new();
+    let mut written = BTreeSet::new();
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<std::io::Result<_>>()?;
+    files.sort();
+    for file in files {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        if !name.starts_with("budge-") || !name.ends_with(".jsonl") {
+            continue;
+        }
+        let own = file == changes_file(dir, &device);
+        for (n, line) in std::fs::read_to_string(&file)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let change: Change = serde_json::from_str(line).map_err(|e| {
+                Error::Invalid(format!("{}:{}: {e}", file.to_string_lossy(), n + 1))
+            })?;
+            match own {
+                true => written.insert(change.id.clone()),
+                false => elsewhere.insert(change.id.clone()),
+            };
+            incoming.push(change);
+        }
+    }
+    let mut report = merge(pool, &incoming).await?;
+    if !written.is_subset(&known) {
+        device = new_device(pool).await?;
+        report.new_device = Some(device.clone());
+        elsewhere.append(&mut written);
+    }
+
+    // Changes made here before a copy got its own id are only in the folder once it writes them
+    let mut lines = String::new();
+    for change in changes(pool).await? {
+        if change.device != device && elsewhere.contains(&change.id) {
+            continue;
+        }
+        lines += &serde_json::to_string(&change).map_err(|e| Error::Invalid(e.to_string()))?;
+        lines += "\n";
+    }
+    // Written next to it and renamed, so that a folder syncing in the meantime never sees half
+    let file = changes_file(dir, &device);
+    let partial = dir.join(format!(".budge-{device}.jsonl.partial"));
+    std::fs::write(&partial, lines)?;
+    std::fs::rename(partial, file)?;
+    Ok(report)
+}
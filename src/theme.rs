@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+
+use ratatui::style::{Color, Modifier, Style};
+
+/// Every style the UI draws with
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub border: Style,
+    pub title: Style,
+    /// Title of the input that has focus
+    pub focused: Style,
+    /// Secondary text, like autocompletion and the sort order
+    pub dim: Style,
+    pub header: Style,
+    /// Every other row of a table
+    pub zebra: Style,
+    pub selected: Style,
+    pub search_match: Style,
+    pub chip: Style,
+    pub scrollbar: Style,
+    pub under_budget: Style,
+    pub over_budget: Style,
+    pub income: Style,
+    pub gauge_label: Style,
+    /// Rejected input and error toasts
+    pub error: Style,
+}
+
+impl Theme {
+    pub const BUILT_IN: [&'static str; 4] = ["dark", "light", "high-contrast", "mono"];
+
+    pub fn dark() -> Theme {
+        Theme {
+            border: Style::new().fg(Color::Red),
+            title: Style::new().fg(Color::White),
+            focused: Style::new().fg(Color::Yellow),
+            dim: Style::new().fg(Color::DarkGray),
+            header: Style::new().add_modifier(Modifier::BOLD),
+            zebra: Style::new().bg(Color::Black),
+            selected: Style::new().add_modifier(Modifier::REVERSED),
+            search_match: Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            chip: Style::new().fg(Color::Black).bg(Color::Yellow),
+            scrollbar: Style::new().fg(Color::Gray),
+            under_budget: Style::new().fg(Color::Yellow),
+            over_budget: Style::new().fg(Color::Red),
+            income: Style::new().fg(Color::Green),
+            gauge_label: Style::new().fg(Color::Black),
+            error: Style::new().fg(Color::LightRed).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            border: Style::new().fg(Color::Blue),
+            title: Style::new().fg(Color::Black),
+            focused: Style::new().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            dim: Style::new().fg(Color::DarkGray),
+            zebra: Style::new().bg(Color::Indexed(254)),
+            search_match: Style::new().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            chip: Style::new().fg(Color::White).bg(Color::Blue),
+            scrollbar: Style::new().fg(Color::DarkGray),
+            under_budget: Style::new().fg(Color::Blue),
+            gauge_label: Style::new().fg(Color::White),
+            error: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ..Theme::dark()
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        Theme {
+            border: Style::new().fg(Color::White),
+            title: Style::new().fg(Color::White).add_modifier(Modifier::BOLD),
+            focused: Style::new()
+                .fg(Color::LightYellow)
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            dim: Style::new().fg(Color::Gray),
+            zebra: Style::new(),
+            search_match: Style::new().fg(Color::Black).bg(Color::LightYellow),
+            chip: Style::new().fg(Color::Black).bg(Color::White),
+            scrollbar: Style::new().fg(Color::White),
+            under_budget: Style::new().fg(Color::LightCyan),
+            over_budget: Style::new().fg(Color::LightRed),
+            income: Style::new().fg(Color::LightGreen),
+            gauge_label: Style::new().fg(Color::Black).bg(Color::White),
+            error: Style::new().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD),
+            ..Theme::dark()
+        }
+    }
+
+    /// No colors at all, only bold, underline and reverse video. Used when `NO_COLOR` is set
+    pub fn mono() -> Theme {
+        let plain = Style::new();
+        Theme {
+            border: plain,
+            title: plain,
+            focused: Style::new().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            dim: Style::new().add_modifier(Modifier::DIM),
+            header: Style::new().add_modifier(Modifier::BOLD),
+            zebra: plain,
+            selected: Style::new().add_modifier(Modifier::REVERSED),
+            search_match: Style::new().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            chip: Style::new().add_modifier(Modifier::REVERSED),
+            scrollbar: plain,
+            under_budget: plain,
+            over_budget: Style::new().add_modifier(Modifier::BOLD),
+            income: plain,
+            gauge_label: Style::new().add_modifier(Modifier::REVERSED),
+            error: Style::new().add_modifier(Modifier::BOLD),
+        }
+    }
+
+    fn built_in(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            "mono" => Some(Theme::mono()),
+            _ => None,
+        }
+    }
+
+    /// Picks the theme named in the config file. User themes live under `[themes.<name>]`,
+    /// start from the theme named by their `base` key (dark by default) and override styles
+    /// by name. A non-empty `NO_COLOR` always wins
+    pub fn from_config(
+        name: &str,
+        themes: &BTreeMap<String, BTreeMap<String, String>>,
+    ) -> Result<Theme, Box<dyn std::error::Error>> {
+        // Still check the configured theme, so that NO_COLOR doesn't hide a broken one
+        let theme = Theme::named(name, themes, 0)?;
+        if std::env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty()) {
+            return Ok(Theme::mono());
+        }
+        Ok(theme)
+    }
+
+    fn named(
+        name: &str,
+        themes: &BTreeMap<String, BTreeMap<String, String>>,
+        depth: usize,
+    ) -> Result<Theme, Box<dyn std::error::Error>> {
+        let Some(table) = themes.get(name) else {
+            return Theme::built_in(name).ok_or_else(|| {
+                format!(
+                    "unknown theme {name:?}, the built in ones are {}",
+                    Theme::BUILT_IN.join(", ")
+                )
+                .into()
+            });
+        };
+        if depth > themes.len() {
+            return Err(format!("the base of theme {name:?} leads back to itself").into());
+        }
+
+        let mut theme = match table.get("base") {
+            Some(base) => Theme::named(base, themes, depth + 1)?,
+            None => Theme::dark(),
+        };
+        for (slot, spec) in table.iter().filter(|(slot, _)| *slot != "base") {
+            let style = parse_style(spec).map_err(|e| format!("theme {name:?}, {slot}: {e}"))?;
+            *theme
+                .slot_mut(slot)
+                .ok_or_else(|| format!("theme {name:?} has an unknown style {slot:?}"))? = style;
+        }
+        Ok(theme)
+    }
+
+    fn slot_mut(&mut self, slot: &str) -> Option<&mut Style> {
+        Some(match slot {
+            "border" => &mut self.border,
+            "title" => &mut self.title,
+            "focused" => &mut self.focused,
+            "dim" => &mut self.dim,
+            "header" => &mut self.header,
+            "zebra" => &mut self.zebra,
+            "selected" => &mut self.selected,
+            "search-match" => &mut self.search_match,
+            "chip" => &mut self.chip,
+            "scrollbar" => &mut self.scrollbar,
+            "under-budget" => &mut self.under_budget,
+            "over-budget" => &mut self.over_budget,
+            "income" => &mut self.income,
+            "gauge-label" => &mut self.gauge_label,
+            "error" => &mut self.error,
+            _ => return None,
+        })
+    }
+
+    /// Style for the nth row of a table
+    pub fn row(&self, i: usize) -> Style {
+        match i % 2 {
+            0 => self.zebra,
+            _ => Style::new(),
+        }
+    }
+}
+
+/// Reads a style written like `yellow`, `black on yellow`, `bold #ff8800` or `reversed`
+pub fn parse_style(spec: &str) -> Result<Style, String> {
+    let mut style = Style::new();
+    let mut words = spec.split_whitespace();
+    while let Some(word) = words.next() {
+        style = match word {
+            "bold" => style.add_modifier(Modifier::BOLD),
+            "dim" => style.add_modifier(Modifier::DIM),
+            "italic" => style.add_modifier(Modifier::ITALIC),
+            "underlined" => style.add_modifier(Modifier::UNDERLINED),
+            "reversed" => style.add_modifier(Modifier::REVERSED),
+            "on" => {
+                let color = words.next().ok_or("expected a color after \"on\"")?;
+                style.bg(color.parse().map_err(|_| format!("unknown color {color:?}"))?)
+            }
+            color => style.fg(color.parse().map_err(|_| format!("unknown color {color:?}"))?),
+        };
+    }
+    Ok(style)
+}
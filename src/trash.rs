@@ -0,0 +1,175 @@
+use clap::ValueEnum;
+use sqlx::{query, query_as, query_scalar, Pool, Sqlite, SqliteConnection};
+
+use crate::{journal, Result};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Record {
+    Budget,
+    Payment,
+}
+
+impl Record {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Record::Budget => "budget",
+            Record::Payment => "payment",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrashItem {
+    pub record: Record,
+    pub id: i64,
+    pub uuid: String,
+    pub description: String,
+    pub deleted_at: String,
+}
+
+struct Trashed {
+    id: i64,
+    uuid: String,
+    description: String,
+    deleted_at: String,
+}
+
+pub async fn trash_budget(pool: &Pool<Sqlite>, id: i64) -> Result<bool> {
+    let res = query!(
+        "UPDATE budget SET deleted_at = datetime('now') WHERE id = ? AND deleted_at IS NULL",
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn trash_payment(conn: &mut SqliteConnection, id: i64) -> Result<bool> {
+    let res = query!(
+        "UPDATE payments SET deleted_at = datetime('now') WHERE id = ? AND deleted_at IS NULL",
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// The trashed budget still holding `month`, which stops a new budget from taking it
+pub async fn trashed_month(pool: &Pool<Sqlite>, month: &str) -> Result<Option<i64>> {
+    let id = query_scalar!(
+        r#"SELECT id AS "id!" FROM budget WHERE month = ? AND deleted_at IS NOT NULL"#,
+        month
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(id)
+}
+
+/// Everything in the trash, most recently deleted first
+pub async fn list(pool: &Pool<Sqlite>) -> Result<Vec<TrashItem>> {
+    let budgets = query_as!(
+        Trashed,
+        r#"SELECT id AS "id!", uuid AS "uuid!",
+                  'budget for ' || month || ' (' || amount || ')' AS "description!: String",
+                  deleted_at AS "deleted_at!"
+           FROM budget WHERE deleted_at IS NOT NULL"#
+    )
+    .fetch_all(pool)
+    .await?;
+    let payments = query_as!(
+        Trashed,
+        r#"SELECT payments.id AS "id!", payments.uuid AS "uuid!",
+                  payments.amount || ' ' || payments.kind || ' on ' || payments.day_of
+                      || COALESCE(' in ' || budget.month, '') AS "description!: String",
+                  payments.deleted_at AS "deleted_at!"
+           FROM payments
+           LEFT JOIN budget ON budget.id = payments.budget_id
+           WHERE payments.deleted_at IS NOT NULL"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut items: Vec<TrashItem> = budgets
+        .into_iter()
+        .map(|x| (Record::Budget, x))
+        .chain(payments.into_iter().map(|x| (Record::Payment, x)))
+        .map(|(record, x)| TrashItem {
+            record,
+            id: x.id,
+            uuid: x.uuid,
+            description: x.description,
+            deleted_at: x.deleted_at,
+        })
+        .collect();
+    items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+    Ok(items)
+}
+
+/// Takes a record out of the trash, returning whether it was in there
+pub async fn restore(pool: &Pool<Sqlite>, record: Record, id: i64) -> Result<bool> {
+    let res = match record {
+        Record::Budget => {
+            query!(
+                "UPDATE budget SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL",
+                id
+            )
+            .execute(pool)
+            .await?
+        }
+        Record::Payment => {
+            query!(
+                "UPDATE payments SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL",
+                id
+            )
+            .execute(pool)
+            .await?
+        }
+    };
+    Ok(res.rows_affected() > 0)
+}
+
+/// Permanently deletes a single trashed record. Purging a budget takes its payments with it,
+/// while a purged payment can still be brought back by undo as long as its budget is there
+pub async fn purge_one(pool: &Pool<Sqlite>, record: Record, id: i64) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let res = match record {
+        Record::Budget => {
+            query!(
+                r#"DELETE FROM payments WHERE budget_id IN
+                       (SELECT id FROM budget WHERE id = ? AND deleted_at IS NOT NULL)"#,
+                id
+            )
+            .execute(&mut *tx)
+            .await?;
+            query!("DELETE FROM budget WHERE id = ? AND deleted_at IS NOT NULL", id)
+                .execute(&mut *tx)
+                .await?
+        }
+        Record::Payment => {
+            query!("DELETE FROM payments WHERE id = ? AND deleted_at IS NOT NULL", id)
+                .execute(&mut *tx)
+                .await?
+        }
+    };
+    journal::forget_purged(&mut tx).await?;
+    tx.commit().await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Empties the trash, returning how many records were permanently deleted
+pub async fn purge(pool: &Pool<Sqlite>) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let payments = query!(
+        r#"DELETE FROM payments WHERE deleted_at IS NOT NULL
+               OR budget_id IN (SELECT id FROM budget WHERE deleted_at IS NOT NULL)"#
+    )
+    .execute(&mut *tx)
+    .await?;
+    let budgets = query!("DELETE FROM budget WHERE deleted_at IS NOT NULL")
+        .execute(&mut *tx)
+        .await?;
+    journal::forget_purged(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(payments.rows_affected() + budgets.rows_affected())
+}
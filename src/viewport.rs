@@ -0,0 +1,72 @@
+/// The selected row of a list and the rows of it that fit on screen. Every change clamps,
+/// so an empty list or a terminal too small to show anything never needs special casing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Viewport {
+    selected: usize,
+    offset: usize,
+    len: usize,
+    height: usize,
+}
+
+impl Viewport {
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Index of the first visible row
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Call when the list is reloaded, keeping the selection as close as possible
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len;
+        self.select(self.selected);
+    }
+
+    /// Call before drawing with the number of rows that fit
+    pub fn set_height(&mut self, height: usize) {
+        self.height = height;
+        self.follow();
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.selected = index.min(self.len.saturating_sub(1));
+        self.follow();
+    }
+
+    pub fn down(&mut self, rows: usize) {
+        self.select(self.selected.saturating_add(rows));
+    }
+
+    pub fn up(&mut self, rows: usize) {
+        self.select(self.selected.saturating_sub(rows));
+    }
+
+    pub fn page_down(&mut self) {
+        self.down(self.height.max(1));
+    }
+
+    pub fn page_up(&mut self) {
+        self.up(self.height.max(1));
+    }
+
+    pub fn top(&mut self) {
+        self.select(0);
+    }
+
+    pub fn bottom(&mut self) {
+        self.select(self.len.saturating_sub(1));
+    }
+
+    /// Scrolls just enough to keep the selection visible
+    fn follow(&mut self) {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.height > 0 && self.selected >= self.offset + self.height {
+            self.offset = self.selected + 1 - self.height;
+        }
+        let last_page = self.len.saturating_sub(self.height.max(1));
+        self.offset = self.offset.min(last_page);
+    }
+}
@@ -0,0 +1,149 @@
+//! Backs databases up to JSON and restores them into new and existing databases
+mod common;
+
+use budge::backup::{self, Backup, Conflict};
+use budge::db::{self, DbPool};
+use budge::filter::{Filters, SortKey};
+use budge::{trash, Error, Storage};
+
+/// The shared books, with a trashed payment in February and a trashed budget for December
+async fn books() -> DbPool {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    common::books(&pool).await;
+    let february = pool.create_budget(400.0, "2025-02").await.unwrap();
+    let old = pool.create_budget(100.0, "2024-12").await.unwrap();
+    let coffee = pool
+        .add_payment(february, "coffee", 3.5, Some("costco"))
+        .await
+        .unwrap();
+    pool.delete_payment(coffee).await.unwrap();
+    pool.add_payment(old, "rent", 900.0, None).await.unwrap();
+    trash::trash_budget(&pool, old).await.unwrap();
+    pool
+}
+
+/// Everything a backup holds, leaving out when it was taken
+async fn contents(pool: &DbPool) -> Backup {
+    Backup {
+        created_at: String::new(),
+        ..backup::backup(pool).await.unwrap()
+    }
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let source = books().await;
+    let json = serde_json::to_string(&backup::backup(&source).await.unwrap()).unwrap();
+    let backup = backup::read(&json).unwrap();
+    assert_eq!(backup.version, backup::VERSION);
+
+    let target = db::connect("sqlite::memory:").await.unwrap();
+    let restored = backup::restore(&target, &backup, Conflict::Fail)
+        .await
+        .unwrap();
+    assert_eq!((restored.budgets, restored.payments), (3, 5));
+    assert_eq!(contents(&target).await, contents(&source).await);
+    assert_eq!(target.budgets().await.unwrap().len(), 2);
+    assert_eq!(trash::list(&target).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn conflicts() {
+    let backup = backup::backup(&books().await).await.unwrap();
+    let target = db::connect("sqlite::memory:").await.unwrap();
+    let mine = target.create_budget(50.0, "2025-01").await.unwrap();
+    target
+        .add_payment(mine, "books", 20.0, Some("COSTCO"))
+        .await
+        .unwrap();
+    let before = contents(&target).await;
+
+    assert!(matches!(
+        backup::restore(&target, &backup, Conflict::Fail).await,
+        Err(Error::Invalid(x)) if x.contains("2025-01")
+    ));
+    assert_eq!(contents(&target).await, before);
+
+    let restored = backup::restore(&target, &backup, Conflict::Skip)
+        .await
+        .unwrap();
+    assert_eq!(restored.skipped, ["2025-01"]);
+    assert_eq!((restored.budgets, restored.payments), (2, 2));
+    let kept = target
+        .payments(mine, &Filters::default(), SortKey::Id, false)
+        .await
+        .unwrap();
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].kind, "books");
+    // The payee is shared with the one already here, whatever its case
+    let payees: Vec<_> = contents(&target).await.payees.into_iter().map(|x| x.name).collect();
+    assert_eq!(payees, ["COSTCO", "Joe's \"Diner\" \\ Grill"]);
+
+    let restored = backup::restore(&target, &backup, Conflict::Overwrite)
+        .await
+        .unwrap();
+    assert_eq!(restored.overwritten, ["2025-01", "2025-02", "2024-12"]);
+    let january = target
+        .budgets()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|x| x.month == "2025-01")
+        .unwrap();
+    assert_eq!(january.amount, 500.0);
+    assert_eq!(target.total(january.id).await.unwrap(), 42.5 - 2500.0 + 0.1);
+}
+
+#[tokio::test]
+async fn validation() {
+    let backup = backup::backup(&books().await).await.unwrap();
+    let json = serde_json::to_value(&backup).unwrap();
+    let broken = |change: fn(&mut serde_json::Value)| {
+        let mut json = json.clone();
+        change(&mut json);
+        backup::read(&json.to_string())
+    };
+
+    assert!(backup::read("{}").is_err());
+    assert!(matches!(
+        broken(|x| x["version"] = (backup::VERSION + 1).into()),
+        Err(Error::Invalid(x)) if x.contains("upgrade")
+    ));
+    assert!(broken(|x| x["budgets"][0]["month"] = "2025/1".into()).is_err());
+    assert!(broken(|x| x["budgets"][1]["month"] = "2025-01".into()).is_err());
+    assert!(broken(|x| x["payments"][0]["budget_id"] = 99.into()).is_err());
+    assert!(broken(|x| x["payments"][0]["payee_id"] = 99.into()).is_err());
+    assert!(broken(|x| x["payments"][0]["kind"] = " ".into()).is_err());
+    assert!(broken(|x| x["payees"][0]["name"] = "".into()).is_err());
+    assert!(broken(|x| x["budgets"][0]["uuid"] = "b1".into()).is_err());
+    assert!(broken(|x| x["payments"][1]["uuid"] = x["payments"][0]["uuid"].clone()).is_err());
+    assert!(broken(|_| {}).is_ok());
+}
+
+#[tokio::test]
+async fn without_uuids() {
+    let source = books().await;
+    let mut json = serde_json::to_value(backup::backup(&source).await.unwrap()).unwrap();
+    json["version"] = 1.into();
+    for table in ["budgets", "payees", "payments", "attachments"] {
+        for row in json[table].as_array_mut().unwrap() {
+            row.as_object_mut().unwrap().remove("uuid");
+        }
+    }
+
+    // Backups from before uuids get new ones, and the same ones when restored again
+    let backup = backup::read(&json.to_string()).unwrap();
+    let target = db::connect("sqlite::memory:").await.unwrap();
+    backup::restore(&target, &backup, Conflict::Fail)
+        .await
+        .unwrap();
+    let restored = contents(&target).await;
+    assert!(restored.payments.iter().all(|x| x.uuid.is_some()));
+    assert_ne!(restored.payments, contents(&source).await.payments);
+
+    let copy = db::connect("sqlite::memory:").await.unwrap();
+    backup::restore(&copy, &restored, Conflict::Fail)
+        .await
+        .unwrap();
+    assert_eq!(contents(&copy).await, restored);
+}
@@ -0,0 +1,34 @@
+//! Helpers shared by the integration tests, each of which uses only some of them
+#![allow(dead_code)]
+use std::path::PathBuf;
+
+use budge::Storage;
+
+/// A new database in its own directory, named after the test file and the test, so that tests
+/// don't see each other's files
+pub fn scratch(prefix: &str, test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("budge-{prefix}-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("books.db")
+}
+
+/// Seeds the month most tests start from, January 2025, with groceries, a salary and a payee
+/// that needs escaping. Returns the id of its budget
+pub async fn books(storage: &dyn Storage) -> i64 {
+    let january = storage.create_budget(500.0, "2025-01").await.unwrap();
+    let groceries = storage
+        .add_payment(january, "groceries", 42.5, Some("Costco"))
+        .await
+        .unwrap();
+    storage.set_memo(groceries, Some("cheese")).await.unwrap();
+    storage
+        .add_payment(january, "salary", -2500.0, None)
+        .await
+        .unwrap();
+    storage
+        .add_payment(january, "eating out", 0.1, Some("Joe's \"Diner\" \\ Grill"))
+        .await
+        .unwrap();
+    january
+}
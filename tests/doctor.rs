@@ -0,0 +1,97 @@
+//! Finds the problems older versions and hand edits leave behind, and repairs what it can
+use budge::{db, doctor, Storage};
+
+#[test]
+fn months() {
+    assert_eq!(
+        doctor::normalize_month("2025-01").as_deref(),
+        Some("2025-01")
+    );
+    for spelling in ["2025-1", "2025/01", "202501", " 2025-01 "] {
+        assert_eq!(
+            doctor::normalize_month(spelling).as_deref(),
+            Some("2025-01")
+        );
+    }
+    for broken in ["2025-13", "2025-00", "25-01", "January", "", "20€1", "2€/01"] {
+        assert_eq!(doctor::normalize_month(broken), None, "{broken}");
+    }
+}
+
+#[tokio::test]
+async fn check_and_fix() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let january = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let rent = pool.add_payment(january, "rent", 90.0, None).await.unwrap();
+    assert!(doctor::check(&pool, false).await.unwrap().is_empty());
+
+    // Foreign keys are only checked per connection, so one without them can break things
+    let mut conn = pool.acquire().await.unwrap();
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    for (id, month) in [(10, "2025-1"), (11, "2025/02"), (12, "someday")] {
+        sqlx::query("INSERT INTO budget (id, amount, month) VALUES (?, 50, ?)")
+            .bind(id)
+            .bind(month)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+    }
+    for (id, budget_id, day_of) in [
+        (20, 10, "2025-01-03 10:00:00"),
+        (21, 99, "2025-01-03 10:00:00"),
+        (22, 11, "yesterday"),
+    ] {
+        sqlx::query(
+            "INSERT INTO payments (id, amount, kind, budget_id, day_of) VALUES (?, 5, 'tea', ?, ?)",
+        )
+        .bind(id)
+        .bind(budget_id)
+        .bind(day_of)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    }
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE payments SET day_of = '2025-01-05T08:00:00' WHERE id = ?")
+        .bind(rent)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    drop(conn);
+
+    let problems = doctor::check(&pool, false).await.unwrap();
+    let descriptions: Vec<&str> = problems.iter().map(|x| x.description.as_str()).collect();
+    assert_eq!(problems.len(), 6, "{descriptions:#?}");
+    assert!(descriptions[0].starts_with("payment 21 belongs to budget 99"));
+    assert_eq!(problems.iter().filter(|x| !x.repairable).count(), 1);
+    // Only looking changes nothing
+    assert_eq!(doctor::check(&pool, false).await.unwrap().len(), 6);
+
+    doctor::check(&pool, true).await.unwrap();
+    let left = doctor::check(&pool, false).await.unwrap();
+    assert_eq!(left.len(), 1);
+    assert!(!left[0].repairable);
+    let months: Vec<String> = pool
+        .budgets()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|x| x.month)
+        .collect();
+    assert_eq!(months, ["2025-01", "2025-02", "someday"]);
+    // The duplicate month's payment moved to the budget that was already there
+    assert_eq!(pool.total(january).await.unwrap(), 95.0);
+    assert!(pool.payment(21).await.unwrap().is_none());
+    let payment = pool.payment(22).await.unwrap().unwrap();
+    assert_eq!(payment.day_of, "2025-02-01 00:00:00");
+    assert_eq!(
+        pool.payment(rent).await.unwrap().unwrap().day_of,
+        "2025-01-05 08:00:00"
+    );
+}
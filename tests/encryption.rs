@@ -0,0 +1,83 @@
+//! Encrypts, rekeys and decrypts databases in a scratch directory. Everything but telling
+//! encrypted files apart needs the `sqlcipher` feature
+mod common;
+
+use budge::{db, encryption};
+#[cfg(feature = "sqlcipher")]
+use budge::{snapshot, Error, Storage};
+use common::scratch;
+
+#[tokio::test]
+async fn detection() {
+    let path = scratch("encryption", "detection");
+    assert!(!encryption::is_encrypted(&path).unwrap());
+    std::fs::write(&path, "").unwrap();
+    assert!(!encryption::is_encrypted(&path).unwrap());
+    db::connect(&path.to_string_lossy()).await.unwrap();
+    assert!(!encryption::is_encrypted(&path).unwrap());
+    std::fs::write(&path, [0x5a; 4096]).unwrap();
+    assert!(encryption::is_encrypted(&path).unwrap());
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[cfg(feature = "sqlcipher")]
+#[tokio::test]
+async fn keys() {
+    let path = scratch("encryption", "keys");
+    let url = path.to_string_lossy().into_owned();
+    let pool = db::connect(&url).await.unwrap();
+    let id = pool.create_budget(100.0, "2025-01").await.unwrap();
+    pool.add_payment(id, "rent", 90.0, None).await.unwrap();
+
+    // Quotes in a passphrase don't end up in the SQL
+    encryption::rewrite(pool, &path, "it's 'secret'")
+        .await
+        .unwrap();
+    assert!(encryption::is_encrypted(&path).unwrap());
+    assert!(!std::fs::read(&path)
+        .unwrap()
+        .windows(4)
+        .any(|x| x == b"rent"));
+    assert!(db::connect(&url).await.is_err());
+    assert!(matches!(
+        db::connect_with_key(&url, "it's").await,
+        Err(Error::Invalid(x)) if x.contains("passphrase")
+    ));
+
+    let pool = db::connect_with_key(&url, "it's 'secret'").await.unwrap();
+    assert_eq!(pool.total(id).await.unwrap(), 90.0);
+    encryption::rewrite(pool, &path, "new").await.unwrap();
+    assert!(db::connect_with_key(&url, "it's 'secret'").await.is_err());
+
+    let pool = db::connect_with_key(&url, "new").await.unwrap();
+    pool.add_payment(id, "coffee", 3.0, None).await.unwrap();
+    encryption::rewrite(pool, &path, "").await.unwrap();
+    assert!(!encryption::is_encrypted(&path).unwrap());
+    let pool = db::connect(&url).await.unwrap();
+    assert_eq!(pool.total(id).await.unwrap(), 93.0);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[cfg(feature = "sqlcipher")]
+#[tokio::test]
+async fn snapshots() {
+    let path = scratch("encryption", "snapshots");
+    let url = path.to_string_lossy().into_owned();
+    let pool = db::connect(&url).await.unwrap();
+    encryption::rewrite(pool, &path, "secret").await.unwrap();
+
+    // Snapshots of an encrypted database are encrypted with the same passphrase
+    let pool = db::connect_with_key(&url, "secret").await.unwrap();
+    let id = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let taken = snapshot::take(&pool, &path, "purge", 3)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(encryption::is_encrypted(&taken.path).unwrap());
+    pool.close().await;
+    let copy = db::connect_with_key(&taken.path.to_string_lossy(), "secret")
+        .await
+        .unwrap();
+    assert_eq!(copy.budgets().await.unwrap()[0].id, id);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
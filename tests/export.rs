@@ -0,0 +1,476 @@
+//! Exports journals and reads them back with a strict parser for each format, checking that
+//! every transaction is well formed, balances to zero and keeps the payment's date, account and
+//! exact amount. When `ledger`, `hledger` or `bean-check` is installed, it checks the output too
+mod common;
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use budge::export::{self, Format, Options};
+use budge::memory::MemoryStorage;
+use budge::{Error, Payment, Storage};
+use chrono::NaiveDate;
+
+#[derive(Debug, Default)]
+struct Transaction {
+    date: String,
+    /// Payee and narration for beancount, the whole description otherwise
+    strings: Vec<String>,
+    meta: Vec<(String, String)>,
+    /// Account and amount
+    postings: Vec<(String, String)>,
+}
+
+impl Transaction {
+    fn meta(&self, key: &str) -> Option<&str> {
+        self.meta
+            .iter()
+            .find(|(x, _)| x == key)
+            .map(|(_, x)| x.as_str())
+    }
+}
+
+fn is_date(text: &str) -> bool {
+    text.len() == 10 && NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok()
+}
+
+fn is_amount(text: &str) -> bool {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, "0"));
+    !whole.is_empty()
+        && !fraction.is_empty()
+        && whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|x| x.is_ascii_digit())
+}
+
+fn is_account(format: Format, text: &str) -> bool {
+    let parts: Vec<&str> = text.split(':').collect();
+    if parts.iter().any(|x| x.is_empty()) {
+        return false;
+    }
+    match format {
+        Format::Beancount => {
+            ["Assets", "Liabilities", "Equity", "Income", "Expenses"].contains(&parts[0])
+                && parts.len() > 1
+                && parts.iter().all(|x| {
+                    let first = x.chars().next().unwrap();
+                    (first.is_uppercase() || first.is_ascii_digit())
+                        && x.chars().all(|c| c.is_alphanumeric() || c == '-')
+                })
+        }
+        _ => !text.contains("  ") && !text.contains('\t') && !text.starts_with(' '),
+    }
+}
+
+fn is_commodity(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    chars.len() >= 2
+        && chars[0].is_ascii_uppercase()
+        && chars
+            .iter()
+            .all(|x| x.is_ascii_uppercase() || x.is_ascii_digit())
+}
+
+/// Reads the quoted strings of a beancount line, undoing the escapes
+fn strings(mut text: &str) -> Result<Vec<String>, String> {
+    let mut found = Vec::new();
+    while !text.is_empty() {
+        text = text
+            .strip_prefix('"')
+            .ok_or(format!("expected a string at {text:?}"))?;
+        let mut value = String::new();
+        let mut chars = text.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, x @ ('"' | '\\'))) => value.push(x),
+                    other => return Err(format!("bad escape {other:?}")),
+                },
+                Some((i, '"')) => break i,
+                Some((_, '\n')) | None => return Err("unterminated string".to_string()),
+                Some((_, x)) => value.push(x),
+            }
+        };
+        found.push(value);
+        text = &text[end + 1..];
+        if !text.is_empty() {
+            text = text.strip_prefix(' ').ok_or("strings must be apart")?;
+        }
+    }
+    Ok(found)
+}
+
+/// The accounts declared or opened, and every transaction, failing on anything the real tools
+/// would reject
+fn parse(format: Format, journal: &str) -> Result<(BTreeSet<String>, Vec<Transaction>), String> {
+    let indent = if format == Format::Beancount {
+        "  "
+    } else {
+        "    "
+    };
+    let mut accounts = BTreeSet::new();
+    let mut transactions: Vec<Transaction> = Vec::new();
+    let mut commodity = None;
+    for (number, line) in journal.lines().enumerate() {
+        let fail = |why: &str| Err(format!("line {}: {why}: {line:?}", number + 1));
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix(indent) {
+            let Some(transaction) = transactions.last_mut() else {
+                return fail("indented line outside a transaction");
+            };
+            if rest.starts_with(' ') {
+                return fail("wrong indent");
+            }
+            let meta = match format {
+                Format::Beancount => rest
+                    .split_once(": ")
+                    .filter(|(key, _)| {
+                        key.starts_with(|x: char| x.is_ascii_lowercase())
+                            && key
+                                .chars()
+                                .all(|x| x.is_ascii_alphanumeric() || "-_".contains(x))
+                    })
+                    .map(|(key, value)| {
+                        let value = match is_amount(value) {
+                            true => Ok(value.to_string()),
+                            false => strings(value).and_then(|x| match &x[..] {
+                                [one] => Ok(one.clone()),
+                                _ => Err("metadata needs one value".to_string()),
+                            }),
+                        };
+                        value.map(|x| (key.to_string(), x))
+                    }),
+                _ => rest.strip_prefix("; ").map(|x| match x.split_once(": ") {
+                    Some((key, value)) => Ok((key.to_string(), value.to_string())),
+                    None => Ok((String::new(), x.to_string())),
+                }),
+            };
+            match meta {
+                Some(Ok(meta)) if transaction.postings.is_empty() => {
+                    transaction.meta.push(meta);
+                    continue;
+                }
+                Some(Ok(_)) => return fail("metadata after the postings"),
+                Some(Err(why)) => return fail(&why),
+                None => {}
+            }
+            let Some((account, amount)) = rest.split_once("  ") else {
+                return fail("posting without two spaces before the amount");
+            };
+            let Some((amount, unit)) = amount.split_once(' ') else {
+                return fail("amount without a commodity");
+            };
+            if !is_account(format, account) {
+                return fail("malformed account");
+            }
+            if !accounts.contains(account) {
+                return fail("account used before it was declared");
+            }
+            if !is_amount(amount) || Some(unit) != commodity.as_deref() {
+                return fail("malformed amount");
+            }
+            transaction
+                .postings
+                .push((account.to_string(), amount.to_string()));
+            continue;
+        }
+
+        let (first, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match (format, first) {
+            (Format::Ledger | Format::Hledger, "commodity") if is_commodity(rest) => {
+                commodity = Some(rest.to_string())
+            }
+            (Format::Ledger | Format::Hledger, "account") if is_account(format, rest) => {
+                accounts.insert(rest.to_string());
+            }
+            (Format::Beancount, "option") => match strings(rest)?[..] {
+                [ref key, ref value] if key == "operating_currency" && is_commodity(value) => {
+                    commodity = Some(value.clone())
+                }
+                _ => return fail("unknown option"),
+            },
+            (_, date) if is_date(date) => {
+                if let Some(account) = rest.strip_prefix("open ") {
+                    let Some((account, unit)) = account.split_once(' ') else {
+                        return fail("open without a commodity");
+                    };
+                    if format != Format::Beancount || !is_account(format, account) {
+                        return fail("malformed open");
+                    }
+                    if Some(unit) != commodity.as_deref() {
+                        return fail("opened for another commodity");
+                    }
+                    accounts.insert(account.to_string());
+                    continue;
+                }
+                let Some(description) = rest.strip_prefix("* ") else {
+                    return fail("transaction without a flag");
+                };
+                let description = match description.strip_prefix('(') {
+                    Some(code) => match code.split_once(") ") {
+                        Some((_, description)) => description,
+                        None => return fail("unterminated code"),
+                    },
+                    None => description,
+                };
+                let strings = match format {
+                    Format::Beancount => strings(description)?,
+                    _ if description.trim() != description || description.contains("  ") => {
+                        return fail("description would be cut short");
+                    }
+                    _ => vec![description.to_string()],
+                };
+                if strings.is_empty() || strings.len() > 2 {
+                    return fail("expected a payee and narration");
+                }
+                transactions.push(Transaction {
+                    date: date.to_string(),
+                    strings,
+                    ..Transaction::default()
+                });
+            }
+            _ => return fail("unknown directive"),
+        }
+    }
+
+    for transaction in &transactions {
+        let scale = transaction
+            .postings
+            .iter()
+            .map(|(_, x)| x.split_once('.').map_or(0, |(_, x)| x.len()))
+            .max()
+            .unwrap_or(0);
+        let sum: i128 = transaction
+            .postings
+            .iter()
+            .map(|(_, amount)| {
+                let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+                let digits = format!("{whole}{fraction:0<scale$}");
+                digits.parse::<i128>().unwrap()
+            })
+            .sum();
+        if transaction.postings.len() < 2 || sum != 0 {
+            return Err(format!(
+                "transaction on {} doesn't balance",
+                transaction.date
+            ));
+        }
+    }
+    Ok((accounts, transactions))
+}
+
+/// Runs one of the real tools on the journal, if it's installed
+fn check_with(format: Format, journal: &str) {
+    let path = std::env::temp_dir().join(format!("budge-export-{}.journal", std::process::id()));
+    std::fs::write(&path, journal).unwrap();
+    let (program, args): (&str, Vec<&str>) = match format {
+        Format::Ledger => ("ledger", vec!["--pedantic", "-f"]),
+        Format::Hledger => ("hledger", vec!["check", "accounts", "commodities", "-f"]),
+        Format::Beancount => ("bean-check", vec![]),
+    };
+    let output = Command::new(program)
+        .args(args)
+        .arg(&path)
+        .args(if format == Format::Ledger {
+            &["balance"][..]
+        } else {
+            &[]
+        })
+        .stdin(Stdio::null())
+        .output();
+    std::fs::remove_file(&path).unwrap();
+    if let Ok(output) = output {
+        let mut stderr = std::io::stderr();
+        stderr.write_all(&output.stderr).unwrap();
+        assert!(output.status.success(), "{program} rejected the export");
+    }
+}
+
+/// Every payment appears once, with its date, account and the very same amount
+fn assert_round_trip(format: Format, journal: &str, payments: &[Payment], options: &Options) {
+    let (_, transactions) = parse(format, journal).unwrap_or_else(|e| panic!("{e}\n{journal}"));
+    assert_eq!(transactions.len(), payments.len());
+    for payment in payments {
+        let id = payment.id.to_string();
+        let found = transactions
+            .iter()
+            .find(|x| x.meta("budge-id") == Some(&id))
+            .unwrap_or_else(|| panic!("payment {id} is missing"));
+        assert_eq!(found.date, payment.day_of[..10]);
+        assert_eq!(found.meta("budge-uuid"), Some(payment.uuid.as_str()));
+        let [(category, spent), (asset, paid)] = &found.postings[..] else {
+            panic!("payment {id} should have two postings");
+        };
+        assert_eq!(*category, options.account(&payment.kind, payment.amount));
+        assert_eq!(*asset, options.asset_account);
+        assert_eq!(spent.parse::<f64>().unwrap(), payment.amount);
+        assert_eq!(paid.parse::<f64>().unwrap(), -payment.amount);
+
+        let kind = payment
+            .kind
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let payee = payment.payee.as_deref();
+        match format {
+            Format::Beancount => match payee {
+                Some(payee) => assert_eq!(found.strings, [payee, kind.as_str()]),
+                None => assert_eq!(found.strings, [kind.as_str()]),
+            },
+            Format::Ledger => {
+                assert_eq!(found.strings, [payee.unwrap_or(&kind)]);
+                if payee.is_some() {
+                    assert_eq!(found.meta("kind"), Some(kind.as_str()));
+                }
+            }
+            Format::Hledger => match payee {
+                Some(payee) => assert_eq!(found.strings, [format!("{payee} | {kind}")]),
+                None => assert_eq!(found.strings, [kind.as_str()]),
+            },
+        }
+        if let Some(memo) = &payment.memo {
+            let memo = memo.split_whitespace().collect::<Vec<_>>().join(" ");
+            assert_eq!(found.meta("memo"), Some(memo.as_str()));
+        }
+    }
+}
+
+async fn payments(storage: &dyn Storage) -> Vec<Payment> {
+    let mut payments = Vec::new();
+    for budget in storage.budgets().await.unwrap() {
+        payments.extend(
+            storage
+                .payments(budget.id, &Default::default(), Default::default(), false)
+                .await
+                .unwrap(),
+        );
+    }
+    payments
+}
+
+/// The shared books, with awkward kinds, memos and amounts in February and March
+async fn books() -> MemoryStorage {
+    let storage = MemoryStorage::new();
+    common::books(&storage).await;
+    let february = storage.create_budget(500.0, "2025-02").await.unwrap();
+    let march = storage.create_budget(500.0, "2025-03").await.unwrap();
+    let odd = storage
+        .add_payment(february, "très-chic!", 1234567.891, None)
+        .await
+        .unwrap();
+    storage
+        .set_memo(odd, Some("two\nlines;  and spaces"))
+        .await
+        .unwrap();
+    storage
+        .add_payment(february, "fees", 0.30000000000000004, Some("Bank"))
+        .await
+        .unwrap();
+    storage
+        .add_payment(march, "refund", -19.99, Some("(Shop)"))
+        .await
+        .unwrap();
+    let deleted = storage
+        .add_payment(march, "rent", 900.0, None)
+        .await
+        .unwrap();
+    storage.delete_payment(deleted).await.unwrap();
+    storage
+}
+
+#[tokio::test]
+async fn formats() {
+    let storage = books().await;
+    let payments = payments(&storage).await;
+    let options = Options::default();
+    for format in [Format::Ledger, Format::Hledger, Format::Beancount] {
+        let journal = export::export(&storage, format, None, None, &options)
+            .await
+            .unwrap();
+        assert_round_trip(format, &journal, &payments, &options);
+        check_with(format, &journal);
+    }
+}
+
+#[tokio::test]
+async fn months() {
+    let storage = books().await;
+    let february: Vec<Payment> = payments(&storage)
+        .await
+        .into_iter()
+        .filter(|x| x.budget_id == 2)
+        .collect();
+    let options = Options::default();
+    let journal = export::export(
+        &storage,
+        Format::Beancount,
+        Some("2025-2"),
+        Some("2025-02"),
+        &options,
+    )
+    .await
+    .unwrap();
+    assert_round_trip(Format::Beancount, &journal, &february, &options);
+
+    let nothing = export::export(
+        &storage,
+        Format::Ledger,
+        Some("2025-03"),
+        Some("2025-01"),
+        &options,
+    )
+    .await
+    .unwrap();
+    assert!(parse(Format::Ledger, &nothing).unwrap().1.is_empty());
+    assert!(matches!(
+        export::export(&storage, Format::Ledger, Some("march"), None, &options).await,
+        Err(Error::Invalid(_))
+    ));
+}
+
+#[tokio::test]
+async fn accounts() {
+    let storage = books().await;
+    let payments = payments(&storage).await;
+    let mut options = Options {
+        asset_account: "Liabilities:Credit-Card".to_string(),
+        commodity: "EUR".to_string(),
+        ..Options::default()
+    };
+    options.accounts.insert(
+        "Groceries".to_string(),
+        "Expenses:Food:Groceries".to_string(),
+    );
+    assert_eq!(
+        options.account(" groceries ", 1.0),
+        "Expenses:Food:Groceries"
+    );
+    assert_eq!(options.account("eating out", 1.0), "Expenses:Eating-Out");
+    assert_eq!(options.account("salary", -1.0), "Income:Salary");
+    assert_eq!(options.account("!!", 1.0), "Expenses:Uncategorized");
+
+    for format in [Format::Ledger, Format::Hledger, Format::Beancount] {
+        let journal = export::export(&storage, format, None, None, &options)
+            .await
+            .unwrap();
+        assert!(journal.contains("Expenses:Food:Groceries  42.50 EUR"));
+        let (accounts, _) = parse(format, &journal).unwrap();
+        assert!(accounts.contains("Liabilities:Credit-Card"));
+        assert_round_trip(format, &journal, &payments, &options);
+    }
+}
+
+#[test]
+fn amounts() {
+    assert_eq!(export::amount(42.5), "42.50");
+    assert_eq!(export::amount(-2500.0), "-2500.00");
+    assert_eq!(export::amount(0.1), "0.10");
+    assert_eq!(export::amount(-0.0), "0.00");
+    assert_eq!(export::amount(1234567.891), "1234567.891");
+    assert_eq!(export::amount(1e21), "1000000000000000000000.00");
+}
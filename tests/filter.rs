@@ -0,0 +1,99 @@
+//! Narrows and orders the payments of a budget, and labels the filters that are on
+use budge::filter::{Filters, Flow, SortKey};
+use budge::{db, Storage};
+
+#[test]
+fn chips() {
+    assert!(Filters::default().chips().is_empty());
+    let filters = Filters {
+        min_amount: Some(5.0),
+        max_amount: Some(50.0),
+        kind: Some("coffee".to_string()),
+        from: Some("2025-01-10".to_string()),
+        to: None,
+        flow: Flow::Expenses,
+    };
+    assert_eq!(
+        filters.chips(),
+        [
+            "5 ≤ amount ≤ 50",
+            "kind: coffee",
+            "since 2025-01-10",
+            "expenses"
+        ]
+    );
+    let filters = Filters {
+        max_amount: Some(0.0),
+        to: Some("2025-01-31".to_string()),
+        flow: Flow::Income,
+        ..Filters::default()
+    };
+    assert_eq!(
+        filters.chips(),
+        ["amount ≤ 0", "until 2025-01-31", "income"]
+    );
+    assert_eq!(Flow::All.next().next().next(), Flow::All);
+}
+
+#[tokio::test]
+async fn payments() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let budget = pool.create_budget(500.0, "2025-01").await.unwrap();
+    for (kind, amount, day) in [
+        ("rent", 400.0, "01"),
+        ("coffee", 3.5, "05"),
+        ("iced coffee", 4.5, "20"),
+        ("refund", -20.0, "25"),
+    ] {
+        let id = pool.add_payment(budget, kind, amount, None).await.unwrap();
+        sqlx::query("UPDATE payments SET day_of = ? WHERE id = ?")
+            .bind(format!("2025-01-{day} 12:00:00"))
+            .bind(id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    let kinds = |filters: Filters, sort: SortKey, descending: bool| {
+        let pool = pool.clone();
+        async move {
+            pool.payments(budget, &filters, sort, descending)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|x| x.kind)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    let everything = kinds(Filters::default(), SortKey::Amount, false).await;
+    assert_eq!(everything, ["refund", "coffee", "iced coffee", "rent"]);
+    let by_kind = Filters {
+        kind: Some("coffee".to_string()),
+        ..Filters::default()
+    };
+    assert_eq!(
+        kinds(by_kind, SortKey::Date, true).await,
+        ["iced coffee", "coffee"]
+    );
+    let range = Filters {
+        min_amount: Some(4.0),
+        max_amount: Some(400.0),
+        from: Some("2025-01-02".to_string()),
+        to: Some("2025-01-20".to_string()),
+        ..Filters::default()
+    };
+    assert_eq!(kinds(range, SortKey::Kind, false).await, ["iced coffee"]);
+    let income = Filters {
+        flow: Flow::Income,
+        ..Filters::default()
+    };
+    assert_eq!(kinds(income, SortKey::Date, false).await, ["refund"]);
+    let expenses = Filters {
+        flow: Flow::Expenses,
+        ..Filters::default()
+    };
+    assert_eq!(
+        kinds(expenses, SortKey::Kind, true).await,
+        ["rent", "iced coffee", "coffee"]
+    );
+}
@@ -0,0 +1,249 @@
+//! Reads hand written journals and budge's own exports back in, checking what becomes a payment
+//! and that everything else is reported rather than dropped
+mod common;
+
+use budge::export::{self, Options};
+use budge::filter::{Filters, SortKey};
+use budge::import::{self, Format, Mapping};
+use budge::{db, trash, Error, Storage};
+use chrono::NaiveDate;
+
+const LEDGER: &str = "\
+; my books
+include other.journal
+account Expenses:Food
+
+2024/12/31 * Opening balance
+    Assets:Checking       $1,000.00
+    Equity:Opening
+
+2025/01/03 * (1042) Trader Joe's  ; weekly shop
+    ; memo: lots of cheese
+    Expenses:Food:Groceries    $42.50
+    Expenses:Household          $7.25
+    Assets:Checking
+
+2025-01-15 Employer | January salary
+    Assets:Checking     $2,500
+    Income:Salary      -$2,500
+
+2025-02-01 rent
+    Expenses:Rent    $1200 = $1200
+    Liabilities:Card
+
+2025-02-02 Hotel abroad
+    Expenses:Travel    100 EUR @ $1.1
+    Assets:Checking   -$110
+
+2025-02-03 Split
+    Expenses:Books
+    Expenses:Music
+    Assets:Checking   -$20
+
+~ monthly
+    Expenses:Rent  $1200
+    Assets:Checking
+";
+
+const BEANCOUNT: &str = r#"
+option "operating_currency" "USD"
+2025-01-01 open Assets:Checking USD
+2025-01-01 open Expenses:Coffee USD
+
+2025-03-01 * "Blue \"Bottle\"" "Flat white"
+  memo: "oat milk"
+  Expenses:Coffee  4.50 USD
+  Assets:Checking
+
+2025-03-02 txn "coffee"
+  Expenses:Coffee  3 USD ; refill
+  ! Assets:Checking  -3 USD
+
+2025-03-03 balance Assets:Checking  -7.50 USD
+
+2025-03-04 * "Move money to savings"
+  Assets:Savings  100 USD
+  Assets:Checking
+"#;
+
+#[test]
+fn ledger() {
+    let mapping = Mapping {
+        commodity: Some("$".to_string()),
+        kinds: [("Expenses:Food".to_string(), "food".to_string())].into(),
+    };
+    let (entries, skipped) = import::parse(Format::Ledger, LEDGER, &mapping);
+    let found: Vec<_> = entries
+        .iter()
+        .map(|x| {
+            (
+                x.kind.as_str(),
+                x.amount,
+                x.payee.as_deref(),
+                x.memo.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("food", 42.5, Some("Trader Joe's"), Some("lots of cheese")),
+            (
+                "household",
+                7.25,
+                Some("Trader Joe's"),
+                Some("lots of cheese")
+            ),
+            ("salary", -2500.0, Some("Employer"), Some("January salary")),
+            ("rent", 1200.0, None, None),
+        ]
+    );
+    assert_eq!(
+        entries[0].date,
+        NaiveDate::from_ymd_opt(2025, 1, 3).unwrap()
+    );
+
+    let lines: Vec<(usize, &str)> = skipped
+        .iter()
+        .map(|x| (x.line, x.reason.as_str()))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            (2, "included files aren't read, import them on their own"),
+            (6, "no expense or income account in this transaction"),
+            (7, "no expense or income account in this transaction"),
+            (24, "has a price or cost"),
+            (28, "more than one posting has no amount"),
+            (29, "more than one posting has no amount"),
+            (32, "automated and periodic transactions aren't imported"),
+        ]
+    );
+}
+
+#[test]
+fn beancount() {
+    let (entries, skipped) = import::parse(Format::Beancount, BEANCOUNT, &Mapping::default());
+    let found: Vec<_> = entries
+        .iter()
+        .map(|x| {
+            (
+                x.kind.as_str(),
+                x.amount,
+                x.payee.as_deref(),
+                x.memo.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("coffee", 4.5, Some("Blue \"Bottle\""), Some("oat milk")),
+            ("coffee", 3.0, None, None),
+        ]
+    );
+    assert_eq!(skipped.len(), 2);
+    assert!(skipped.iter().all(|x| x.text.starts_with("Assets:")));
+}
+
+/// What an export keeps of every payment
+async fn exported(
+    pool: &budge::db::DbPool,
+) -> Vec<(String, String, f64, Option<String>, Option<String>)> {
+    let mut payments = Vec::new();
+    for budget in pool.budgets().await.unwrap() {
+        for x in pool
+            .payments(budget.id, &Filters::default(), SortKey::Id, false)
+            .await
+            .unwrap()
+        {
+            payments.push((
+                x.day_of[..10].to_string(),
+                x.kind,
+                x.amount,
+                x.payee,
+                x.memo,
+            ));
+        }
+    }
+    payments.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    payments
+}
+
+async fn uuids(pool: &budge::db::DbPool) -> Vec<String> {
+    sqlx::query_scalar("SELECT uuid FROM payments ORDER BY uuid")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let source = db::connect("sqlite::memory:").await.unwrap();
+    common::books(&source).await;
+    let expected = exported(&source).await;
+
+    for (from, to) in [
+        (export::Format::Ledger, Format::Ledger),
+        (export::Format::Hledger, Format::Ledger),
+        (export::Format::Beancount, Format::Beancount),
+    ] {
+        let journal = export::export(&source, from, None, None, &Options::default())
+            .await
+            .unwrap();
+        let target = db::connect("sqlite::memory:").await.unwrap();
+        let report = import::import(&target, to, &journal, &Mapping::default(), 100.0)
+            .await
+            .unwrap();
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+        assert_eq!((report.payments, report.budgets), (3, 1));
+        assert_eq!(exported(&target).await, expected, "{from:?}");
+        assert_eq!(uuids(&target).await, uuids(&source).await);
+
+        // Importing the same journal again finds every payment already there
+        let report = import::import(&target, to, &journal, &Mapping::default(), 100.0)
+            .await
+            .unwrap();
+        assert_eq!((report.payments, report.existing), (0, 3));
+        assert_eq!(exported(&target).await, expected);
+    }
+}
+
+#[tokio::test]
+async fn budgets() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let mapping = Mapping {
+        commodity: Some("$".to_string()),
+        ..Mapping::default()
+    };
+    let january = pool.create_budget(300.0, "2025-01").await.unwrap();
+    let report = import::import(&pool, Format::Ledger, LEDGER, &mapping, 100.0)
+        .await
+        .unwrap();
+    assert_eq!((report.payments, report.budgets), (4, 1));
+    let months: Vec<_> = pool
+        .budgets()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|x| (x.month, x.amount))
+        .collect();
+    assert_eq!(
+        months,
+        [
+            ("2025-01".to_string(), 300.0),
+            ("2025-02".to_string(), 100.0)
+        ]
+    );
+    assert_eq!(pool.total(january).await.unwrap(), 42.5 + 7.25 - 2500.0);
+
+    // Nothing is imported when a month is in the trash, not even the months before it
+    let february = pool.find_or_create_budget("2025-02", 0.0).await.unwrap();
+    trash::trash_budget(&pool, february).await.unwrap();
+    let before = exported(&pool).await;
+    assert!(matches!(
+        import::import(&pool, Format::Ledger, LEDGER, &mapping, 100.0).await,
+        Err(Error::TrashedMonth { id, .. }) if id == february
+    ));
+    assert_eq!(exported(&pool).await, before);
+}
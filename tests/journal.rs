@@ -0,0 +1,132 @@
+//! Undoes and redoes adds, edits and deletes of payments
+use budge::trash::{self, Record};
+use budge::{db, journal, Storage};
+
+#[tokio::test]
+async fn undo_and_redo() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let budget = pool.create_budget(100.0, "2025-01").await.unwrap();
+    assert!(journal::undo(&pool).await.unwrap().is_none());
+    assert!(journal::redo(&pool).await.unwrap().is_none());
+
+    let rent = pool
+        .add_payment(budget, "rent", 90.0, Some("Landlord"))
+        .await
+        .unwrap();
+    pool.set_memo(rent, Some("January")).await.unwrap();
+    pool.delete_payment(rent).await.unwrap();
+    assert!(pool.payment(rent).await.unwrap().is_none());
+
+    // Newest first: the delete, the edit, then the add
+    let entry = journal::undo(&pool).await.unwrap().unwrap();
+    assert_eq!(entry.describe(), format!("delete of payment {rent}"));
+    let payment = pool.payment(rent).await.unwrap().unwrap();
+    assert_eq!(payment.memo.as_deref(), Some("January"));
+    assert_eq!(payment.payee.as_deref(), Some("Landlord"));
+
+    assert_eq!(journal::undo(&pool).await.unwrap().unwrap().action, "edit");
+    assert_eq!(pool.payment(rent).await.unwrap().unwrap().memo, None);
+
+    assert_eq!(journal::undo(&pool).await.unwrap().unwrap().action, "add");
+    assert!(pool.payment(rent).await.unwrap().is_none());
+    assert!(trash::list(&pool).await.unwrap().is_empty());
+    assert!(journal::undo(&pool).await.unwrap().is_none());
+
+    // Redo goes the other way, oldest first
+    assert_eq!(journal::redo(&pool).await.unwrap().unwrap().action, "add");
+    let payment = pool.payment(rent).await.unwrap().unwrap();
+    assert_eq!((payment.kind.as_str(), payment.amount), ("rent", 90.0));
+    assert_eq!(journal::redo(&pool).await.unwrap().unwrap().action, "edit");
+    assert_eq!(
+        pool.payment(rent).await.unwrap().unwrap().memo.as_deref(),
+        Some("January")
+    );
+    assert_eq!(
+        journal::redo(&pool).await.unwrap().unwrap().action,
+        "delete"
+    );
+    assert!(pool.payment(rent).await.unwrap().is_none());
+    assert_eq!(trash::list(&pool).await.unwrap().len(), 1);
+    assert!(journal::redo(&pool).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn new_change_drops_redo() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let budget = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let rent = pool.add_payment(budget, "rent", 90.0, None).await.unwrap();
+    pool.set_memo(rent, Some("January")).await.unwrap();
+    journal::undo(&pool).await.unwrap().unwrap();
+
+    pool.add_payment(budget, "coffee", 3.5, None).await.unwrap();
+    assert!(journal::redo(&pool).await.unwrap().is_none());
+    assert_eq!(pool.payment(rent).await.unwrap().unwrap().memo, None);
+
+    // An edit drops what was undone as well, so the coffee stays gone
+    journal::undo(&pool).await.unwrap().unwrap();
+    pool.set_memo(rent, Some("February")).await.unwrap();
+    assert!(journal::redo(&pool).await.unwrap().is_none());
+    assert_eq!(pool.total(budget).await.unwrap(), 90.0);
+}
+
+#[tokio::test]
+async fn undo_after_purge() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let budget = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let rent = pool
+        .add_payment(budget, "rent", 90.0, Some("Landlord"))
+        .await
+        .unwrap();
+    pool.set_memo(rent, Some("January")).await.unwrap();
+    let before = pool.payment(rent).await.unwrap().unwrap();
+    pool.delete_payment(rent).await.unwrap();
+    assert_eq!(trash::purge(&pool).await.unwrap(), 1);
+
+    journal::undo(&pool).await.unwrap().unwrap();
+    let after = pool.payment(rent).await.unwrap().unwrap();
+    assert_eq!(
+        (
+            after.uuid,
+            after.amount,
+            after.kind,
+            after.day_of,
+            after.payee,
+            after.memo
+        ),
+        (
+            before.uuid,
+            before.amount,
+            before.kind,
+            before.day_of,
+            before.payee,
+            before.memo
+        )
+    );
+    assert_eq!(pool.total(budget).await.unwrap(), 90.0);
+}
+
+#[tokio::test]
+async fn purged_budget() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let january = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let february = pool.create_budget(100.0, "2025-02").await.unwrap();
+    let rent = pool.add_payment(january, "rent", 90.0, None).await.unwrap();
+    let coffee = pool.add_payment(february, "coffee", 3.5, None).await.unwrap();
+    pool.delete_payment(coffee).await.unwrap();
+    trash::trash_budget(&pool, february).await.unwrap();
+    trash::purge(&pool).await.unwrap();
+
+    // The coffee can't come back without its month, so undo moves on to the rent
+    let entry = journal::undo(&pool).await.unwrap().unwrap();
+    assert_eq!(entry.payment_id, rent);
+    assert!(pool.payment(rent).await.unwrap().is_none());
+    assert!(journal::undo(&pool).await.unwrap().is_none());
+    assert_eq!(journal::redo(&pool).await.unwrap().unwrap().payment_id, rent);
+
+    // The same goes for a budget purged on its own
+    let march = pool.create_budget(100.0, "2025-03").await.unwrap();
+    pool.add_payment(march, "tea", 2.0, None).await.unwrap();
+    trash::trash_budget(&pool, march).await.unwrap();
+    trash::purge_one(&pool, Record::Budget, march).await.unwrap();
+    assert_eq!(journal::undo(&pool).await.unwrap().unwrap().payment_id, rent);
+}
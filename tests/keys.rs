@@ -0,0 +1,78 @@
+//! Reads key bindings from the config file, through the `budge` binary since the key map lives
+//! there
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// A config directory of its own holding `config`, if there is one
+fn config_dir(test: &str, config: Option<&str>) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("budge-keys-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("budge")).unwrap();
+    if let Some(config) = config {
+        std::fs::write(dir.join("budge/config.toml"), config).unwrap();
+    }
+    dir
+}
+
+fn budge(config_dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_budge"))
+        .env("XDG_CONFIG_HOME", config_dir)
+        .env("XDG_DATA_HOME", config_dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn bindings() {
+    let dir = config_dir(
+        "bindings",
+        Some("[keys.normal]\n\"C-n\" = \"scroll-down\"\n\"g t\" = \"trash\"\nq = \"none\"\n"),
+    );
+    let output = budge(&dir, &["keys"]);
+    assert!(output.status.success());
+    let table = String::from_utf8(output.stdout).unwrap();
+    let row = |description: &str| {
+        table
+            .lines()
+            .find(|x| x.split('|').nth(2).map(str::trim) == Some(description))
+            .map(|x| x.split('|').nth(1).unwrap().trim().to_string())
+    };
+    assert_eq!(row("Scroll down").as_deref(), Some("`j`, `Down`, `C-n`"));
+    assert_eq!(row("Show the trash").as_deref(), Some("`t`, `g t`"));
+    assert_eq!(row("Quit"), None);
+    assert_eq!(row("Close the popup (trash)").as_deref(), Some("`Esc`, `t`, `q`"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mistakes() {
+    for (config, error) in [
+        (
+            "[keys.nowhere]\nx = \"help\"\n",
+            "unknown key scope \"nowhere\"",
+        ),
+        ("[keys.normal]\n\"C-\" = \"help\"\n", "unknown key \"C-\""),
+        ("[keys.normal]\nx = \"fly\"\n", "unknown action \"fly\""),
+    ] {
+        let dir = config_dir("mistakes", Some(config));
+        let output = budge(&dir, &["keys"]);
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains(error),
+            "{config}"
+        );
+    }
+
+    // `config set` refuses them rather than breaking every later command
+    let dir = config_dir("mistakes", None);
+    let output = budge(&dir, &["config", "set", "keys.normal.x", "fly"]);
+    assert!(!output.status.success());
+    assert!(!dir.join("budge/config.toml").exists());
+    assert!(budge(&dir, &["config", "set", "keys.normal.x", "help"])
+        .status
+        .success());
+    assert!(budge(&dir, &["keys"]).status.success());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+//! Names payees once whatever their case, completes them and sums up what they were paid
+use budge::payee;
+use budge::{db, Storage};
+
+#[tokio::test]
+async fn find_or_create() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let costco = payee::find_or_create(&pool, "Costco").await.unwrap();
+    assert_eq!(payee::find_or_create(&pool, "Costco").await.unwrap(), costco);
+    assert_eq!(payee::find_or_create(&pool, "COSTCO").await.unwrap(), costco);
+    assert_eq!(payee::find_or_create(&pool, "costco").await.unwrap(), costco);
+    let landlord = payee::find_or_create(&pool, "Landlord").await.unwrap();
+    assert_ne!(landlord, costco);
+
+    // The first spelling is the one that's kept
+    assert_eq!(payee::names(&pool).await.unwrap(), ["Costco", "Landlord"]);
+
+    // Payments reuse them the same way
+    let budget = pool.create_budget(100.0, "2025-01").await.unwrap();
+    pool.add_payment(budget, "groceries", 10.0, Some(" costco ")).await.unwrap();
+    assert_eq!(payee::names(&pool).await.unwrap().len(), 2);
+}
+
+#[test]
+fn complete() {
+    let names = ["Costco".to_string(), "Café Nero".to_string(), "\u{212A}iosk".to_string()];
+    assert_eq!(payee::complete(&names, ""), None);
+    assert_eq!(payee::complete(&names, "co"), Some("Costco"));
+    assert_eq!(payee::complete(&names, "COST"), Some("Costco"));
+    assert_eq!(payee::complete(&names, "café"), Some("Café Nero"));
+    assert_eq!(payee::complete(&names, "CAFÉ N"), Some("Café Nero"));
+    assert_eq!(payee::complete(&names, "tesco"), None);
+    // Nothing is left to add once the whole name is typed
+    assert_eq!(payee::complete(&names, "costco"), None);
+    assert_eq!(payee::complete(&names, "Café Nero"), None);
+    // The Kelvin sign lowercases to a `k` a third of its length
+    assert_eq!(payee::complete(&names, "ki"), Some("\u{212A}iosk"));
+    assert_eq!(payee::complete(&names, "kiosk"), None);
+}
+
+#[tokio::test]
+async fn summaries() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let january = pool.create_budget(500.0, "2025-01").await.unwrap();
+    let february = pool.create_budget(500.0, "2025-02").await.unwrap();
+    pool.add_payment(january, "groceries", 30.0, Some("Costco")).await.unwrap();
+    pool.add_payment(february, "groceries", 50.0, Some("Costco")).await.unwrap();
+    let trashed = pool.add_payment(february, "tv", 900.0, Some("Costco")).await.unwrap();
+    pool.delete_payment(trashed).await.unwrap();
+    pool.add_payment(january, "rent", 400.0, Some("Landlord")).await.unwrap();
+    pool.add_payment(january, "coffee", 3.0, None).await.unwrap();
+    payee::find_or_create(&pool, "Tesco").await.unwrap();
+
+    let summaries = payee::summaries(&pool).await.unwrap();
+    let rows: Vec<_> = summaries
+        .iter()
+        .map(|x| (x.name.as_str(), x.visits, x.total, x.average))
+        .collect();
+    // Biggest total first, leaving out the trash
+    assert_eq!(
+        rows,
+        [
+            ("Landlord", 1, 400.0, 400.0),
+            ("Costco", 2, 80.0, 40.0),
+            ("Tesco", 0, 0.0, 0.0),
+        ]
+    );
+    assert!(summaries[1].last_visit.is_some());
+    assert_eq!(summaries[2].last_visit, None);
+}
@@ -0,0 +1,69 @@
+//! Picks the database to open from --db, --profile, BUDGE_DB and the config file, through the
+//! `budge` binary since the config lives there
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A home of its own, with the config and data directories inside it
+fn home(test: &str, config: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("budge-profiles-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("config/budge")).unwrap();
+    std::fs::write(dir.join("config/budge/config.toml"), config).unwrap();
+    dir
+}
+
+/// The database `budge list` opens with `args` and, when given, `BUDGE_DB`
+fn opened(home: &Path, budge_db: Option<&str>, args: &[&str]) -> PathBuf {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_budge"));
+    command
+        .current_dir(home)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env_remove("BUDGE_DB")
+        .args(args)
+        .arg("list")
+        .stdin(Stdio::null());
+    if let Some(db) = budge_db {
+        command.env("BUDGE_DB", db);
+    }
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let db = stdout.lines().next().unwrap().strip_prefix("Hosted at: ").unwrap();
+    PathBuf::from(db)
+}
+
+#[test]
+fn order() {
+    let dir = home(
+        "order",
+        "default_profile = \"home\"\n\
+         [profiles.home]\ndb = \"~/home.db\"\n\
+         [profiles.work]\ndb = \"~/work/books.db\"\n",
+    );
+    let db = dir.join("db.db");
+    let env = dir.join("env.db");
+    let (db, env) = (db.to_str().unwrap(), Some(env.to_str().unwrap()));
+
+    // Each one wins over everything after it
+    assert_eq!(opened(&dir, env, &["--db", db, "--profile", "work"]), Path::new(db));
+    assert_eq!(opened(&dir, env, &["--profile", "work"]), dir.join("work/books.db"));
+    assert_eq!(opened(&dir, env, &[]), Path::new(env.unwrap()));
+    assert_eq!(opened(&dir, Some(""), &[]), dir.join("home.db"));
+    assert_eq!(opened(&dir, None, &[]), dir.join("home.db"));
+
+    let dir = home("no-default", "[profiles.work]\ndb = \"~/work/books.db\"\n");
+    assert_eq!(opened(&dir, None, &[]), dir.join("data/budge.db"));
+}
+
+#[test]
+fn expand_home() {
+    let dir = home("expand", "[profiles.elsewhere]\ndb = \"~elsewhere/books.db\"\n");
+
+    assert_eq!(opened(&dir, None, &["--db", "~/a.db"]), dir.join("a.db"));
+    assert_eq!(opened(&dir, Some("~/b.db"), &[]), dir.join("b.db"));
+    // Only a `~` of its own is the home directory, so this is relative to the working one
+    let db = opened(&dir, None, &["--profile", "elsewhere"]);
+    assert_eq!(db, Path::new("~elsewhere/books.db"));
+}
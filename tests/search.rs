@@ -0,0 +1,71 @@
+//! Turns what the user typed into FTS5 queries and keeps the index in step with the payments
+use budge::search::{self, fts_query};
+use budge::trash::{self, Record};
+use budge::{db, Storage};
+
+#[test]
+fn quoting() {
+    assert_eq!(fts_query(""), None);
+    assert_eq!(fts_query("   "), None);
+    assert_eq!(fts_query("rent"), Some("\"rent\"*".to_string()));
+    assert_eq!(fts_query(" rent  june "), Some("\"rent\"* \"june\"*".to_string()));
+    assert_eq!(fts_query("AT&T (NY)"), Some("\"AT&T\"* \"(NY)\"*".to_string()));
+    assert_eq!(fts_query("say \"hi\""), Some("\"say\"* \"\"\"hi\"\"\"*".to_string()));
+}
+
+#[tokio::test]
+async fn punctuation() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let budget = pool.create_budget(100.0, "2025-01").await.unwrap();
+    pool.add_payment(budget, "phone", 40.0, Some("AT&T")).await.unwrap();
+
+    // None of these are valid FTS5 syntax on their own
+    for input in ["AT&T", "\"", "a\"b", "(", "*", "-phone", "phone:", "NEAR("] {
+        search::search(&pool, input).await.unwrap();
+        search::matching_ids(&pool, input, budget).await.unwrap();
+    }
+    assert_eq!(search::search(&pool, "pho").await.unwrap().len(), 1);
+    assert!(search::search(&pool, "").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn follows_changes() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let budget = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let rent = pool.add_payment(budget, "rent", 90.0, Some("Landlord")).await.unwrap();
+    let food = pool.add_payment(budget, "groceries", 20.0, Some("Costco")).await.unwrap();
+    assert_eq!(search::matching_ids(&pool, "land", budget).await.unwrap(), [rent]);
+
+    // An edit
+    pool.set_memo(food, Some("birthday cake")).await.unwrap();
+    let hits = search::search(&pool, "cake").await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, food);
+    assert_eq!(hits[0].memo.as_deref(), Some("birthday cake"));
+    pool.set_memo(food, None).await.unwrap();
+    assert!(search::search(&pool, "cake").await.unwrap().is_empty());
+
+    // A payee rename
+    sqlx::query("UPDATE payees SET name = 'Property Co' WHERE name = 'Landlord'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(search::search(&pool, "landlord").await.unwrap().is_empty());
+    let hits = search::search(&pool, "property").await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].payee.as_deref(), Some("Property Co"));
+
+    // A delete hides it, restoring brings it back and purging drops it from the index
+    pool.delete_payment(rent).await.unwrap();
+    assert!(search::search(&pool, "property").await.unwrap().is_empty());
+    assert!(search::matching_ids(&pool, "rent", budget).await.unwrap().is_empty());
+    trash::restore(&pool, Record::Payment, rent).await.unwrap();
+    assert_eq!(search::matching_ids(&pool, "rent", budget).await.unwrap(), [rent]);
+    pool.delete_payment(rent).await.unwrap();
+    trash::purge_one(&pool, Record::Payment, rent).await.unwrap();
+    let indexed: i64 = sqlx::query_scalar("SELECT count(*) FROM payments_fts")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(indexed, 1);
+}
@@ -0,0 +1,183 @@
+//! Talks to the JSON API without a network, checking the token, ETags and preconditions
+use std::sync::Arc;
+
+use axum::body::{to_bytes, Body};
+use axum::http::header::{AUTHORIZATION, ETAG, IF_MATCH, IF_NONE_MATCH};
+use axum::http::{Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use budge::{db, serve, Storage};
+use serde_json::Value;
+use tower::ServiceExt;
+
+const TOKEN: &str = "change-me";
+
+async fn app() -> (Router, i64) {
+    let storage = db::connect("sqlite::memory:").await.unwrap();
+    let budget = storage.create_budget(500.0, "2025-01").await.unwrap();
+    let payment = storage
+        .add_payment(budget, "rent", 90.0, None)
+        .await
+        .unwrap();
+    (serve::router(Arc::new(storage), TOKEN.to_string()), payment)
+}
+
+/// A request with the token and the given headers, and a JSON body when there is one
+fn request(
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Option<Value>,
+) -> Request<Body> {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(AUTHORIZATION, format!("Bearer {TOKEN}"));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap()
+}
+
+async fn send(app: &Router, request: Request<Body>) -> Response {
+    app.clone().oneshot(request).await.unwrap()
+}
+
+fn tag(response: &Response) -> String {
+    response.headers()[ETAG].to_str().unwrap().to_string()
+}
+
+async fn json(response: Response) -> Value {
+    serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn token() {
+    let (app, payment) = app().await;
+    let uri = format!("/payments/{payment}");
+    let anonymous = Request::get(&uri).body(Body::empty()).unwrap();
+    let response = send(&app, anonymous).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+
+    for header in [
+        "Bearer change-it",
+        "Bearer ",
+        "change-me",
+        "Basic change-me",
+    ] {
+        let request = Request::get(&uri)
+            .header(AUTHORIZATION, header)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            send(&app, request).await.status(),
+            StatusCode::UNAUTHORIZED,
+            "{header}"
+        );
+    }
+    assert_eq!(
+        send(&app, request("GET", &uri, &[], None)).await.status(),
+        StatusCode::OK
+    );
+
+    // The description of the API is open to anyone
+    let openapi = Request::get("/openapi.json").body(Body::empty()).unwrap();
+    assert_eq!(send(&app, openapi).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn versions() {
+    let (app, payment) = app().await;
+    let uri = format!("/payments/{payment}");
+    let response = send(&app, request("GET", &uri, &[], None)).await;
+    let first = tag(&response);
+    assert_eq!(json(response).await["kind"], "rent");
+
+    let response = send(
+        &app,
+        request("GET", &uri, &[(IF_NONE_MATCH.as_str(), &first)], None),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(tag(&response), first);
+
+    let memo = Some(serde_json::json!({ "memo": "January" }));
+    let response = send(&app, request("PATCH", &uri, &[], memo.clone())).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    let response = send(
+        &app,
+        request(
+            "PATCH",
+            &uri,
+            &[(IF_MATCH.as_str(), "\"stale\"")],
+            memo.clone(),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let response = send(
+        &app,
+        request("PATCH", &uri, &[(IF_MATCH.as_str(), &first)], memo.clone()),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let second = tag(&response);
+    assert_ne!(second, first);
+    assert_eq!(json(response).await["memo"], "January");
+
+    // The old version is stale now, for changes and deletes alike
+    let response = send(
+        &app,
+        request("PATCH", &uri, &[(IF_MATCH.as_str(), &first)], memo),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let response = send(&app, request("DELETE", &uri, &[], None)).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    let response = send(
+        &app,
+        request("DELETE", &uri, &[(IF_MATCH.as_str(), &first)], None),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let response = send(
+        &app,
+        request("DELETE", &uri, &[(IF_MATCH.as_str(), &second)], None),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = send(&app, request("GET", &uri, &[], None)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn same_version_twice() {
+    let (app, payment) = app().await;
+    let uri = format!("/payments/{payment}");
+    let first = tag(&send(&app, request("GET", &uri, &[], None)).await);
+
+    // Two edits based on the same version at once: only one of them goes through
+    let edits = ["laptop", "phone"].map(|memo| {
+        let request = request(
+            "PATCH",
+            &uri,
+            &[(IF_MATCH.as_str(), &first)],
+            Some(serde_json::json!({ "memo": memo })),
+        );
+        tokio::spawn(app.clone().oneshot(request))
+    });
+    let mut statuses = Vec::new();
+    for edit in edits {
+        statuses.push(edit.await.unwrap().unwrap().status());
+    }
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::PRECONDITION_FAILED]);
+}
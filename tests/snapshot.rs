@@ -0,0 +1,145 @@
+//! Takes, rotates and restores snapshots of a database in a scratch directory
+mod common;
+
+use std::process::{Command, Stdio};
+
+use budge::{db, snapshot, Storage};
+use common::scratch;
+
+#[tokio::test]
+async fn rotation() {
+    let path = scratch("snapshot", "rotation");
+    let pool = db::connect(&path.to_string_lossy()).await.unwrap();
+
+    assert!(snapshot::take(&pool, &path, "remove", 0)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(snapshot::list(&path).unwrap().is_empty());
+
+    let daily = snapshot::daily(&pool, &path, 3).await.unwrap().unwrap();
+    assert_eq!(daily.reason, "daily");
+    assert!(daily.name.starts_with("books-"));
+    assert!(snapshot::daily(&pool, &path, 3).await.unwrap().is_none());
+
+    for reason in ["remove", "purge", "import", "doctor"] {
+        snapshot::take(&pool, &path, reason, 3)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    let reasons: Vec<String> = snapshot::list(&path)
+        .unwrap()
+        .into_iter()
+        .map(|x| x.reason)
+        .collect();
+    assert_eq!(reasons, ["doctor", "import", "purge"]);
+
+    // Other files in the directory are left alone
+    let dir = snapshot::snapshots_dir(&path);
+    std::fs::write(dir.join("notes.txt"), "mine").unwrap();
+    std::fs::write(dir.join("other-20250101-000000-daily.db"), "").unwrap();
+    snapshot::take(&pool, &path, "remove", 1).await.unwrap();
+    assert_eq!(snapshot::list(&path).unwrap().len(), 1);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn restore() {
+    let path = scratch("snapshot", "restore");
+    let pool = db::connect(&path.to_string_lossy()).await.unwrap();
+    let id = pool.create_budget(100.0, "2025-01").await.unwrap();
+    pool.add_payment(id, "rent", 90.0, None).await.unwrap();
+    let before = snapshot::take(&pool, &path, "purge", 5)
+        .await
+        .unwrap()
+        .unwrap();
+    sqlx::query("DELETE FROM budget")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    assert!(snapshot::restore(pool.clone(), &path, "nope", 5)
+        .await
+        .is_err());
+    let name = before.name.strip_suffix(".db").unwrap();
+    let restored = snapshot::restore(pool, &path, name, 5).await.unwrap();
+    assert_eq!(restored.name, before.name);
+    assert!(!path.with_extension("db.restore").exists());
+
+    let pool = db::connect(&path.to_string_lossy()).await.unwrap();
+    assert_eq!(pool.total(id).await.unwrap(), 90.0);
+    let newest = snapshot::list(&path).unwrap().remove(0);
+    assert_eq!(newest.reason, "restore");
+
+    // The state from before the restore can be brought back too
+    snapshot::restore(pool, &path, &newest.name, 5)
+        .await
+        .unwrap();
+    let pool = db::connect(&path.to_string_lossy()).await.unwrap();
+    assert!(pool.budgets().await.unwrap().is_empty());
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn failed_restore() {
+    let path = scratch("snapshot", "failed");
+    let pool = db::connect(&path.to_string_lossy()).await.unwrap();
+    let id = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let before = snapshot::take(&pool, &path, "purge", 5)
+        .await
+        .unwrap()
+        .unwrap();
+    pool.add_payment(id, "rent", 90.0, None).await.unwrap();
+
+    // A copy that can't be written leaves the database alone
+    std::fs::create_dir(path.with_extension("db.restore")).unwrap();
+    assert!(snapshot::restore(pool, &path, &before.name, 5)
+        .await
+        .is_err());
+    let pool = db::connect(&path.to_string_lossy()).await.unwrap();
+    assert_eq!(pool.total(id).await.unwrap(), 90.0);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn restore_the_oldest() {
+    let path = scratch("snapshot", "oldest");
+    let pool = db::connect(&path.to_string_lossy()).await.unwrap();
+    let id = pool.create_budget(100.0, "2025-01").await.unwrap();
+
+    // Two snapshots from days ago, with `keep` reached and no daily snapshot for today
+    let dir = snapshot::snapshots_dir(&path);
+    for (day, amount) in [("20250101", 10.0), ("20250102", 20.0)] {
+        pool.add_payment(id, "rent", amount, None).await.unwrap();
+        let taken = snapshot::take(&pool, &path, "remove", 2)
+            .await
+            .unwrap()
+            .unwrap();
+        let name = format!("books-{day}-000000.000-remove.db");
+        std::fs::rename(taken.path, dir.join(name)).unwrap();
+    }
+    pool.close().await;
+    let config = path.parent().unwrap().join("config");
+    std::fs::create_dir_all(config.join("budge")).unwrap();
+    std::fs::write(config.join("budge/config.toml"), "[snapshots]\nkeep = 2\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_budge"))
+        .env("XDG_CONFIG_HOME", &config)
+        .arg("--db")
+        .arg(&path)
+        .args(["snapshots", "restore", "books-20250101-000000.000-remove"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let pool = db::connect(&path.to_string_lossy()).await.unwrap();
+    assert_eq!(pool.total(id).await.unwrap(), 10.0);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
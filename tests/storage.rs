@@ -0,0 +1,147 @@
+//! The same scenario against every storage backend, so that they can't drift apart. The
+//! PostgreSQL run needs `--features postgres` and a scratch database in `BUDGE_TEST_POSTGRES`,
+//! such as `postgres://postgres@localhost/budge_test`, which it empties first
+use budge::filter::{Filters, Flow, SortKey};
+use budge::memory::MemoryStorage;
+use budge::storage::Ref;
+use budge::trash::Record;
+use budge::{db, Error, Storage};
+
+async fn exercise(storage: &dyn Storage) {
+    let id = storage.create_budget(100.0, "2025/1").await.unwrap();
+    let budgets = storage.budgets().await.unwrap();
+    assert_eq!(budgets.len(), 1);
+    assert_eq!(budgets[0].month, "2025-01");
+    assert!(matches!(
+        storage.create_budget(5.0, "2025-01").await,
+        Err(Error::DuplicateMonth { id: x, .. }) if x == id
+    ));
+    assert!(matches!(storage.create_budget(5.0, "soon").await, Err(Error::Invalid(_))));
+
+    let groceries = storage
+        .add_payment(id, "groceries", 42.5, Some("Costco"))
+        .await
+        .unwrap();
+    let salary = storage.add_payment(id, "salary", -1000.0, None).await.unwrap();
+    let coffee = storage
+        .add_payment(id, " coffee ", 3.5, Some("costco"))
+        .await
+        .unwrap();
+    assert!(matches!(storage.add_payment(id, " ", 1.0, None).await, Err(Error::Invalid(_))));
+    assert!(matches!(
+        storage.add_payment(id + 100, "rent", 1.0, None).await,
+        Err(Error::NotFound { record: "budget", .. })
+    ));
+
+    let first = storage.payment(groceries).await.unwrap().unwrap();
+    let third = storage.payment(coffee).await.unwrap().unwrap();
+    assert_eq!(third.kind, "coffee");
+    assert_eq!(first.payee.as_deref(), Some("Costco"));
+    assert_eq!(first.payee_id, third.payee_id);
+    assert_ne!(first.uuid, third.uuid);
+    let by_uuid: Ref = first.uuid.to_uppercase().parse().unwrap();
+    assert_eq!(storage.resolve(Record::Payment, &by_uuid).await.unwrap(), groceries);
+    assert_eq!(storage.resolve(Record::Payment, &Ref::Id(coffee)).await.unwrap(), coffee);
+    assert!(matches!(
+        storage.resolve(Record::Budget, &by_uuid).await,
+        Err(Error::UnknownUuid { record: "budget", .. })
+    ));
+    assert_eq!(storage.total(id).await.unwrap(), -954.0);
+
+    let expenses = Filters {
+        flow: Flow::Expenses,
+        ..Filters::default()
+    };
+    let ids: Vec<i64> = storage
+        .payments(id, &expenses, SortKey::Amount, false)
+        .await
+        .unwrap()
+        .iter()
+        .map(|x| x.id)
+        .collect();
+    assert_eq!(ids, [coffee, groceries]);
+    let by_kind = Filters {
+        kind: Some("GRO".to_string()),
+        ..Filters::default()
+    };
+    let found = storage.payments(id, &by_kind, SortKey::Date, true).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, groceries);
+    let everything = Filters::default();
+    let ids: Vec<i64> = storage
+        .payments(id, &everything, SortKey::Kind, true)
+        .await
+        .unwrap()
+        .iter()
+        .map(|x| x.id)
+        .collect();
+    assert_eq!(ids, [salary, groceries, coffee]);
+
+    storage.set_memo(salary, Some("october")).await.unwrap();
+    let memo = storage.payment(salary).await.unwrap().unwrap().memo;
+    assert_eq!(memo.as_deref(), Some("october"));
+    storage.set_memo(salary, None).await.unwrap();
+    assert_eq!(storage.payment(salary).await.unwrap().unwrap().memo, None);
+    assert!(matches!(
+        storage.set_memo(coffee + 100, None).await,
+        Err(Error::NotFound { record: "payment", .. })
+    ));
+
+    storage.delete_payment(salary).await.unwrap();
+    assert!(storage.payment(salary).await.unwrap().is_none());
+    assert_eq!(storage.total(id).await.unwrap(), 46.0);
+    assert!(matches!(storage.delete_payment(salary).await, Err(Error::NotFound { .. })));
+
+    let next = storage.find_or_create_budget("2025-02", 5.0).await.unwrap();
+    assert_ne!(next, id);
+    assert_eq!(storage.find_or_create_budget("2025-02", 9.0).await.unwrap(), next);
+    assert_eq!(storage.budget(next).await.unwrap().unwrap().amount, 5.0);
+    // Months are read the same way as when creating a budget
+    assert_eq!(storage.find_or_create_budget("202502", 9.0).await.unwrap(), next);
+    assert!(matches!(
+        storage.find_or_create_budget("February", 9.0).await,
+        Err(Error::Invalid(_))
+    ));
+    assert_eq!(storage.budgets().await.unwrap().len(), 2);
+
+    let uuid = storage.budget(id).await.unwrap().unwrap().uuid;
+    assert!(storage.remove_budget(id).await.unwrap());
+    assert!(!storage.remove_budget(id).await.unwrap());
+    assert!(storage.budget(id).await.unwrap().is_none());
+    // Uuids still find what's in the trash, so that it can be restored by them
+    assert_eq!(storage.id_of(Record::Budget, &uuid).await.unwrap(), Some(id));
+    assert_eq!(storage.budgets().await.unwrap().len(), 1);
+    assert!(matches!(
+        storage.create_budget(5.0, "2025-01").await,
+        Err(Error::TrashedMonth { id: x, .. }) if x == id
+    ));
+    assert!(matches!(
+        storage.find_or_create_budget("2025-01", 5.0).await,
+        Err(Error::TrashedMonth { id: x, .. }) if x == id
+    ));
+}
+
+#[tokio::test]
+async fn memory() {
+    exercise(&MemoryStorage::new()).await;
+}
+
+#[tokio::test]
+async fn sqlite() {
+    exercise(&db::connect("sqlite::memory:").await.unwrap()).await;
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn postgres() {
+    let Ok(url) = std::env::var("BUDGE_TEST_POSTGRES") else {
+        eprintln!("BUDGE_TEST_POSTGRES isn't set, skipping");
+        return;
+    };
+    let pool = budge::postgres::connect(&url).await.unwrap();
+    sqlx::query("TRUNCATE budget, payments, payees RESTART IDENTITY CASCADE")
+        .execute(&pool)
+        .await
+        .unwrap();
+    exercise(&pool).await;
+}
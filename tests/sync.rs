@@ -0,0 +1,238 @@
+//! Syncs databases with each other and through a shared folder, checking that they end up
+//! the same whichever way the changes travel
+use budge::db::{self, DbPool};
+use budge::sync::{self, Action};
+use budge::{doctor, trash, Storage};
+
+type Synced = (
+    Vec<(String, f64, String, Option<String>)>,
+    Vec<(String, f64, String, String, Option<String>, Option<String>)>,
+);
+
+/// Budgets and payments as sync sees them, leaving out the ids that differ between databases
+async fn synced(pool: &DbPool) -> Synced {
+    let budgets =
+        sqlx::query_as("SELECT month, amount, uuid, deleted_at FROM budget ORDER BY month")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+    let payments = sqlx::query_as(
+        r#"SELECT payments.uuid, payments.amount, payments.kind, budget.month, payments.memo,
+                  payments.deleted_at
+           FROM payments JOIN budget ON budget.id = payments.budget_id
+           ORDER BY payments.uuid"#,
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap();
+    (budgets, payments)
+}
+
+async fn payment(pool: &DbPool, kind: &str) -> i64 {
+    sqlx::query_scalar("SELECT id FROM payments WHERE kind = ?")
+        .bind(kind)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+fn updates(report: &sync::Report) -> Vec<(&str, &str, Action)> {
+    report
+        .updates
+        .iter()
+        .map(|x| (x.record.as_str(), x.label.as_str(), x.action))
+        .collect()
+}
+
+#[tokio::test]
+async fn databases() {
+    let laptop = db::connect("sqlite::memory:").await.unwrap();
+    let desktop = db::connect("sqlite::memory:").await.unwrap();
+    let january = laptop.create_budget(500.0, "2025-01").await.unwrap();
+    laptop
+        .add_payment(january, "groceries", 42.5, Some("Costco"))
+        .await
+        .unwrap();
+    // Both made a budget for February, which becomes one with the newest amount
+    laptop.create_budget(300.0, "2025-02").await.unwrap();
+    // Changes are stamped to the millisecond, and a tie would go to the larger device id
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let february = desktop.create_budget(400.0, "2025-02").await.unwrap();
+    desktop
+        .add_payment(february, "rent", 900.0, None)
+        .await
+        .unwrap();
+
+    let (here, there) = sync::with_database(&laptop, &desktop).await.unwrap();
+    assert_eq!(synced(&laptop).await, synced(&desktop).await);
+    assert_eq!((here.received, there.received), (2, 3));
+    assert_eq!(updates(&there)[0], ("budget", "2025-01", Action::Added));
+    assert_eq!(updates(&there)[1].2, Action::Added);
+    assert_eq!(updates(&here)[0], ("budget", "2025-02", Action::Changed));
+    assert_eq!(laptop.budgets().await.unwrap()[1].amount, 400.0);
+    assert_eq!(here.conflicts.len(), 1);
+    assert!(!here.conflicts[0].kept_ours);
+    assert!(there.conflicts[0].kept_ours);
+
+    // Nothing is new the second time
+    let (here, there) = sync::with_database(&laptop, &desktop).await.unwrap();
+    assert_eq!((here.received, there.received), (0, 0));
+}
+
+#[tokio::test]
+async fn conflicts() {
+    let laptop = db::connect("sqlite::memory:").await.unwrap();
+    let desktop = db::connect("sqlite::memory:").await.unwrap();
+    let january = laptop.create_budget(500.0, "2025-01").await.unwrap();
+    laptop
+        .add_payment(january, "groceries", 42.5, None)
+        .await
+        .unwrap();
+    laptop
+        .add_payment(january, "rent", 900.0, None)
+        .await
+        .unwrap();
+    sync::with_database(&laptop, &desktop).await.unwrap();
+
+    // The edit made last wins, whichever side syncs
+    let groceries = payment(&laptop, "groceries").await;
+    laptop.set_memo(groceries, Some("laptop")).await.unwrap();
+    let groceries = payment(&desktop, "groceries").await;
+    desktop.set_memo(groceries, Some("desktop")).await.unwrap();
+    // And so does a purge made after an edit
+    let rent = payment(&desktop, "rent").await;
+    desktop.set_memo(rent, Some("late")).await.unwrap();
+    let rent = payment(&laptop, "rent").await;
+    laptop.delete_payment(rent).await.unwrap();
+    trash::purge(&laptop).await.unwrap();
+
+    let (here, there) = sync::with_database(&desktop, &laptop).await.unwrap();
+    assert_eq!(synced(&laptop).await, synced(&desktop).await);
+    let (_, payments) = synced(&laptop).await;
+    assert_eq!(payments.len(), 1);
+    assert_eq!(payments[0].4.as_deref(), Some("desktop"));
+    // Labels carry the day the payment was made
+    let removed: Vec<_> = updates(&here)
+        .into_iter()
+        .map(|(record, label, action)| (record, label.starts_with("rent on "), action))
+        .collect();
+    assert_eq!(removed, [("payment", true, Action::Removed)]);
+    let mut conflicts: Vec<_> = here
+        .conflicts
+        .iter()
+        .map(|x| (x.label.starts_with("rent"), x.kept_ours))
+        .collect();
+    conflicts.sort();
+    assert_eq!(conflicts, [(false, true), (true, false)]);
+    assert_eq!(there.conflicts.len(), 2);
+}
+
+#[tokio::test]
+async fn folder() {
+    let dir = std::env::temp_dir().join(format!("budge-sync-folder-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut devices = Vec::new();
+    for (month, kind) in [
+        ("2025-01", "rent"),
+        ("2025-01", "coffee"),
+        ("2025-02", "books"),
+    ] {
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        let id = pool.find_or_create_budget(month, 100.0).await.unwrap();
+        pool.add_payment(id, kind, 10.0, None).await.unwrap();
+        sync::with_folder(&pool, &dir).await.unwrap();
+        devices.push(pool);
+    }
+    // The first two only see the third's changes once they sync again
+    for pool in &devices {
+        sync::with_folder(pool, &dir).await.unwrap();
+    }
+    let expected = synced(&devices[2]).await;
+    assert_eq!(expected.0.len(), 2);
+    assert_eq!(expected.1.len(), 3);
+    for pool in &devices {
+        assert_eq!(synced(pool).await, expected);
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+    let device = sync::device(&devices[0]).await.unwrap();
+    assert!(sync::changes_file(&dir, &device).exists());
+
+    // A file that isn't a change log stops the sync before anything is merged
+    let report = sync::with_folder(&devices[0], &dir).await.unwrap();
+    assert_eq!(report.received, 0);
+    std::fs::write(dir.join("budge-broken.jsonl"), "{}\n").unwrap();
+    assert!(sync::with_folder(&devices[0], &dir).await.is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn moved_month() {
+    let laptop = db::connect("sqlite::memory:").await.unwrap();
+    let desktop = db::connect("sqlite::memory:").await.unwrap();
+    sqlx::query("INSERT INTO budget (amount, month) VALUES (100, '2025-1')")
+        .execute(&laptop)
+        .await
+        .unwrap();
+    let id = sqlx::query_scalar("SELECT id FROM budget")
+        .fetch_one(&laptop)
+        .await
+        .unwrap();
+    laptop.add_payment(id, "rent", 90.0, None).await.unwrap();
+    sync::with_database(&laptop, &desktop).await.unwrap();
+
+    // The doctor renames the month here, and the payment follows it there
+    doctor::check(&laptop, true).await.unwrap();
+    let (_, there) = sync::with_database(&laptop, &desktop).await.unwrap();
+    assert_eq!(synced(&laptop).await, synced(&desktop).await);
+    let (budgets, payments) = synced(&desktop).await;
+    assert_eq!(budgets[0].0, "2025-01");
+    assert_eq!(payments[0].3, "2025-01");
+    assert_eq!(there.updates[0].action, Action::Removed);
+}
+
+#[tokio::test]
+async fn copied_database() {
+    let dir = std::env::temp_dir().join(format!("budge-sync-copied-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let laptop = dir.join("laptop.db");
+    let laptop = db::connect(&laptop.to_string_lossy()).await.unwrap();
+    let january = laptop.create_budget(500.0, "2025-01").await.unwrap();
+    laptop.add_payment(january, "rent", 900.0, None).await.unwrap();
+    sync::with_folder(&laptop, &dir).await.unwrap();
+
+    // A copy of the database, which starts out with the same device id
+    let copy = dir.join("copy.db");
+    sqlx::query("VACUUM INTO ?")
+        .bind(copy.to_string_lossy())
+        .execute(&laptop)
+        .await
+        .unwrap();
+    let desktop = db::connect(&copy.to_string_lossy()).await.unwrap();
+    let device = sync::device(&laptop).await.unwrap();
+    assert_eq!(sync::device(&desktop).await.unwrap(), device);
+
+    laptop.add_payment(january, "coffee", 4.5, None).await.unwrap();
+    desktop.add_payment(january, "books", 30.0, None).await.unwrap();
+    let report = sync::with_folder(&laptop, &dir).await.unwrap();
+    assert!(report.new_device.is_none());
+    let report = sync::with_folder(&desktop, &dir).await.unwrap();
+    assert_eq!(report.received, 1);
+    assert_eq!(report.updates[0].action, Action::Added);
+    let new_device = report.new_device.unwrap();
+    assert_ne!(new_device, device);
+    assert_eq!(sync::device(&desktop).await.unwrap(), new_device);
+
+    // The copy's payment reaches the original, and neither takes over the other's file
+    let report = sync::with_folder(&laptop, &dir).await.unwrap();
+    assert!(report.new_device.is_none());
+    assert_eq!(report.received, 1);
+    assert_eq!(synced(&laptop).await, synced(&desktop).await);
+    assert_eq!(synced(&laptop).await.1.len(), 3);
+    assert!(sync::changes_file(&dir, &new_device).exists());
+    let report = sync::with_folder(&desktop, &dir).await.unwrap();
+    assert!(report.new_device.is_none());
+    assert_eq!(report.received, 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,24 @@
+//! Moves records to the trash and back, leaving the ones that aren't in it alone
+use budge::trash::{self, Record};
+use budge::{db, sync, Storage};
+
+#[tokio::test]
+async fn restore() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let budget = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let rent = pool.add_payment(budget, "rent", 90.0, None).await.unwrap();
+    let logged = sync::changes(&pool).await.unwrap().len();
+
+    // A live record isn't restored, and syncing doesn't hear of it
+    assert!(!trash::restore(&pool, Record::Payment, rent).await.unwrap());
+    assert!(!trash::restore(&pool, Record::Budget, budget).await.unwrap());
+    assert!(!trash::restore(&pool, Record::Payment, rent + 1).await.unwrap());
+    assert_eq!(sync::changes(&pool).await.unwrap().len(), logged);
+
+    pool.delete_payment(rent).await.unwrap();
+    assert_eq!(trash::list(&pool).await.unwrap().len(), 1);
+    assert!(trash::restore(&pool, Record::Payment, rent).await.unwrap());
+    assert!(!trash::restore(&pool, Record::Payment, rent).await.unwrap());
+    assert!(pool.payment(rent).await.unwrap().is_some());
+    assert!(trash::list(&pool).await.unwrap().is_empty());
+}
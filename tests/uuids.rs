@@ -0,0 +1,83 @@
+//! Every record gets a uuid that no other database has, and commands take it wherever they
+//! take an id
+use std::collections::BTreeSet;
+
+use budge::storage::Ref;
+use budge::{attachment, backup, db, journal, trash, Error, Storage};
+
+#[test]
+fn references() {
+    assert_eq!("42".parse::<Ref>().unwrap(), Ref::Id(42));
+    assert_eq!(" 7 ".parse::<Ref>().unwrap(), Ref::Id(7));
+    let expected = Ref::Uuid("6f9619ff-8b86-d011-b42d-00c04fc964ff".to_string());
+    for spelling in [
+        "6F9619FF-8B86-D011-B42D-00C04FC964FF",
+        "6f9619ff8b86d011b42d00c04fc964ff",
+        "{6f9619ff-8b86-d011-b42d-00c04fc964ff}",
+    ] {
+        assert_eq!(spelling.parse::<Ref>().unwrap(), expected);
+    }
+    assert_eq!(expected.to_string(), "6f9619ff-8b86-d011-b42d-00c04fc964ff");
+    assert!(matches!("6f9619ff".parse::<Ref>(), Err(Error::Invalid(_))));
+    assert!(matches!("".parse::<Ref>(), Err(Error::Invalid(_))));
+}
+
+#[tokio::test]
+async fn every_table() {
+    let dir = std::env::temp_dir().join(format!("budge-uuids-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let receipt = dir.join("receipt.txt");
+    std::fs::write(&receipt, "coffee").unwrap();
+
+    // Two databases with the same records, which share their ids but none of their uuids
+    let mut uuids = BTreeSet::new();
+    for _ in 0..2 {
+        let pool = db::connect("sqlite::memory:").await.unwrap();
+        let id = pool.create_budget(100.0, "2025-01").await.unwrap();
+        let coffee = pool
+            .add_payment(id, "coffee", 3.0, Some("Cafe"))
+            .await
+            .unwrap();
+        attachment::attach(&pool, &dir, coffee, &receipt)
+            .await
+            .unwrap();
+
+        let backup = backup::backup(&pool).await.unwrap();
+        let found: Vec<&Option<String>> = backup
+            .budgets
+            .iter()
+            .map(|x| &x.uuid)
+            .chain(backup.payees.iter().map(|x| &x.uuid))
+            .chain(backup.payments.iter().map(|x| &x.uuid))
+            .chain(backup.attachments.iter().map(|x| &x.uuid))
+            .collect();
+        assert_eq!(found.len(), 4);
+        for uuid in found {
+            assert!(uuids.insert(uuid.clone().unwrap()));
+        }
+        let payment = pool.payment(coffee).await.unwrap().unwrap();
+        assert_eq!(backup.payments[0].uuid.as_ref(), Some(&payment.uuid));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn undo_after_purge() {
+    let pool = db::connect("sqlite::memory:").await.unwrap();
+    let budget = pool.create_budget(100.0, "2025-01").await.unwrap();
+    let rent = pool.add_payment(budget, "rent", 90.0, None).await.unwrap();
+    let uuid = pool.payment(rent).await.unwrap().unwrap().uuid;
+
+    // Undoing the delete brings the purged payment back as the same record
+    pool.delete_payment(rent).await.unwrap();
+    trash::purge(&pool).await.unwrap();
+    journal::undo(&pool).await.unwrap().unwrap();
+    assert_eq!(pool.payment(rent).await.unwrap().unwrap().uuid, uuid);
+
+    // So does redoing an add that undo removed for good
+    journal::undo(&pool).await.unwrap().unwrap();
+    assert!(pool.payment(rent).await.unwrap().is_none());
+    journal::redo(&pool).await.unwrap().unwrap();
+    assert_eq!(pool.payment(rent).await.unwrap().unwrap().uuid, uuid);
+}
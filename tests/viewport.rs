@@ -0,0 +1,88 @@
+//! Keeps the selected row of the payment list in range and on screen
+use budge::viewport::Viewport;
+
+fn viewport(len: usize, height: usize) -> Viewport {
+    let mut viewport = Viewport::default();
+    viewport.set_len(len);
+    viewport.set_height(height);
+    viewport
+}
+
+fn at(viewport: &Viewport) -> (usize, usize) {
+    (viewport.selected(), viewport.offset())
+}
+
+#[test]
+fn empty() {
+    let mut list = viewport(0, 5);
+    list.select(3);
+    assert_eq!(at(&list), (0, 0));
+    list.down(1);
+    list.page_down();
+    list.bottom();
+    assert_eq!(at(&list), (0, 0));
+    list.up(1);
+    list.page_up();
+    list.top();
+    assert_eq!(at(&list), (0, 0));
+}
+
+#[test]
+fn no_height() {
+    let mut list = viewport(10, 0);
+    list.select(7);
+    assert_eq!(at(&list), (7, 0));
+    // A page is still a row, so paging moves
+    list.page_down();
+    assert_eq!(at(&list), (8, 0));
+    list.page_up();
+    assert_eq!(at(&list), (7, 0));
+    list.bottom();
+    assert_eq!(at(&list), (9, 0));
+
+    // Once rows fit, the selection comes into view
+    list.set_height(3);
+    assert_eq!(at(&list), (9, 7));
+    list.set_height(0);
+    assert_eq!(at(&list), (9, 7));
+}
+
+#[test]
+fn past_the_end() {
+    let mut list = viewport(10, 4);
+    list.select(20);
+    assert_eq!(at(&list), (9, 6));
+    list.down(usize::MAX);
+    assert_eq!(at(&list), (9, 6));
+
+    // The list shrinking underneath the selection
+    list.set_len(3);
+    assert_eq!(at(&list), (2, 0));
+    list.set_len(0);
+    assert_eq!(at(&list), (0, 0));
+    list.set_len(10);
+    assert_eq!(at(&list), (0, 0));
+}
+
+#[test]
+fn scrolling() {
+    let mut list = viewport(10, 4);
+    list.down(5);
+    assert_eq!(at(&list), (5, 2));
+    list.up(1);
+    assert_eq!(at(&list), (4, 2));
+    list.up(3);
+    assert_eq!(at(&list), (1, 1));
+    list.page_down();
+    assert_eq!(at(&list), (5, 2));
+    list.up(10);
+    assert_eq!(at(&list), (0, 0));
+
+    // A taller terminal shows more of the end, a shorter one keeps the selection visible
+    list.bottom();
+    assert_eq!(at(&list), (9, 6));
+    list.set_height(8);
+    assert_eq!(at(&list), (9, 2));
+    list.set_height(2);
+    assert_eq!(at(&list), (9, 8));
+}